@@ -0,0 +1,452 @@
+use crate::tasks::{NoFindingsPolicy, QuestionValidation, SummaryStrategy};
+use crate::tools::llm::{parse_task_map, Model};
+use crate::tools::moderation::parse_blocklist;
+use crate::tools::pools::parse_pool_sizes;
+use crate::tools::tier::ProviderTier;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+/// Every tunable setting, read and validated once at startup so a typo fails
+/// startup instead of silently falling back to a default. Numeric settings
+/// are `None` when unset and the code using them applies its own default;
+/// flags and named options are resolved here.
+///
+/// Secrets, endpoints and file paths (API keys, `HTTP_PROXY_URL`,
+/// `LLM_CACHE_DIR` and so on) are still read where they are used.
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    // Server
+    pub log_format: LogFormat,
+    pub warmup_topic: Option<String>,
+    pub workflow_deadline_secs: Option<u64>,
+    pub request_retry_budget: Option<u32>,
+    pub inter_task_delay_ms: Option<u64>,
+    pub research_queue_workers: Option<usize>,
+    pub research_queue_capacity: usize,
+    pub queue_high_water: Option<usize>,
+    pub queue_low_water: Option<usize>,
+    pub session_ttl_secs: Option<u64>,
+    pub recent_errors_capacity: usize,
+    pub idempotency_ttl_secs: Option<u64>,
+    pub stream_buffer_capacity: Option<usize>,
+    pub trace_sample_rate: Option<f64>,
+    pub enable_moderation: bool,
+    pub moderation_blocklist: Vec<String>,
+    pub cost_breakdown: bool,
+    pub detailed_timings: bool,
+
+    // Workflow
+    pub require_approval: bool,
+    pub fact_check: bool,
+    pub detect_contradictions: bool,
+    pub question_extraction_retries: Option<u32>,
+    pub question_validation: QuestionValidation,
+    pub question_near_dedup: bool,
+    pub question_timeout_secs: Option<u64>,
+    pub max_researched_questions: Option<usize>,
+    pub researcher_tools: Vec<String>,
+    pub no_findings_policy: NoFindingsPolicy,
+    pub max_findings_per_domain: Option<usize>,
+    pub min_total_findings: Option<usize>,
+    pub min_findings_retries: Option<u32>,
+    pub ordered_results: bool,
+    pub semantic_dedup: bool,
+    pub semantic_dedup_threshold: Option<f64>,
+    pub score_findings: bool,
+    pub summary_strategy: SummaryStrategy,
+    pub summary_max_findings_per_q: Option<usize>,
+    pub max_research_iterations: Option<u32>,
+    pub require_grounding: bool,
+    pub relevance_gate: bool,
+    pub relevance_min_score: Option<f64>,
+    pub pipeline_report_prep: bool,
+    pub self_score: bool,
+    pub report_max_prompt_tokens: Option<usize>,
+    pub min_report_chars: Option<usize>,
+
+    // LLM and search clients
+    pub llm_model: Option<Model>,
+    pub task_models: HashMap<String, Model>,
+    pub task_max_tokens: HashMap<String, u64>,
+    pub structured_output: bool,
+    pub embedding_model: Option<String>,
+    pub capture_llm_calls: bool,
+    pub llm_prompt_price_per_mtok: Option<f64>,
+    pub llm_completion_price_per_mtok: Option<f64>,
+    pub llm_breaker_threshold: Option<u32>,
+    pub llm_breaker_cooldown_secs: Option<u64>,
+    pub retry_max_attempts: Option<u32>,
+    pub retry_base_delay_ms: Option<u64>,
+    pub retry_max_delay_ms: Option<u64>,
+    pub retry_jitter: bool,
+    pub provider_tier: Option<ProviderTier>,
+    pub llm_max_concurrency: Option<usize>,
+    pub llm_qps: Option<f64>,
+    pub search_max_concurrency: Option<usize>,
+    pub tavily_qps: Option<f64>,
+    pub task_pool_sizes: HashMap<String, usize>,
+    pub clean_search_queries: bool,
+    pub search_query_max_chars: Option<usize>,
+    pub max_finding_content_chars: Option<usize>,
+    pub tavily_min_score: Option<f64>,
+    pub tavily_max_score: Option<f64>,
+    pub unicode_normalize: bool,
+}
+
+/// How logs are written, from `LOG_FORMAT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// Structured JSON with queryable fields such as `task` and `elapsed_ms`.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format {:?}, expected text or json", other)),
+        }
+    }
+}
+
+/// Ring buffer size for `GET /errors`, unless `RECENT_ERRORS_CAPACITY` is set.
+const DEFAULT_RECENT_ERRORS_CAPACITY: usize = 100;
+const DEFAULT_QUEUE_CAPACITY: usize = 100;
+/// Slowest pacing `LLM_QPS` and `TAVILY_QPS` accept, one call every 1000
+/// seconds. Anything lower is a typo, and values near zero would overflow the
+/// rate limiter's interval.
+const MIN_QPS: f64 = 0.001;
+
+static SETTINGS: OnceLock<Arc<Config>> = OnceLock::new();
+
+/// Makes `config` the process-wide settings returned by `settings`. Called
+/// once from `main` with the same `Config` the `AppState` holds.
+pub fn install(config: Arc<Config>) {
+    if SETTINGS.set(config).is_err() {
+        tracing::warn!("Settings were read before startup installed them; keeping the defaults");
+    }
+}
+
+/// The settings installed at startup, for code that runs inside tasks and
+/// tools and has no `AppState` at hand. Falls back to the defaults (every
+/// variable unset) if nothing was installed, as in unit tests.
+pub fn settings() -> &'static Config {
+    SETTINGS.get_or_init(|| Arc::new(Config::default()))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_lookup(|_| None).expect("defaults are valid")
+    }
+}
+
+/// Parses `1`, `true`, `yes` and `on` as enabled and `0`, `false`, `no`,
+/// `off` or an empty value as disabled, case-insensitively.
+pub fn parse_flag(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "" | "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("{:?} is not a boolean; use true or false", value)),
+    }
+}
+
+/// Reads settings through a lookup function, collecting every problem
+/// instead of stopping at the first one.
+struct Reader<L> {
+    lookup: L,
+    errors: Vec<String>,
+}
+
+impl<L: Fn(&str) -> Option<String>> Reader<L> {
+    fn parse<T>(&mut self, name: &str, parse: impl FnOnce(&str) -> Result<T, String>) -> Option<T> {
+        let value = (self.lookup)(name)?;
+        match parse(&value) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.errors.push(format!("{}: {}", name, e));
+                None
+            }
+        }
+    }
+
+    fn integer<T: FromStr>(&mut self, name: &str) -> Option<T> {
+        let value = (self.lookup)(name)?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.errors.push(format!("{}={:?} is not a non-negative integer", name, value));
+                None
+            }
+        }
+    }
+
+    fn number(&mut self, name: &str) -> Option<f64> {
+        let value = (self.lookup)(name)?;
+        match value.trim().parse::<f64>() {
+            Ok(number) if number.is_finite() => Some(number),
+            _ => {
+                self.errors.push(format!("{}={:?} is not a number", name, value));
+                None
+            }
+        }
+    }
+
+    /// A number that must lie within `range`, such as a rate or a price.
+    fn number_in(&mut self, name: &str, range: std::ops::RangeInclusive<f64>) -> Option<f64> {
+        let number = self.number(name)?;
+        if range.contains(&number) {
+            Some(number)
+        } else {
+            self.errors.push(format!(
+                "{}={} must be between {} and {}",
+                name,
+                number,
+                range.start(),
+                range.end()
+            ));
+            None
+        }
+    }
+
+    /// Free text such as a topic or model name; blank counts as unset.
+    fn text(&mut self, name: &str) -> Option<String> {
+        (self.lookup)(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn flag(&mut self, name: &str, default: bool) -> bool {
+        self.parse(name, parse_flag).unwrap_or(default)
+    }
+
+    fn value<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.parse(name, |value| value.parse::<T>().map_err(|e| e.to_string()))
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, Vec<String>> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Parses every setting through `lookup` and collects all problems
+    /// instead of stopping at the first one.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, Vec<String>> {
+        let mut r = Reader { lookup, errors: Vec::new() };
+
+        let request_retry_budget = r.integer::<u64>("REQUEST_RETRY_BUDGET").and_then(|budget| {
+            u32::try_from(budget)
+                .map_err(|_| r.errors.push(format!("REQUEST_RETRY_BUDGET={} is larger than {}", budget, u32::MAX)))
+                .ok()
+        });
+        let config = Config {
+            log_format: r.value("LOG_FORMAT").unwrap_or_default(),
+            warmup_topic: r.text("WARMUP_TOPIC"),
+            workflow_deadline_secs: r.integer("WORKFLOW_DEADLINE_SECS"),
+            request_retry_budget,
+            inter_task_delay_ms: r.integer("INTER_TASK_DELAY_MS"),
+            research_queue_workers: r.integer("RESEARCH_QUEUE_WORKERS"),
+            research_queue_capacity: r.integer("RESEARCH_QUEUE_CAPACITY").unwrap_or(DEFAULT_QUEUE_CAPACITY),
+            queue_high_water: r.integer("QUEUE_HIGH_WATER"),
+            queue_low_water: r.integer("QUEUE_LOW_WATER"),
+            session_ttl_secs: r.integer("SESSION_TTL_SECS"),
+            recent_errors_capacity: r.integer("RECENT_ERRORS_CAPACITY").unwrap_or(DEFAULT_RECENT_ERRORS_CAPACITY),
+            idempotency_ttl_secs: r.integer("IDEMPOTENCY_TTL_SECS"),
+            stream_buffer_capacity: r.integer("STREAM_BUFFER_CAPACITY"),
+            trace_sample_rate: r.number_in("TRACE_SAMPLE_RATE", 0.0..=1.0),
+            enable_moderation: r.flag("ENABLE_MODERATION", false),
+            moderation_blocklist: r
+                .parse("MODERATION_BLOCKLIST", |value| Ok(parse_blocklist(value)))
+                .unwrap_or_default(),
+            cost_breakdown: r.flag("COST_BREAKDOWN", false),
+            detailed_timings: r.flag("DETAILED_TIMINGS", false),
+
+            require_approval: r.flag("REQUIRE_APPROVAL", false),
+            fact_check: r.flag("FACT_CHECK", false),
+            detect_contradictions: r.flag("DETECT_CONTRADICTIONS", false),
+            question_extraction_retries: r.integer("QUESTION_EXTRACTION_RETRIES"),
+            question_validation: r.value("QUESTION_VALIDATION").unwrap_or_default(),
+            question_near_dedup: r.flag("QUESTION_NEAR_DEDUP", false),
+            question_timeout_secs: r.integer("QUESTION_TIMEOUT_SECS"),
+            max_researched_questions: r.integer("MAX_RESEARCHED_QUESTIONS"),
+            researcher_tools: r.parse("RESEARCHER_TOOLS", crate::tasks::parse_researcher_tools).unwrap_or_default(),
+            no_findings_policy: r.value("NO_FINDINGS_POLICY").unwrap_or_default(),
+            max_findings_per_domain: r.integer("MAX_FINDINGS_PER_DOMAIN"),
+            min_total_findings: r.integer("MIN_TOTAL_FINDINGS"),
+            min_findings_retries: r.integer("MIN_FINDINGS_RETRIES"),
+            ordered_results: r.flag("ORDERED_RESULTS", false),
+            semantic_dedup: r.flag("SEMANTIC_DEDUP", false),
+            semantic_dedup_threshold: r.number_in("SEMANTIC_DEDUP_THRESHOLD", 0.0..=1.0),
+            score_findings: r.flag("SCORE_FINDINGS", false),
+            summary_strategy: r.value("SUMMARY_STRATEGY").unwrap_or_default(),
+            summary_max_findings_per_q: r.integer("SUMMARY_MAX_FINDINGS_PER_Q"),
+            max_research_iterations: r.integer("MAX_RESEARCH_ITERATIONS"),
+            require_grounding: r.flag("REQUIRE_GROUNDING", false),
+            relevance_gate: r.flag("RELEVANCE_GATE", false),
+            relevance_min_score: r.number_in("RELEVANCE_MIN_SCORE", 0.0..=1.0),
+            pipeline_report_prep: r.flag("PIPELINE_REPORT_PREP", true),
+            self_score: r.flag("SELF_SCORE", false),
+            report_max_prompt_tokens: r.integer("REPORT_MAX_PROMPT_TOKENS"),
+            min_report_chars: r.integer("MIN_REPORT_CHARS"),
+
+            llm_model: r.value("LLM_MODEL"),
+            task_models: r.parse("TASK_MODELS", parse_task_map).unwrap_or_default(),
+            task_max_tokens: r.parse("TASK_MAX_TOKENS", parse_task_map).unwrap_or_default(),
+            structured_output: r.flag("STRUCTURED_OUTPUT", false),
+            embedding_model: r.text("EMBEDDING_MODEL"),
+            capture_llm_calls: r.flag("CAPTURE_LLM_CALLS", false),
+            llm_prompt_price_per_mtok: r.number_in("LLM_PROMPT_PRICE_PER_MTOK", 0.0..=f64::MAX),
+            llm_completion_price_per_mtok: r.number_in("LLM_COMPLETION_PRICE_PER_MTOK", 0.0..=f64::MAX),
+            llm_breaker_threshold: r.integer("LLM_BREAKER_THRESHOLD"),
+            llm_breaker_cooldown_secs: r.integer("LLM_BREAKER_COOLDOWN_SECS"),
+            retry_max_attempts: r.integer("RETRY_MAX_ATTEMPTS"),
+            retry_base_delay_ms: r.integer("RETRY_BASE_DELAY_MS"),
+            retry_max_delay_ms: r.integer("RETRY_MAX_DELAY_MS"),
+            retry_jitter: r.flag("RETRY_JITTER", true),
+            provider_tier: r.value("PROVIDER_TIER"),
+            llm_max_concurrency: r.integer("LLM_MAX_CONCURRENCY"),
+            llm_qps: r.number_in("LLM_QPS", MIN_QPS..=f64::MAX),
+            search_max_concurrency: r.integer("SEARCH_MAX_CONCURRENCY"),
+            tavily_qps: r.number_in("TAVILY_QPS", MIN_QPS..=f64::MAX),
+            task_pool_sizes: r.parse("TASK_POOL_SIZES", parse_pool_sizes).unwrap_or_default(),
+            clean_search_queries: r.flag("CLEAN_SEARCH_QUERIES", false),
+            search_query_max_chars: r.integer("SEARCH_QUERY_MAX_CHARS"),
+            max_finding_content_chars: r.integer("MAX_FINDING_CONTENT_CHARS"),
+            tavily_min_score: r.number("TAVILY_MIN_SCORE"),
+            tavily_max_score: r.number("TAVILY_MAX_SCORE"),
+            unicode_normalize: r.flag("UNICODE_NORMALIZE", true),
+        };
+
+        if let (Some(high), Some(low)) = (config.queue_high_water, config.queue_low_water) {
+            if low > high {
+                r.errors.push(format!("QUEUE_LOW_WATER={} is above QUEUE_HIGH_WATER={}", low, high));
+            }
+        }
+        if let (Some(min), Some(max)) = (config.tavily_min_score, config.tavily_max_score) {
+            if min > max {
+                r.errors.push(format!("TAVILY_MIN_SCORE={} is above TAVILY_MAX_SCORE={}", min, max));
+            }
+        }
+
+        if r.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(r.errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_pairs(pairs: &[(&str, &str)]) -> Result<Config, Vec<String>> {
+        let env: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::from_lookup(|name| env.get(name).cloned())
+    }
+
+    #[test]
+    fn unset_variables_give_the_defaults() {
+        let config = from_pairs(&[]).unwrap();
+        assert_eq!(config.research_queue_capacity, DEFAULT_QUEUE_CAPACITY);
+        assert_eq!(config.request_retry_budget, None);
+        assert!(config.retry_jitter);
+        assert!(config.unicode_normalize);
+        assert!(!config.require_approval);
+        assert_eq!(config.summary_strategy, SummaryStrategy::SinglePass);
+        assert_eq!(config.no_findings_policy, NoFindingsPolicy::Skip);
+        assert!(config.task_pool_sizes.is_empty());
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.warmup_topic, None);
+        assert!(config.moderation_blocklist.is_empty());
+    }
+
+    #[test]
+    fn valid_values_are_parsed() {
+        let config = from_pairs(&[
+            ("REQUEST_RETRY_BUDGET", "7"),
+            ("REQUIRE_APPROVAL", "yes"),
+            ("RETRY_JITTER", "off"),
+            ("SUMMARY_STRATEGY", "map_reduce"),
+            ("NO_FINDINGS_POLICY", "Retry"),
+            ("PROVIDER_TIER", "tier-2"),
+            ("TASK_POOL_SIZES", "researcher=8, reporter=4"),
+            ("LLM_PROMPT_PRICE_PER_MTOK", "0.15"),
+            ("LOG_FORMAT", "JSON"),
+            ("WARMUP_TOPIC", " Rust async runtimes "),
+            ("MODERATION_BLOCKLIST", "Malware, exploit kit"),
+            ("EMBEDDING_MODEL", "text-embedding-3-large"),
+        ])
+        .unwrap();
+        assert_eq!(config.request_retry_budget, Some(7));
+        assert!(config.require_approval);
+        assert!(!config.retry_jitter);
+        assert_eq!(config.summary_strategy, SummaryStrategy::MapReduce);
+        assert_eq!(config.no_findings_policy, NoFindingsPolicy::Retry);
+        assert_eq!(config.provider_tier, Some(ProviderTier::Tier2));
+        assert_eq!(config.task_pool_sizes.get("researcher"), Some(&8));
+        assert_eq!(config.task_pool_sizes.get("reporter"), Some(&4));
+        assert_eq!(config.llm_prompt_price_per_mtok, Some(0.15));
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.warmup_topic.as_deref(), Some("Rust async runtimes"));
+        assert_eq!(config.moderation_blocklist, ["malware", "exploit kit"]);
+        assert_eq!(config.embedding_model.as_deref(), Some("text-embedding-3-large"));
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let errors = from_pairs(&[
+            ("REQUEST_RETRY_BUDGET", "4294967296"),
+            ("REQUIRE_APPROVAL", "maybe"),
+            ("SUMMARY_STRATEGY", "three_pass"),
+            ("NO_FINDINGS_POLICY", "ignore"),
+            ("PROVIDER_TIER", "platinum"),
+            ("TASK_POOL_SIZES", "researcher=0"),
+            ("LLM_PROMPT_PRICE_PER_MTOK", "-1"),
+            ("LLM_COMPLETION_PRICE_PER_MTOK", "cheap"),
+            ("TRACE_SAMPLE_RATE", "2"),
+            ("MAX_RESEARCH_ITERATIONS", "-3"),
+            ("LLM_QPS", "0"),
+            ("TAVILY_QPS", "1e-320"),
+            ("LOG_FORMAT", "xml"),
+        ])
+        .unwrap_err();
+        for name in [
+            "REQUEST_RETRY_BUDGET",
+            "REQUIRE_APPROVAL",
+            "SUMMARY_STRATEGY",
+            "NO_FINDINGS_POLICY",
+            "PROVIDER_TIER",
+            "TASK_POOL_SIZES",
+            "LLM_PROMPT_PRICE_PER_MTOK",
+            "LLM_COMPLETION_PRICE_PER_MTOK",
+            "TRACE_SAMPLE_RATE",
+            "MAX_RESEARCH_ITERATIONS",
+            "LLM_QPS",
+            "TAVILY_QPS",
+            "LOG_FORMAT",
+        ] {
+            assert!(errors.iter().any(|e| e.starts_with(name)), "no error for {}: {:?}", name, errors);
+        }
+    }
+
+    #[test]
+    fn queue_watermarks_must_be_ordered() {
+        let errors = from_pairs(&[("QUEUE_HIGH_WATER", "5"), ("QUEUE_LOW_WATER", "10")]).unwrap_err();
+        assert_eq!(errors, vec!["QUEUE_LOW_WATER=10 is above QUEUE_HIGH_WATER=5".to_string()]);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::models::ResearchContext;
+use graph_flow::Context;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The `ResearchContext` shared by every task.
+pub const RESEARCH_CONTEXT: &str = "research_context";
+/// Milliseconds per task, written by `record_task_time`.
+pub const TASK_TIMES: &str = "task_times";
+/// Time-to-first-token per task, written by `record_ttft`.
+pub const TTFT_MS: &str = "ttft_ms";
+/// Per-task sub-call durations, written under `DETAILED_TIMINGS`.
+pub const SUBCALL_TIMES: &str = "subcall_times";
+/// Captured `LlmCall`s, appended after each task under `CAPTURE_LLM_CALLS`.
+pub const LLM_CALLS: &str = "llm_calls";
+/// Optional prefix applied to every other key. A sub-workflow that shares a
+/// session with another one sets a distinct namespace here so their state
+/// does not collide; sessions without one use the bare keys.
+pub const NAMESPACE: &str = "namespace";
+
+/// Resolves `key` under the context's namespace, if it has one.
+pub async fn scoped(context: &Context, key: &str) -> String {
+    match context.get::<String>(NAMESPACE).await {
+        Some(namespace) if !namespace.is_empty() => format!("{}.{}", namespace, key),
+        _ => key.to_string(),
+    }
+}
+
+pub async fn get<T: DeserializeOwned>(context: &Context, key: &str) -> Option<T> {
+    context.get(&scoped(context, key).await).await
+}
+
+pub async fn set<T: Serialize>(context: &Context, key: &str, value: T) {
+    context.set(&scoped(context, key).await, value).await;
+}
+
+pub async fn get_research_context(context: &Context) -> Option<ResearchContext> {
+    get(context, RESEARCH_CONTEXT).await
+}
+
+pub async fn set_research_context(context: &Context, research_context: ResearchContext) {
+    set(context, RESEARCH_CONTEXT, research_context).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(topic: &str) -> ResearchContext {
+        ResearchContext {
+            topic: topic.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn namespaces_keep_their_contexts_apart() {
+        let context = Context::new();
+        set_research_context(&context, topic("outer")).await;
+
+        context.set(NAMESPACE, "first".to_string()).await;
+        set_research_context(&context, topic("first workflow")).await;
+        context.set(NAMESPACE, "second".to_string()).await;
+        assert!(get_research_context(&context).await.is_none());
+        set_research_context(&context, topic("second workflow")).await;
+
+        context.set(NAMESPACE, "first".to_string()).await;
+        assert_eq!(get_research_context(&context).await.unwrap().topic, "first workflow");
+        assert_eq!(scoped(&context, TASK_TIMES).await, "first.task_times");
+        context.set(NAMESPACE, "second".to_string()).await;
+        assert_eq!(get_research_context(&context).await.unwrap().topic, "second workflow");
+
+        context.set(NAMESPACE, String::new()).await;
+        assert_eq!(get_research_context(&context).await.unwrap().topic, "outer");
+    }
+}
@@ -0,0 +1,219 @@
+use crate::models::ResearchResponse;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use serde_json::json;
+use std::any::Any;
+use std::fmt;
+use uuid::Uuid;
+
+/// Coarse failure categories, reported in error responses and counted on `/stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    LlmTimeout,
+    LlmRateLimit,
+    LlmUnavailable,
+    SearchFailure,
+    InsufficientFindings,
+    ParseFailure,
+    ContextError,
+    Other,
+}
+
+impl ErrorKind {
+    /// Classifies a workflow error from its message. The errors reaching the
+    /// handler are `GraphError`s wrapping `anyhow` chains from rig, reqwest and
+    /// our own tasks, so the message is the only common ground.
+    pub fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        if message.contains("circuit breaker") {
+            ErrorKind::LlmUnavailable
+        } else if message.contains("insufficient findings") {
+            ErrorKind::InsufficientFindings
+        } else if message.contains("tavily") {
+            ErrorKind::SearchFailure
+        } else if message.contains("429") || message.contains("rate limit") || message.contains("too many requests") {
+            ErrorKind::LlmRateLimit
+        } else if message.contains("timed out") || message.contains("timeout") || message.contains("deadline") {
+            ErrorKind::LlmTimeout
+        } else if message.contains("parse") || message.contains("json") || message.contains("deserializ") {
+            ErrorKind::ParseFailure
+        } else if message.contains("context") {
+            ErrorKind::ContextError
+        } else {
+            ErrorKind::Other
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::LlmTimeout => "llm_timeout",
+            ErrorKind::LlmRateLimit => "llm_rate_limit",
+            ErrorKind::LlmUnavailable => "llm_unavailable",
+            ErrorKind::SearchFailure => "search_failure",
+            ErrorKind::InsufficientFindings => "insufficient_findings",
+            ErrorKind::ParseFailure => "parse_failure",
+            ErrorKind::ContextError => "context_error",
+            ErrorKind::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug)]
+pub enum ResearchError {
+    Status(StatusCode),
+    /// The overall workflow deadline elapsed while `task` was running.
+    DeadlineExceeded {
+        task: String,
+        partial: Box<ResearchResponse>,
+    },
+    /// The topic was rejected by the moderation gate.
+    Moderated { reason: String },
+    /// A task failed; `kind` says roughly why.
+    Workflow { kind: ErrorKind, status: StatusCode },
+    /// The run was stopped from outside before it finished.
+    Cancelled { reason: String },
+}
+
+impl ResearchError {
+    /// Short label for the failure, as listed on `/errors`.
+    pub fn category(&self) -> String {
+        match self {
+            ResearchError::Status(status) => format!("http_{}", status.as_u16()),
+            ResearchError::DeadlineExceeded { .. } => "deadline_exceeded".to_string(),
+            ResearchError::Moderated { .. } => "moderated".to_string(),
+            ResearchError::Workflow { kind, .. } => kind.to_string(),
+            ResearchError::Cancelled { .. } => "cancelled".to_string(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ResearchError::Status(status) => *status,
+            ResearchError::DeadlineExceeded { .. } => StatusCode::GATEWAY_TIMEOUT,
+            ResearchError::Moderated { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ResearchError::Workflow { status, .. } => *status,
+            ResearchError::Cancelled { .. } => StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl From<StatusCode> for ResearchError {
+    fn from(status: StatusCode) -> Self {
+        ResearchError::Status(status)
+    }
+}
+
+impl fmt::Display for ResearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResearchError::Status(status) => write!(f, "{}", status),
+            ResearchError::DeadlineExceeded { task, .. } => {
+                write!(f, "workflow deadline exceeded while running {}", task)
+            }
+            ResearchError::Moderated { reason } => write!(f, "topic rejected by moderation: {}", reason),
+            ResearchError::Workflow { kind, .. } => write!(f, "workflow failed: {}", kind),
+            ResearchError::Cancelled { reason } => write!(f, "cancelled: {}", reason),
+        }
+    }
+}
+
+impl IntoResponse for ResearchError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        match self {
+            ResearchError::Status(status) => status.into_response(),
+            ResearchError::DeadlineExceeded { ref task, ref partial } => (
+                status,
+                Json(json!({
+                    "error": self.to_string(),
+                    "timed_out_task": task,
+                    "partial": partial,
+                })),
+            )
+                .into_response(),
+            ResearchError::Moderated { ref reason } => (
+                status,
+                Json(json!({
+                    "error": "topic rejected by moderation",
+                    "reason": reason,
+                })),
+            )
+                .into_response(),
+            ResearchError::Workflow { kind, .. } => (
+                status,
+                Json(json!({
+                    "error": self.to_string(),
+                    "kind": kind,
+                })),
+            )
+                .into_response(),
+            ResearchError::Cancelled { .. } => (status, Json(json!({ "error": self.to_string() }))).into_response(),
+        }
+    }
+}
+
+/// Turns a handler panic caught by `CatchPanicLayer` into a 500 JSON body
+/// instead of a dropped connection. The `request_id` is logged with the panic
+/// message so the two can be matched up.
+pub fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    let request_id = Uuid::new_v4().to_string();
+    tracing::error!(request_id = %request_id, "Request handler panicked: {}", message);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({
+            "error": "internal server error",
+            "kind": "panic",
+            "request_id": request_id,
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn representative_errors_map_to_their_kind() {
+        let cases = [
+            ("Task execution failed: CompletionError: HttpError: operation timed out", ErrorKind::LlmTimeout),
+            ("ProviderError: 429 Too Many Requests", ErrorKind::LlmRateLimit),
+            ("LLM circuit breaker is open", ErrorKind::LlmUnavailable),
+            ("Tavily request failed with 502 Bad Gateway", ErrorKind::SearchFailure),
+            ("insufficient findings: 1 of 3 required", ErrorKind::InsufficientFindings),
+            ("Failed to parse questions: expected value at line 1", ErrorKind::ParseFailure),
+            ("No research context found", ErrorKind::ContextError),
+            ("something else entirely", ErrorKind::Other),
+        ];
+        for (message, kind) in cases {
+            assert_eq!(ErrorKind::classify(message), kind, "{}", message);
+        }
+    }
+
+    #[tokio::test]
+    async fn workflow_errors_report_their_kind() {
+        let error = ResearchError::Workflow {
+            kind: ErrorKind::SearchFailure,
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        assert_eq!(error.category(), "search_failure");
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, json!({"error": "workflow failed: search_failure", "kind": "search_failure"}));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::context_keys::{self, get_research_context, TASK_TIMES};
+use crate::graph::TASKS;
+use crate::storage::{ExtendedSessionStorage, SessionSummary};
+use futures::stream::{self, Stream, StreamExt};
+use graph_flow::SessionStorage;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// One column per graph task, in graph order, so every row lines up even when
+/// a session skipped or has not reached some tasks.
+pub fn csv_header() -> String {
+    let task_columns: Vec<String> = TASKS.iter().map(|task| format!("{}_ms", task)).collect();
+    format!(
+        "session_id,topic,status,created_at,question_count,{},total_time_ms\n",
+        task_columns.join(",")
+    )
+}
+
+/// Streams every stored session as CSV, newest first, one row per chunk.
+/// The ids are snapshotted once up front, so sessions created during the
+/// export are left out rather than shifting rows between pages, and only one
+/// session is loaded at a time. Sessions removed meanwhile are skipped.
+pub fn csv_stream(
+    storage: Arc<dyn ExtendedSessionStorage>,
+) -> impl Stream<Item = Result<String, std::io::Error>> + Send {
+    let rows = stream::once({
+        let storage = storage.clone();
+        async move { storage.session_ids().await }
+    })
+    .flat_map(move |ids| {
+        let storage = storage.clone();
+        match ids {
+            Ok(ids) => stream::iter(ids)
+                .filter_map(move |id| {
+                    let storage = storage.clone();
+                    async move {
+                        match storage.session_summary(&id).await {
+                            Ok(Some(summary)) => Some(Ok(csv_row(storage.as_ref(), &summary).await)),
+                            Ok(None) => None,
+                            Err(e) => Some(Err(std::io::Error::other(e.to_string()))),
+                        }
+                    }
+                })
+                .left_stream(),
+            Err(e) => stream::once(async move { Err(std::io::Error::other(e.to_string())) }).right_stream(),
+        }
+    });
+    stream::once(async { Ok(csv_header()) }).chain(rows)
+}
+
+/// `total_time_ms` is the sum of the recorded task times, since wall-clock
+/// time is not stored with the session.
+async fn csv_row(storage: &dyn ExtendedSessionStorage, summary: &SessionSummary) -> String {
+    let (question_count, task_times) = match storage.get(&summary.session_id).await {
+        Ok(Some(session)) => {
+            let question_count = get_research_context(&session.context)
+                .await
+                .map_or(0, |context| context.questions.len());
+            let task_times: HashMap<String, u64> = context_keys::get(&session.context, TASK_TIMES)
+                .await
+                .unwrap_or_default();
+            (question_count, task_times)
+        }
+        _ => (0, HashMap::new()),
+    };
+
+    let task_columns: Vec<String> = TASKS
+        .iter()
+        .map(|task| task_times.get(*task).map(u64::to_string).unwrap_or_default())
+        .collect();
+    format!(
+        "{},{},{},{},{},{},{}\n",
+        csv_field(&summary.session_id),
+        csv_field(&summary.topic),
+        csv_field(&summary.status),
+        summary.created_at.to_rfc3339(),
+        question_count,
+        task_columns.join(","),
+        task_times.values().sum::<u64>()
+    )
+}
+
+/// Quotes a field when it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemorySessionStore;
+    use graph_flow::Session;
+
+    async fn stored(storage: &InMemorySessionStore, id: &str) {
+        storage.save(Session::new_from_task(id.to_string(), "categorizer")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn export_covers_the_sessions_stored_when_it_started() {
+        let storage = Arc::new(InMemorySessionStore::new());
+        for id in ["a", "b", "c"] {
+            stored(&storage, id).await;
+        }
+        let mut rows = Box::pin(csv_stream(storage.clone()));
+        assert_eq!(rows.next().await.unwrap().unwrap(), csv_header());
+        let first = rows.next().await.unwrap().unwrap();
+
+        stored(&storage, "late").await;
+        let remaining: Vec<String> = rows.map(Result::unwrap).collect().await;
+        assert_eq!(remaining.len(), 2);
+        assert!(!remaining.iter().any(|row| row.starts_with("late,")));
+        assert!(!remaining.contains(&first));
+    }
+
+    #[tokio::test]
+    async fn sessions_removed_during_the_export_are_skipped() {
+        let storage = Arc::new(InMemorySessionStore::new());
+        for id in ["a", "b", "c"] {
+            stored(&storage, id).await;
+        }
+        let mut rows = Box::pin(csv_stream(storage.clone()));
+        rows.next().await.unwrap().unwrap();
+        let first = rows.next().await.unwrap().unwrap();
+        for id in ["a", "b", "c"].into_iter().filter(|id| !first.starts_with(&format!("{},", id))) {
+            storage.delete(id).await.unwrap();
+        }
+        assert!(rows.next().await.is_none());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::config::settings;
+use crate::tasks::{
+    ApprovalTask, CategorizerTask, ContradictionsTask, FactCheckerTask, QuestionExtractorTask,
+    ReporterTask, ResearcherTask, SummarizerTask,
+};
+use graph_flow::{Context, Graph, GraphBuilder, Task};
+use serde::Serialize;
+use std::sync::Arc;
+
+pub const GRAPH_ID: &str = "research_workflow";
+
+pub const START_TASK: &str = "categorizer";
+
+pub const TASKS: &[&str] = &[
+    "categorizer",
+    "question_extractor",
+    "researcher",
+    "summarizer",
+    "contradictions",
+    "fact_checker",
+    "approval",
+    "reporter",
+];
+
+/// Tasks that call the LLM, whose model `task_models` can set.
+pub const LLM_TASKS: &[&str] = &[
+    "categorizer",
+    "question_extractor",
+    "researcher",
+    "summarizer",
+    "contradictions",
+    "fact_checker",
+    "reporter",
+];
+
+pub const EDGES: &[(&str, &str)] = &[
+    ("categorizer", "question_extractor"),
+    ("question_extractor", "researcher"),
+    ("researcher", "summarizer"),
+    ("summarizer", "contradictions"),
+    ("fact_checker", "approval"),
+    ("approval", "reporter"),
+];
+
+pub const CONDITIONAL_EDGES: &[ConditionalEdge] = &[ConditionalEdge {
+    from: "contradictions",
+    condition: "FACT_CHECK",
+    check: fact_check_enabled,
+    yes: "fact_checker",
+    no: "approval",
+}];
+
+/// Edges that tasks take themselves with `NextAction::GoTo`, listed with the
+/// condition under which they do. They are not added to the builder, only
+/// to the description served on `/graph`.
+pub const LOOP_EDGES: &[(&str, &str, &str)] = &[
+    (
+        "researcher",
+        "researcher",
+        "search findings < MIN_TOTAL_FINDINGS && research_attempts <= MIN_FINDINGS_RETRIES",
+    ),
+    (
+        "summarizer",
+        "researcher",
+        "follow-up questions && research_iterations < MAX_RESEARCH_ITERATIONS",
+    ),
+];
+
+pub struct ConditionalEdge {
+    pub from: &'static str,
+    pub condition: &'static str,
+    pub check: fn(&Context) -> bool,
+    pub yes: &'static str,
+    pub no: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphDescription {
+    pub id: &'static str,
+    pub start: &'static str,
+    pub nodes: Vec<&'static str>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub from: &'static str,
+    pub to: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+}
+
+fn fact_check_enabled(_context: &Context) -> bool {
+    settings().fact_check
+}
+
+/// The workflow's task instances, in `TASKS` order.
+fn tasks() -> Vec<Arc<dyn Task>> {
+    vec![
+        Arc::new(CategorizerTask),
+        Arc::new(QuestionExtractorTask),
+        Arc::new(ResearcherTask),
+        Arc::new(SummarizerTask::from_env()),
+        Arc::new(ContradictionsTask),
+        Arc::new(FactCheckerTask),
+        Arc::new(ApprovalTask::from_env()),
+        Arc::new(ReporterTask),
+    ]
+}
+
+/// Builds the research workflow. Edges come from `EDGES` and
+/// `CONDITIONAL_EDGES` so that the description served on `/graph` cannot
+/// drift from the real topology.
+pub fn build_graph() -> Graph {
+    let builder = tasks()
+        .into_iter()
+        .fold(GraphBuilder::new(GRAPH_ID), |builder, task| builder.add_task(task));
+
+    let builder = EDGES
+        .iter()
+        .fold(builder, |builder, (from, to)| builder.add_edge(*from, *to));
+
+    CONDITIONAL_EDGES
+        .iter()
+        .fold(builder, |builder, edge| {
+            builder.add_conditional_edge(edge.from, edge.check, edge.yes, edge.no)
+        })
+        .build()
+}
+
+pub fn describe() -> GraphDescription {
+    let edges = EDGES
+        .iter()
+        .map(|(from, to)| GraphEdge {
+            from,
+            to,
+            condition: None,
+        })
+        .chain(CONDITIONAL_EDGES.iter().flat_map(|edge| {
+            [
+                GraphEdge {
+                    from: edge.from,
+                    to: edge.yes,
+                    condition: Some(edge.condition.to_string()),
+                },
+                GraphEdge {
+                    from: edge.from,
+                    to: edge.no,
+                    condition: Some(format!("!{}", edge.condition)),
+                },
+            ]
+        }))
+        .chain(LOOP_EDGES.iter().map(|(from, to, condition)| GraphEdge {
+            from,
+            to,
+            condition: Some(condition.to_string()),
+        }))
+        .collect();
+
+    GraphDescription {
+        id: GRAPH_ID,
+        start: START_TASK,
+        nodes: TASKS.to_vec(),
+        edges,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_list_matches_the_built_tasks() {
+        let ids: Vec<String> = tasks().iter().map(|task| task.id().to_string()).collect();
+        assert_eq!(ids, TASKS);
+    }
+
+    #[test]
+    fn edges_connect_known_tasks() {
+        assert!(TASKS.contains(&START_TASK));
+        assert!(LLM_TASKS.iter().all(|task| TASKS.contains(task)));
+        let description = describe();
+        for edge in &description.edges {
+            assert!(TASKS.contains(&edge.from), "unknown task {}", edge.from);
+            assert!(TASKS.contains(&edge.to), "unknown task {}", edge.to);
+        }
+        // Every task but the start has an incoming edge.
+        for task in TASKS.iter().filter(|task| **task != START_TASK) {
+            assert!(description.edges.iter().any(|edge| edge.to == *task), "{} is unreachable", task);
+        }
+    }
+
+    #[test]
+    fn fact_checker_is_skipped_unless_enabled() {
+        let edge = CONDITIONAL_EDGES.iter().find(|edge| edge.from == "contradictions").unwrap();
+        assert_eq!((edge.yes, edge.no), ("fact_checker", "approval"));
+        assert_eq!(edge.condition, "FACT_CHECK");
+        // `FACT_CHECK` is unset under test.
+        assert!(!(edge.check)(&Context::new()));
+    }
+
+    #[test]
+    fn follow_up_loop_is_described() {
+        let description = describe();
+        let edge = description
+            .edges
+            .iter()
+            .find(|edge| (edge.from, edge.to) == ("summarizer", "researcher"))
+            .expect("summarizer loops back to the researcher");
+        assert!(edge.condition.as_deref().unwrap().contains("MAX_RESEARCH_ITERATIONS"));
+    }
+
+    #[test]
+    fn findings_retry_loop_is_described() {
+        let description = describe();
+        let edge = description
+            .edges
+            .iter()
+            .find(|edge| (edge.from, edge.to) == ("researcher", "researcher"))
+            .expect("researcher retries itself");
+        let condition = edge.condition.as_deref().unwrap();
+        assert!(condition.contains("MIN_TOTAL_FINDINGS") && condition.contains("MIN_FINDINGS_RETRIES"));
+    }
+}
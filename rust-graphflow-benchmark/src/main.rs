@@ -1,127 +1,3077 @@
+mod config;
+mod context_keys;
+mod error;
+mod export;
+mod graph;
 mod models;
+mod openapi;
+mod presets;
+mod queue;
+mod sampling;
+mod stats;
+mod storage;
 mod tasks;
 mod tools;
 
 use anyhow::Result;
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
-use graph_flow::{FlowRunner, GraphBuilder, Session, SessionStorage};
-use models::{ResearchContext, ResearchRequest, ResearchResponse};
-use std::sync::Arc;
-use tasks::{QuestionExtractorTask, ReporterTask, ResearcherTask, SummarizerTask};
+use config::{Config, LogFormat};
+use context_keys::{
+    get_research_context, set_research_context, LLM_CALLS, SUBCALL_TIMES, TASK_TIMES, TTFT_MS,
+};
+use error::{panic_response, ErrorKind, ResearchError};
+use graph_flow::{FlowRunner, Session, SessionStorage, Task};
+use models::{
+    ApprovalRequest, Artifact, BatchRequest, BatchResponse, BatchResult, CompareRequest,
+    CompareResponse, ExportQuery, Followup, FollowupRequest, ListQuery, LlmCall, OutputFormat,
+    QuestionsRequest, QuestionsResponse, QueuedResponse, RegenerateReportRequest, ReplayRequest,
+    ReportDiffRequest, ReportDiffResponse, ReportFormat, ReportSectionPage, ReportVersion,
+    ResearchContext, ResearchQuery, ResearchRequest, ResearchResponse, SessionBundle, SessionQuery,
+    TaskCallback, TokenUsage, VariantResult, WorkflowConfig, MAX_DOCUMENTS_BYTES,
+    MAX_OUTPUT_LANGUAGES, MAX_SESSION_ID_CHARS, MAX_TAGS, MAX_TAG_CHARS, MAX_QUESTIONS,
+    SESSION_BUNDLE_VERSION,
+};
+use stats::{LatencyStats, RecentError, RecentErrors, StatsResponse};
+use futures::{stream, Stream};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use queue::{QueuedResearch, ResearchQueue};
+use storage::{InMemorySessionStore, ExtendedSessionStorage, SessionFilter, SessionPage};
+use tasks::{answer_followup, followup_prompt, judge_reports, split_sections, QuestionExtractorTask, ReporterTask};
+use tools::language::detect_language;
+use tools::llm::{self, LLM_BREAKER};
+use tools::moderation::moderate;
+use tools::retry::with_retry_budget;
+use tools::tavily::valid_domains;
+use tools::unicode::normalize_text;
+use tools::usage::{capped_calls, cost_breakdown, current_usage, take_calls, track_usage};
+use tools::webhook;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
-use tracing::{info, instrument};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use uuid::Uuid;
 
 #[derive(Clone)]
 struct AppState {
     runner: Arc<FlowRunner>,
-    storage: Arc<dyn SessionStorage>,
+    storage: Arc<dyn ExtendedSessionStorage>,
+    stats: Arc<Mutex<LatencyStats>>,
+    errors: Arc<Mutex<RecentErrors>>,
+    config: Arc<Config>,
+    shutdown: Arc<tokio::sync::Notify>,
+    /// Set when `RESEARCH_QUEUE_WORKERS` enables the async `Prefer: respond-async` path.
+    queue: Option<Arc<ResearchQueue>>,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter("rust_graphflow_benchmark=debug,graph_flow=info")
-        .init();
+    let config = match Config::from_env() {
+        Ok(config) => Arc::new(config),
+        Err(errors) => {
+            eprintln!("Invalid configuration:");
+            for error in errors {
+                eprintln!("  - {}", error);
+            }
+            std::process::exit(1);
+        }
+    };
+    config::install(config.clone());
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter("rust_graphflow_benchmark=debug,graph_flow=info,audit=info");
+    if config.log_format == LogFormat::Json {
+        subscriber.json().finish().with(sampling::filter()).init();
+    } else {
+        subscriber.finish().with(sampling::filter()).init();
+    }
 
-    let storage: Arc<dyn SessionStorage> = Arc::new(graph_flow::InMemorySessionStorage::new());
+    let storage: Arc<dyn ExtendedSessionStorage> = Arc::new(InMemorySessionStore::new());
     
-    let graph = GraphBuilder::new("research_workflow")
-        .add_task(Arc::new(QuestionExtractorTask))
-        .add_task(Arc::new(ResearcherTask))
-        .add_task(Arc::new(SummarizerTask))
-        .add_task(Arc::new(ReporterTask))
-        .add_edge("question_extractor", "researcher")
-        .add_edge("researcher", "summarizer")
-        .add_edge("summarizer", "reporter")
-        .build();
-
-    let runner = Arc::new(FlowRunner::new(Arc::new(graph), storage.clone()));
-    let state = AppState { runner, storage };
+    let graph = graph::build_graph();
+
+    let runner = Arc::new(FlowRunner::new(Arc::new(graph), storage.clone() as Arc<dyn SessionStorage>));
+    let mut state = AppState {
+        runner,
+        storage,
+        stats: Arc::new(Mutex::new(LatencyStats::default())),
+        errors: Arc::new(Mutex::new(RecentErrors::new(config.recent_errors_capacity))),
+        config: config.clone(),
+        shutdown: Arc::new(tokio::sync::Notify::new()),
+        queue: None,
+    };
+    if let Some(workers) = config.research_queue_workers.filter(|workers| *workers > 0) {
+        let capacity = config.research_queue_capacity;
+        let worker_state = state.clone();
+        let mut queue = ResearchQueue::start(workers, capacity, move |job| {
+            let state = worker_state.clone();
+            async move { run_queued(&state, job).await }
+        });
+        if let Some(high) = config.queue_high_water {
+            let low = config.queue_low_water.unwrap_or(high / 2);
+            queue = queue.with_watermarks(high, low);
+        }
+        state.queue = Some(Arc::new(queue));
+    }
+    let shutdown = state.shutdown.clone();
+
+    if let Some(ttl) = config.session_ttl_secs {
+        spawn_session_cleanup(state.storage.clone(), std::time::Duration::from_secs(ttl));
+    }
+
+    if let Some(topic) = config.warmup_topic.clone() {
+        warmup(&state, topic).await;
+    }
 
     let app = Router::new()
         .route("/health", get(health))
-        .route("/research", post(research))
+        .route("/health/ready", get(readiness))
+        .route("/graph", get(graph_definition))
+        .route("/openapi.json", get(openapi_spec))
+        .route("/stats", get(latency_stats))
+        .route("/errors", get(recent_errors))
+        .route("/research", get(list_sessions).post(research))
+        .route("/research/import", post(import_session))
+        .route("/research/:id", get(get_session))
+        .route("/research/:id/export", get(export_session))
+        .route("/research/:id/approve", post(approve))
+        .route("/research/:id/followup", post(followup))
+        .route("/research/:id/followup/stream", post(followup_stream))
+        .route("/research/:id/report", post(regenerate_report))
+        .route("/research/:id/replay", post(replay))
+        .route("/questions", post(questions))
+        .route("/benchmark/compare", post(compare))
+        .route("/benchmark/batch", post(batch))
+        .route("/benchmark/export", get(export_benchmark))
+        .route("/reports/diff", post(diff_reports))
+        .route("/admin/shutdown", post(admin_shutdown))
+        .layer(axum::middleware::from_fn(pretty_json))
+        // Outside `pretty_json`, which needs the uncompressed body.
+        .layer(CompressionLayer::new())
+        .layer(axum::middleware::from_fn(sampling::sample_request))
+        .layer(CatchPanicLayer::custom(panic_response))
         .layer(CorsLayer::permissive())
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
     info!("Rust GraphFlow benchmark server running on http://0.0.0.0:3000");
     
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(shutdown))
+        .await?;
+    info!("Server stopped");
     Ok(())
 }
 
+/// Resolves on Ctrl-C or when `/admin/shutdown` is called. axum then stops
+/// accepting connections and waits for in-flight requests to finish.
+async fn shutdown_signal(shutdown: Arc<tokio::sync::Notify>) {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C, shutting down"),
+        _ = shutdown.notified() => info!("Shutdown requested via admin endpoint"),
+    }
+}
+
+/// Triggers a graceful shutdown. Requires `Authorization: Bearer <ADMIN_TOKEN>`;
+/// when `ADMIN_TOKEN` is unset every request is rejected.
+async fn admin_shutdown(State(state): State<AppState>, headers: HeaderMap) -> StatusCode {
+    let admin_token = std::env::var("ADMIN_TOKEN").ok();
+    request_shutdown(&state, admin_token.as_deref(), &headers)
+}
+
+/// Signals shutdown if `headers` carry `admin_token` as a bearer token.
+fn request_shutdown(state: &AppState, admin_token: Option<&str>, headers: &HeaderMap) -> StatusCode {
+    let expected = admin_token.filter(|token| !token.is_empty());
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match (expected, provided) {
+        (Some(expected), Some(provided)) if constant_time_eq(expected.as_bytes(), provided.as_bytes()) => {
+            state.shutdown.notify_one();
+            StatusCode::ACCEPTED
+        }
+        _ => {
+            tracing::warn!("Rejected unauthorized shutdown request");
+            StatusCode::UNAUTHORIZED
+        }
+    }
+}
+
+/// Compares secrets without an early exit, so response time does not reveal
+/// how much of a guessed token was right. Only the length can leak.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Re-serializes JSON responses with indentation when the request has
+/// `?pretty=true` or an `X-Pretty: true` header. Responses stay compact otherwise.
+async fn pretty_json(request: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    let wants_pretty = request
+        .uri()
+        .query()
+        .is_some_and(|query| query.split('&').any(|pair| pair == "pretty=true" || pair == "pretty=1" || pair == "pretty"))
+        || request
+            .headers()
+            .get("x-pretty")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"));
+
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !wants_pretty || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes)
+        .and_then(|value| serde_json::to_vec_pretty(&value))
+    {
+        Ok(pretty) => pretty,
+        Err(_) => bytes.to_vec(),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, axum::body::Body::from(body))
+}
+
+/// Periodically evicts finished sessions not updated for `ttl`. Runs every
+/// `ttl`, capped at once a minute, so an expired session outlives its TTL by
+/// at most that long.
+fn spawn_session_cleanup(storage: Arc<dyn ExtendedSessionStorage>, ttl: std::time::Duration) {
+    let period = ttl.min(std::time::Duration::from_secs(60)).max(std::time::Duration::from_secs(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match storage.evict_expired(ttl).await {
+                Ok(0) => {}
+                Ok(evicted) => info!(evicted, "Evicted expired sessions"),
+                Err(e) => tracing::warn!("Session cleanup failed: {}", e),
+            }
+        }
+    });
+}
+
+#[utoipa::path(get, path = "/health", responses((status = 200, description = "Server is up", body = String)))]
 async fn health() -> &'static str {
     "OK"
 }
 
-#[instrument(skip(state))]
-async fn research(
+/// 503 while the research queue is shedding load, so a load balancer can
+/// route around a backlogged instance.
+async fn readiness(State(state): State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
+    let queue_depth = state.queue.as_ref().map(|queue| queue.depth());
+    let shedding = state.queue.as_ref().is_some_and(|queue| queue.is_shedding());
+    let status = if shedding {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "ready": !shedding,
+            "queue_depth": queue_depth,
+        })),
+    )
+}
+
+async fn openapi_spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(openapi::spec())
+}
+
+async fn graph_definition() -> Json<graph::GraphDescription> {
+    Json(graph::describe())
+}
+
+async fn latency_stats(State(state): State<AppState>) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        latency: state.stats.lock().unwrap().clone(),
+        llm_circuit_breaker: LLM_BREAKER.status(),
+        queue_depth: state.queue.as_ref().map(|queue| queue.depth()),
+    })
+}
+
+const DEFAULT_ERRORS_LIMIT: usize = 20;
+
+async fn recent_errors(State(state): State<AppState>, Query(query): Query<ListQuery>) -> Json<Vec<RecentError>> {
+    let limit = query.limit.unwrap_or(DEFAULT_ERRORS_LIMIT);
+    Json(state.errors.lock().unwrap().latest(limit))
+}
+
+/// Default and maximum page sizes for `GET /research`.
+const DEFAULT_LIST_LIMIT: usize = 50;
+const MAX_LIST_LIMIT: usize = 500;
+
+#[utoipa::path(
+    get,
+    path = "/research",
+    params(ListQuery),
+    responses((status = 200, description = "Stored sessions, newest first", body = SessionPage))
+)]
+async fn list_sessions(
     State(state): State<AppState>,
-    Json(req): Json<ResearchRequest>,
-) -> Result<Json<ResearchResponse>, StatusCode> {
+    Query(query): Query<ListQuery>,
+) -> Result<Json<SessionPage>, StatusCode> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let filter = SessionFilter {
+        tag: query.tag,
+        run_group: query.run_group,
+    };
+    state
+        .storage
+        .list_sessions(limit, offset, &filter)
+        .await
+        .map(Json)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Streams all stored sessions as CSV. `csv` is currently the only format.
+async fn export_benchmark(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, StatusCode> {
+    if query.format.as_deref().is_some_and(|format| format != "csv") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"benchmark.csv\""),
+        ],
+        axum::body::Body::from_stream(export::csv_stream(state.storage.clone())),
+    )
+        .into_response())
+}
+
+/// Runs a single research workflow before the listener is bound so that
+/// connection pools and lazy initialization are primed for the first request.
+async fn warmup(state: &AppState, topic: String) {
+    info!("Running warmup workflow for topic: {}", topic);
     let start_time = std::time::Instant::now();
     let session_id = Uuid::new_v4().to_string();
-    
-    info!("Starting research workflow for session {}", session_id);
+    let req = ResearchRequest {
+        topic,
+        ..Default::default()
+    };
+    match run_research(state, session_id, req, &CancellationToken::new()).await {
+        Ok(_) => info!("Warmup succeeded in {:?}", start_time.elapsed()),
+        Err(status) => tracing::warn!("Warmup failed with {} after {:?}", status, start_time.elapsed()),
+    }
+}
 
-    let session = Session::new_from_task(session_id.clone(), "question_extractor");
-    let context = ResearchContext {
-        topic: req.topic.clone(),
-        questions: vec![],
-        research_results: vec![],
-        summary: String::new(),
-        report: String::new(),
+#[utoipa::path(
+    post,
+    path = "/research",
+    request_body = ResearchRequest,
+    params(ResearchQuery),
+    responses(
+        (status = 200, description = "Completed research; shape depends on `format`", body = ResearchResponse),
+        (status = 202, description = "Queued (`Prefer: respond-async`); poll `GET /research/{id}`", body = QueuedResponse),
+        (status = 400, description = "Invalid provided questions or session_id"),
+        (status = 409, description = "A request with the same Idempotency-Key is still running, or `session_id` is taken"),
+        (status = 413, description = "Seed documents too large"),
+        (status = 429, description = "Research queue is full"),
+        (status = 503, description = "LLM provider unavailable"),
+        (status = 504, description = "Workflow deadline exceeded; body carries the partial result"),
+    )
+)]
+#[instrument(skip(state, headers))]
+async fn research(
+    State(state): State<AppState>,
+    Query(query): Query<ResearchQuery>,
+    headers: HeaderMap,
+    Json(req): Json<ResearchRequest>,
+) -> Result<Response, ResearchError> {
+    if state.queue.as_ref().is_some_and(|queue| queue.is_shedding()) {
+        return Err(StatusCode::SERVICE_UNAVAILABLE.into());
+    }
+
+    let format = query.format.unwrap_or_else(|| format_from_accept(&headers));
+    let include = parse_include(query.include.as_deref())?;
+    let session_id = match &req.session_id {
+        Some(id) if valid_session_id(id) => id.clone(),
+        Some(id) => {
+            warn!("Rejecting request with invalid session_id {:?}", id);
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+        None => Uuid::new_v4().to_string(),
     };
-    
-    session.context.set("research_context", context).await;
-    (*state.storage).save(session).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    loop {
-        let result = state.runner.run(&session_id).await
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    if let Some(key) = &idempotency_key {
+        let claimed = state
+            .storage
+            .claim_idempotency_key(key, &session_id)
+            .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if let Some(prior_session_id) = claimed {
+            let response = replay_session(&state, &prior_session_id).await?;
+            let response = select_artifacts(&state, response, include.as_deref()).await?;
+            return Ok(shape_response(response, format));
+        }
+    }
 
-        match &result.status {
-            graph_flow::ExecutionStatus::Completed => {
-                info!("Workflow completed in {:?}", start_time.elapsed());
-                break;
+    let start_time = std::time::Instant::now();
+    let provided_id = req.session_id.is_some();
+    let context = match prepare_research(&session_id, req).await {
+        Ok(context) => context,
+        Err(e) => {
+            record_recent_error(&state, &session_id, &e);
+            if let Some(key) = &idempotency_key {
+                let _ = state.storage.release_idempotency_key(key).await;
             }
-            graph_flow::ExecutionStatus::Paused { next_task_id, .. } => {
-                info!("Workflow paused, next task: {}", next_task_id);
-                continue;
+            return Err(e);
+        }
+    };
+
+    // Claim a provided id only once the request has passed validation, so a
+    // rejected request does not leave the id taken. Two requests naming the
+    // same id cannot both get past `create_session`.
+    if provided_id {
+        let session = Session::new_from_task(session_id.clone(), graph::START_TASK);
+        set_research_context(&session.context, context.clone()).await;
+        let created = state
+            .storage
+            .create_session(session)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !created {
+            warn!(session_id = %session_id, "Rejecting request for an existing session id");
+            if let Some(key) = &idempotency_key {
+                let _ = state.storage.release_idempotency_key(key).await;
             }
-            graph_flow::ExecutionStatus::Error(e) => {
-                tracing::error!("Workflow error: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(StatusCode::CONFLICT.into());
+        }
+    }
+
+    if prefers_async(&headers) {
+        if let Some(queue) = &state.queue {
+            return enqueue_research(&state, queue, session_id, context, idempotency_key).await;
+        }
+    }
+
+    let guard = CancellationGuard::new(state.storage.clone(), session_id.clone(), idempotency_key.clone());
+    let result = start_research(&state, &session_id, context, start_time, &CancellationToken::new()).await;
+    guard.disarm();
+
+    if let Err(e) = &result {
+        record_recent_error(&state, &session_id, e);
+        if let Some(key) = &idempotency_key {
+            let _ = state.storage.release_idempotency_key(key).await;
+        }
+    }
+    let response = select_artifacts(&state, result?, include.as_deref()).await?;
+    Ok(shape_response(response, format))
+}
+
+fn valid_session_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_SESSION_ID_CHARS
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn parse_include(include: Option<&str>) -> Result<Option<Vec<Artifact>>, ResearchError> {
+    include
+        .map(Artifact::parse_list)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST.into())
+}
+
+/// Narrows `response` to the artifacts in `include`, reading findings and
+/// captured LLM calls from the stored session. Without `include`, the
+/// response is returned as-is: everything except those heavy fields.
+async fn select_artifacts(
+    state: &AppState,
+    mut response: ResearchResponse,
+    include: Option<&[Artifact]>,
+) -> Result<ResearchResponse, ResearchError> {
+    let Some(include) = include else {
+        return Ok(response);
+    };
+
+    if !include.contains(&Artifact::Questions) {
+        response.questions.clear();
+    }
+    if !include.contains(&Artifact::Summary) {
+        response.summary.clear();
+        response.key_points.clear();
+    }
+    let prompts = include.contains(&Artifact::Prompts);
+    let raw_responses = include.contains(&Artifact::RawResponses);
+    if !include.contains(&Artifact::Findings) && !prompts && !raw_responses {
+        return Ok(response);
+    }
+
+    let session = (*state.storage).get(&response.session_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if include.contains(&Artifact::Findings) {
+        response.findings = get_research_context(&session.context)
+            .await
+            .map(|context| context.research_results);
+    }
+    if prompts || raw_responses {
+        let calls: Vec<LlmCall> = context_keys::get(&session.context, LLM_CALLS).await.unwrap_or_default();
+        response.llm_calls = Some(
+            calls
+                .into_iter()
+                .map(|call| LlmCall {
+                    task: call.task,
+                    prompt: call.prompt.filter(|_| prompts),
+                    response: call.response.filter(|_| raw_responses),
+                })
+                .collect(),
+        );
+    }
+    Ok(response)
+}
+
+#[utoipa::path(
+    post,
+    path = "/questions",
+    request_body = QuestionsRequest,
+    responses(
+        (status = 200, description = "Extracted research questions", body = QuestionsResponse),
+        (status = 422, description = "Topic rejected by moderation"),
+    )
+)]
+#[instrument(skip(req), fields(topic = %req.topic))]
+async fn questions(Json(req): Json<QuestionsRequest>) -> Result<Json<QuestionsResponse>, ResearchError> {
+    extract_questions(&QuestionExtractorTask, req).await.map(Json)
+}
+
+/// Runs `extractor` alone for `/questions`.
+async fn extract_questions(extractor: &dyn Task, mut req: QuestionsRequest) -> Result<QuestionsResponse, ResearchError> {
+    let start_time = std::time::Instant::now();
+    req.topic = normalize_text(&req.topic);
+    check_moderation("questions", &req.topic).await?;
+
+    // Runs the extractor task on a throwaway context; no session is stored and
+    // none of the search or report tasks are involved.
+    let context = graph_flow::Context::new();
+    set_research_context(
+        &context,
+        ResearchContext {
+            topic: req.topic.clone(),
+            detected_language: detect_language(&req.topic),
+            config: presets::resolve(&req.config, req.depth),
+            ..Default::default()
+        },
+    )
+    .await;
+    extractor.run(context.clone()).await.map_err(|e| {
+        tracing::error!("Question extraction failed: {}", e);
+        workflow_error(&e.to_string())
+    })?;
+
+    let research_context = get_research_context(&context)
+        .await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(QuestionsResponse {
+        topic: research_context.topic,
+        questions: research_context.questions,
+        extraction_attempts: research_context.extraction_attempts,
+        total_time_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/benchmark/compare",
+    request_body = CompareRequest,
+    responses(
+        (status = 200, description = "Both variants' results side by side", body = CompareResponse),
+        (status = 400, description = "Not exactly two variants"),
+    )
+)]
+#[instrument(skip(state, req), fields(topic = %req.topic))]
+async fn compare(
+    State(state): State<AppState>,
+    Json(req): Json<CompareRequest>,
+) -> Result<Json<CompareResponse>, ResearchError> {
+    if req.variants.len() != 2 {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let runs = req.variants.iter().map(|config| {
+        let request = ResearchRequest {
+            topic: req.topic.clone(),
+            config: config.clone(),
+            ..Default::default()
+        };
+        run_research(&state, Uuid::new_v4().to_string(), request, &CancellationToken::new())
+    });
+    let responses = futures::future::try_join_all(runs).await?;
+
+    Ok(Json(CompareResponse {
+        topic: req.topic,
+        results: req
+            .variants
+            .into_iter()
+            .zip(responses)
+            .map(|(config, response)| VariantResult { config, response })
+            .collect(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/benchmark/batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Each topic's result or error, in request order", body = BatchResponse),
+        (status = 400, description = "No topics"),
+    )
+)]
+#[instrument(skip(state, req), fields(topics = req.topics.len(), fail_fast = req.fail_fast))]
+async fn batch(
+    State(state): State<AppState>,
+    Json(req): Json<BatchRequest>,
+) -> Result<Json<BatchResponse>, ResearchError> {
+    if req.topics.is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let state = &state;
+    let config = &req.config;
+    let outcomes = run_batch(&req.topics, req.fail_fast, |topic, cancel| async move {
+        let request = ResearchRequest {
+            topic: topic.clone(),
+            config: config.clone(),
+            ..Default::default()
+        };
+        run_research(state, Uuid::new_v4().to_string(), request, &cancel).await
+    })
+    .await;
+
+    Ok(Json(BatchResponse {
+        results: req
+            .topics
+            .into_iter()
+            .zip(outcomes)
+            .map(|(topic, outcome)| match outcome {
+                Ok(response) => BatchResult {
+                    topic,
+                    response: Some(response),
+                    error: None,
+                },
+                Err(e) => BatchResult {
+                    topic,
+                    response: None,
+                    error: Some(e.to_string()),
+                },
+            })
+            .collect(),
+    }))
+}
+
+/// Runs `run` for every topic concurrently, in request order. With
+/// `fail_fast` the first failure cancels the shared token, so the sibling
+/// runs stop at their next task and report `cancelled: batch fail_fast`.
+async fn run_batch<'a, T, F, Fut>(
+    topics: &'a [String],
+    fail_fast: bool,
+    run: F,
+) -> Vec<Result<T, ResearchError>>
+where
+    F: Fn(&'a String, CancellationToken) -> Fut,
+    Fut: std::future::Future<Output = Result<T, ResearchError>>,
+{
+    let cancel = CancellationToken::new();
+    let runs = topics.iter().map(|topic| {
+        let outcome = run(topic, cancel.clone());
+        let cancel = &cancel;
+        async move {
+            let outcome = outcome.await;
+            if fail_fast && outcome.is_err() {
+                cancel.cancel();
             }
-            _ => continue,
+            outcome
+        }
+    });
+    futures::future::join_all(runs).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/reports/diff",
+    request_body = ReportDiffRequest,
+    responses(
+        (status = 200, description = "Length and citation comparison of two reports", body = ReportDiffResponse),
+        (status = 404, description = "Unknown session"),
+        (status = 409, description = "A session has not produced a report yet"),
+    )
+)]
+#[instrument(skip(state, req), fields(first = %req.first, second = %req.second))]
+async fn diff_reports(
+    State(state): State<AppState>,
+    Json(req): Json<ReportDiffRequest>,
+) -> Result<Json<ReportDiffResponse>, ResearchError> {
+    let mut contexts = Vec::with_capacity(2);
+    for session_id in [&req.first, &req.second] {
+        let session = (*state.storage).get(session_id).await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let context = get_research_context(&session.context).await
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        if context.report.is_empty() {
+            return Err(StatusCode::CONFLICT.into());
         }
+        contexts.push(context);
+    }
+    let (first, second) = (&contexts[0], &contexts[1]);
+
+    let first_sources = first.sources();
+    let second_sources = second.sources();
+    let (shared_sources, only_in_first): (Vec<String>, Vec<String>) = first_sources
+        .iter()
+        .cloned()
+        .partition(|url| second_sources.contains(url));
+    let only_in_second = second_sources
+        .into_iter()
+        .filter(|url| !first_sources.contains(url))
+        .collect();
+
+    let judgment = if req.judge {
+        Some(judge_reports(first, second).await.map_err(|e| {
+            tracing::error!("Report judgment failed: {}", e);
+            workflow_error(&e.to_string())
+        })?)
+    } else {
+        None
+    };
+
+    let first_words = first.report.split_whitespace().count();
+    let second_words = second.report.split_whitespace().count();
+    Ok(Json(ReportDiffResponse {
+        first: req.first,
+        second: req.second,
+        first_words,
+        second_words,
+        length_delta: second_words as i64 - first_words as i64,
+        shared_sources,
+        only_in_first,
+        only_in_second,
+        judgment,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/research/{id}/approve",
+    params(("id" = String, Path, description = "Session awaiting approval")),
+    request_body = ApprovalRequest,
+    responses(
+        (status = 200, description = "Approved; the report has been generated", body = ResearchResponse),
+        (status = 404, description = "Unknown session"),
+        (status = 409, description = "Session is not awaiting approval, or another approval is in progress"),
+    )
+)]
+#[instrument(skip(state, approval))]
+async fn approve(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(approval): Json<ApprovalRequest>,
+) -> Result<Json<ResearchResponse>, ResearchError> {
+    let start_time = std::time::Instant::now();
+    // Checking `awaiting_approval` and clearing it are separate steps, so
+    // concurrent approvals are serialized here; the loser sees a 409.
+    let claimed = state.storage.claim_approval(&session_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !claimed {
+        warn!(session_id = %session_id, "Rejecting a concurrent approval");
+        return Err(StatusCode::CONFLICT.into());
+    }
+    let applied = apply_approval(&state, &session_id, approval).await;
+    let _ = state.storage.release_approval(&session_id).await;
+    applied?;
+
+    drive_workflow(&state, &session_id, start_time, &CancellationToken::new()).await.map(Json)
+}
+
+/// Records the approval, and any edited summary, on a session that is
+/// awaiting it.
+async fn apply_approval(state: &AppState, session_id: &str, approval: ApprovalRequest) -> Result<(), ResearchError> {
+    let session = (*state.storage).get(session_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut context = get_research_context(&session.context).await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !context.awaiting_approval {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    if let Some(summary) = approval.summary {
+        info!(session_id = %session_id, "Approved with an edited summary");
+        context.summary = summary;
+    }
+    context.awaiting_approval = false;
+    context.approved = true;
+    set_research_context(&session.context, context).await;
+    (*state.storage).save(session).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/research/{id}/followup",
+    params(("id" = String, Path, description = "Completed session to ask about")),
+    request_body = FollowupRequest,
+    responses(
+        (status = 200, description = "Answer based on the session's findings", body = Followup),
+        (status = 400, description = "Empty question"),
+        (status = 404, description = "Unknown session"),
+        (status = 409, description = "Session has not produced a report yet"),
+    )
+)]
+#[instrument(skip(state, req))]
+async fn followup(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(req): Json<FollowupRequest>,
+) -> Result<Json<Followup>, ResearchError> {
+    let question = req.question.trim();
+    if question.is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
     }
 
     let session = (*state.storage).get(&session_id).await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
+    let mut context = get_research_context(&session.context).await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    if context.report.is_empty() {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    let answer = answer_followup(&context, question).await.map_err(|e| {
+        tracing::error!("Follow-up failed for session {}: {}", session_id, e);
+        workflow_error(&e.to_string())
+    })?;
+
+    let followup = Followup {
+        question: question.to_string(),
+        answer,
+    };
+    context.followups.push(followup.clone());
+    set_research_context(&session.context, context).await;
+    (*state.storage).save(session).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(followup))
+}
+
+#[utoipa::path(
+    post,
+    path = "/research/{id}/followup/stream",
+    params(("id" = String, Path, description = "Completed session to ask about")),
+    request_body = FollowupRequest,
+    responses(
+        (status = 200, description = "Server-sent `token` events with the answer text, then `done` (or `error`)"),
+        (status = 400, description = "Empty question"),
+        (status = 404, description = "Unknown session"),
+        (status = 409, description = "Session has not produced a report yet"),
+    )
+)]
+#[instrument(skip(state, req))]
+async fn followup_stream(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(req): Json<FollowupRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ResearchError> {
+    let question = req.question.trim().to_string();
+    if question.is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
 
-    let context: ResearchContext = session.context.get("research_context").await
+    let session = (*state.storage).get(&session_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let context = get_research_context(&session.context).await
         .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    if context.report.is_empty() {
+        return Err(StatusCode::CONFLICT.into());
+    }
 
-    let response = ResearchResponse {
-        session_id,
-        topic: req.topic,
-        questions: context.questions,
-        summary: context.summary,
-        report: context.report,
-        total_time_ms: start_time.elapsed().as_millis() as u64,
-        task_times: session.context.get("task_times").await.unwrap_or_default(),
+    let agent = llm::get_llm(&context.config).map_err(|e| workflow_error(&e.to_string()))?;
+    let chunks = llm::stream_llm(Arc::new(agent), followup_prompt(&context, &question))
+        .map_err(|e| workflow_error(&e.to_string()))?;
+
+    // The answer is stored once the stream completes, like the blocking
+    // endpoint does; a client that disconnects early leaves no follow-up.
+    let events = stream::unfold(Some((chunks, String::new())), move |pending| {
+        let state = state.clone();
+        let session_id = session_id.clone();
+        let question = question.clone();
+        async move {
+            let (mut chunks, mut answer) = pending?;
+            let event = match chunks.recv().await {
+                Some(Ok(chunk)) => {
+                    answer.push_str(&chunk);
+                    return Some((Ok(Event::default().event("token").data(chunk)), Some((chunks, answer))));
+                }
+                Some(Err(e)) => {
+                    tracing::error!("Streaming follow-up failed for session {}: {}", session_id, e);
+                    Event::default().event("error").data(ErrorKind::classify(&e.to_string()).to_string())
+                }
+                None => {
+                    store_followup(&state, &session_id, Followup { question, answer }).await;
+                    Event::default().event("done").data("")
+                }
+            };
+            Some((Ok(event), None))
+        }
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Appends a streamed follow-up to its session.
+async fn store_followup(state: &AppState, session_id: &str, followup: Followup) {
+    let Ok(Some(session)) = (*state.storage).get(session_id).await else {
+        warn!(session_id = %session_id, "Session vanished before its follow-up was stored");
+        return;
+    };
+    let Some(mut context) = get_research_context(&session.context).await else {
+        return;
     };
+    context.followups.push(followup);
+    set_research_context(&session.context, context).await;
+    if let Err(e) = (*state.storage).save(session).await {
+        warn!(session_id = %session_id, "Failed to store follow-up: {}", e);
+    }
+}
+
+/// `Prefer: respond-async` (RFC 7240) asks for a 202 and a session id to poll
+/// instead of holding the connection open for the whole workflow.
+fn prefers_async(headers: &HeaderMap) -> bool {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|preference| preference.trim().eq_ignore_ascii_case("respond-async"))
+}
+
+/// Stores the prepared session so the id can be polled right away, then
+/// queues it. A full queue is answered with 429.
+async fn enqueue_research(
+    state: &AppState,
+    queue: &ResearchQueue,
+    session_id: String,
+    context: ResearchContext,
+    idempotency_key: Option<String>,
+) -> Result<Response, ResearchError> {
+    let session = Session::new_from_task(session_id.clone(), graph::START_TASK);
+    set_research_context(&session.context, context.clone()).await;
+    (*state.storage).save(session).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let job = QueuedResearch {
+        session_id: session_id.clone(),
+        context,
+        idempotency_key,
+    };
+    match queue.try_enqueue(job) {
+        Ok(queue_position) => {
+            info!(session_id = %session_id, queue_position, "Queued research request");
+            Ok((
+                StatusCode::ACCEPTED,
+                Json(QueuedResponse {
+                    session_id,
+                    queue_position,
+                }),
+            )
+                .into_response())
+        }
+        Err(job) => {
+            tracing::warn!("Research queue is full, rejecting session {}", session_id);
+            let _ = (*state.storage).delete(&session_id).await;
+            if let Some(key) = &job.idempotency_key {
+                let _ = state.storage.release_idempotency_key(key).await;
+            }
+            Err(StatusCode::TOO_MANY_REQUESTS.into())
+        }
+    }
+}
+
+/// Worker side of the queue. The request was validated before it was
+/// queued, so a failure here comes from the workflow itself; it is written to
+/// the session's `status_message` for `GET /research/{id}` to report.
+async fn run_queued(state: &AppState, job: QueuedResearch) {
+    let start_time = std::time::Instant::now();
+    if let Err(e) = start_research(state, &job.session_id, job.context, start_time, &CancellationToken::new()).await {
+        tracing::warn!("Queued research for session {} failed: {}", job.session_id, e);
+        record_recent_error(state, &job.session_id, &e);
+        if let Some(key) = &job.idempotency_key {
+            let _ = state.storage.release_idempotency_key(key).await;
+        }
+        if let Ok(Some(mut session)) = (*state.storage).get(&job.session_id).await {
+            session.status_message = Some(format!("failed: {}", e.category()));
+            if let Err(e) = (*state.storage).save(session).await {
+                tracing::error!("Failed to record the failure of session {}: {}", job.session_id, e);
+            }
+        }
+    }
+}
+
+fn record_recent_error(state: &AppState, session_id: &str, error: &ResearchError) {
+    state
+        .errors
+        .lock()
+        .unwrap()
+        .push(session_id, error.category(), error.to_string());
+}
+
+#[utoipa::path(
+    get,
+    path = "/research/{id}",
+    params(("id" = String, Path, description = "Session id"), SessionQuery),
+    responses(
+        (status = 200, description = "The session's current state; `report` is empty until it completes. With `?section=`, one report section and the section index instead", body = ResearchResponse),
+        (status = 400, description = "`section` was requested for an HTML report"),
+        (status = 404, description = "Unknown session or section"),
+        (status = 409, description = "`section` was requested before the report exists"),
+    )
+)]
+async fn get_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Query(query): Query<SessionQuery>,
+) -> Result<Response, ResearchError> {
+    let response = load_response(&state, &session_id, std::time::Instant::now()).await?;
+    let Some(section) = query.section else {
+        let include = parse_include(query.include.as_deref())?;
+        let response = select_artifacts(&state, response, include.as_deref()).await?;
+        return Ok(Json(response).into_response());
+    };
+
+    if response.report.is_empty() {
+        return Err(StatusCode::CONFLICT.into());
+    }
+    if response.report_format == ReportFormat::Html {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let sections = split_sections(&response.report);
+    let (title, content) = sections.get(section).cloned().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ReportSectionPage {
+        session_id,
+        section,
+        title,
+        content,
+        sections: sections.into_iter().map(|(title, _)| title).collect(),
+    })
+    .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/research/{id}/report",
+    params(("id" = String, Path, description = "Completed session")),
+    request_body = RegenerateReportRequest,
+    responses(
+        (status = 200, description = "The newly generated report version", body = ReportVersion),
+        (status = 404, description = "Unknown session"),
+        (status = 409, description = "Session has not produced a report yet"),
+    )
+)]
+#[instrument(skip(state, req))]
+async fn regenerate_report(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    Json(req): Json<RegenerateReportRequest>,
+) -> Result<Json<ReportVersion>, ResearchError> {
+    rerun_reporter(&state, &ReporterTask, &session_id, req).await.map(Json)
+}
+
+/// Runs `reporter` on the stored session with `req`'s report settings.
+async fn rerun_reporter(
+    state: &AppState,
+    reporter: &dyn Task,
+    session_id: &str,
+    req: RegenerateReportRequest,
+) -> Result<ReportVersion, ResearchError> {
+    let session = (*state.storage).get(session_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let mut context = get_research_context(&session.context).await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    if context.report.is_empty() {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    if context.report_versions.is_empty() {
+        context.report_versions.push(current_report_version(&context, 1));
+    }
+    let previous = context.clone();
+
+    if let Some(report_format) = req.report_format {
+        context.report_format = report_format;
+    }
+    if req.audience.is_some() {
+        context.audience = req.audience;
+    }
+    context.report_max_words = req.max_words;
+    set_research_context(&session.context, context).await;
+
+    // The reporter only reads and writes the context, so it can run on the
+    // stored session directly without going through the graph.
+    reporter.run(session.context.clone()).await.map_err(|e| {
+        tracing::error!("Report regeneration failed for session {}: {}", session_id, e);
+        workflow_error(&e.to_string())
+    })?;
+
+    let mut context = get_research_context(&session.context).await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let version = current_report_version(&context, context.report_versions.len() + 1);
+    if !req.overwrite {
+        context = ResearchContext {
+            report_versions: context.report_versions,
+            ..previous
+        };
+    }
+    context.report_versions.push(version.clone());
+    set_research_context(&session.context, context).await;
+    (*state.storage).save(session).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!(session_id = %session_id, version = version.version, overwrite = req.overwrite, "Regenerated report");
+    Ok(version)
+}
+
+fn current_report_version(context: &ResearchContext, version: usize) -> ReportVersion {
+    ReportVersion {
+        version,
+        report: context.report.clone(),
+        report_format: context.report_format,
+        audience: context.audience.clone(),
+        max_words: context.report_max_words,
+    }
+}
+
+/// The original run's settings with the replay's model overrides. A new
+/// `model` replaces the original per-task models too, since otherwise any
+/// task with its own model would ignore it.
+fn replay_config(original: &WorkflowConfig, req: &ReplayRequest) -> WorkflowConfig {
+    let mut config = original.clone();
+    if let Some(model) = &req.model {
+        config.model = Some(model.clone());
+        config.task_models.clear();
+    }
+    config.task_models.extend(req.task_models.clone());
+    config
+}
 
-    Ok(Json(response))
-}
\ No newline at end of file
+#[utoipa::path(
+    post,
+    path = "/research/{id}/replay",
+    params(("id" = String, Path, description = "Completed session to replay")),
+    request_body = ReplayRequest,
+    responses(
+        (status = 200, description = "Result of the new session", body = ResearchResponse),
+        (status = 404, description = "Unknown session"),
+        (status = 409, description = "Session has not produced a report yet"),
+    )
+)]
+#[instrument(skip(state, req))]
+async fn replay(
+    State(state): State<AppState>,
+    Path(original_id): Path<String>,
+    Json(req): Json<ReplayRequest>,
+) -> Result<Response, ResearchError> {
+    let start_time = std::time::Instant::now();
+    let original = (*state.storage).get(&original_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let original = get_research_context(&original.context).await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    if original.report.is_empty() {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    let reuse_questions = req.reuse_findings || req.reuse_questions.unwrap_or(true);
+    let config = replay_config(&original.config, &req);
+
+    let session_id = Uuid::new_v4().to_string();
+    info!(
+        session_id = %session_id,
+        replayed_from = %original_id,
+        model = ?config.model,
+        reuse_questions,
+        reuse_findings = req.reuse_findings,
+        "Replaying session"
+    );
+
+    let session = Session::new_from_task(session_id.clone(), graph::START_TASK);
+    let context = ResearchContext {
+        topic: original.topic,
+        replayed_from: Some(original_id),
+        tags: original.tags,
+        run_group: original.run_group,
+        findings_reused: req.reuse_findings,
+        depth: original.depth,
+        config,
+        detected_language: original.detected_language,
+        extraction_skipped: reuse_questions,
+        questions: if reuse_questions { original.questions } else { Vec::new() },
+        audience: original.audience,
+        documents: original.documents,
+        include_domains: original.include_domains,
+        exclude_domains: original.exclude_domains,
+        research_results: if req.reuse_findings { original.research_results } else { Vec::new() },
+        report_format: original.report_format,
+        output_languages: original.output_languages,
+        ..Default::default()
+    };
+    set_research_context(&session.context, context).await;
+    (*state.storage).save(session).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let response = drive_workflow(&state, &session_id, start_time, &CancellationToken::new()).await?;
+    Ok(shape_response(response, OutputFormat::Full))
+}
+
+#[utoipa::path(
+    get,
+    path = "/research/{id}/export",
+    params(("id" = String, Path, description = "Session to export")),
+    responses(
+        (status = 200, description = "The session as a portable bundle", body = SessionBundle),
+        (status = 404, description = "Unknown session"),
+    )
+)]
+async fn export_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<Json<SessionBundle>, StatusCode> {
+    let session = (*state.storage).get(&session_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let context = get_research_context(&session.context).await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(SessionBundle {
+        version: SESSION_BUNDLE_VERSION,
+        session_id,
+        current_task: session.current_task_id,
+        context,
+        task_times: context_keys::get(&session.context, TASK_TIMES).await.unwrap_or_default(),
+        ttft_ms: context_keys::get(&session.context, TTFT_MS).await.unwrap_or_default(),
+        subcall_times: context_keys::get(&session.context, SUBCALL_TIMES).await.unwrap_or_default(),
+        llm_calls: context_keys::get(&session.context, LLM_CALLS).await.unwrap_or_default(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/research/import",
+    request_body = SessionBundle,
+    responses(
+        (status = 200, description = "The recreated session, under a new id", body = ResearchResponse),
+        (status = 400, description = "Unsupported bundle version or unknown current task"),
+    )
+)]
+#[instrument(skip(state, bundle), fields(imported_from = %bundle.session_id))]
+async fn import_session(
+    State(state): State<AppState>,
+    Json(bundle): Json<SessionBundle>,
+) -> Result<Json<ResearchResponse>, ResearchError> {
+    if bundle.version != SESSION_BUNDLE_VERSION {
+        warn!(version = bundle.version, "Rejecting bundle with unsupported version");
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    if !graph::TASKS.contains(&bundle.current_task.as_str()) {
+        warn!(task = %bundle.current_task, "Rejecting bundle with unknown current task");
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    // A fresh id keeps an import from overwriting the session it came from
+    // when both live in the same store.
+    let session_id = Uuid::new_v4().to_string();
+    let session = Session::new_from_task(session_id.clone(), &bundle.current_task);
+    set_research_context(&session.context, bundle.context).await;
+    context_keys::set(&session.context, TASK_TIMES, bundle.task_times).await;
+    context_keys::set(&session.context, TTFT_MS, bundle.ttft_ms).await;
+    context_keys::set(&session.context, SUBCALL_TIMES, bundle.subcall_times).await;
+    context_keys::set(&session.context, LLM_CALLS, bundle.llm_calls).await;
+    (*state.storage).save(session).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!(session_id = %session_id, "Imported session");
+    Ok(Json(load_response(&state, &session_id, std::time::Instant::now()).await?))
+}
+
+/// Answers a repeated `Idempotency-Key` with the response of the session it
+/// first started, or 409 while that session is still running.
+async fn replay_session(state: &AppState, session_id: &str) -> Result<ResearchResponse, ResearchError> {
+    let response = load_response(state, session_id, std::time::Instant::now()).await?;
+    if response.report.is_empty() {
+        info!("Idempotent request for session {} is still in progress", session_id);
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    info!("Replaying stored response for session {}", session_id);
+    Ok(response)
+}
+
+/// Picks an output format from the `Accept` header when no `?format=` is given.
+fn format_from_accept(headers: &HeaderMap) -> OutputFormat {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if accept.contains("text/markdown") {
+        OutputFormat::Report
+    } else if accept.contains("text/plain") {
+        OutputFormat::Summary
+    } else {
+        OutputFormat::Full
+    }
+}
+
+fn shape_response(response: ResearchResponse, format: OutputFormat) -> Response {
+    if response.awaiting_approval {
+        return (StatusCode::ACCEPTED, Json(response)).into_response();
+    }
+
+    match format {
+        OutputFormat::Full => Json(response).into_response(),
+        OutputFormat::Report => (
+            [(header::CONTENT_TYPE, response.report_format.content_type())],
+            response.report,
+        )
+            .into_response(),
+        OutputFormat::Summary => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            response.summary,
+        )
+            .into_response(),
+    }
+}
+
+/// Marks a session as cancelled if the handler future is dropped before the
+/// workflow finishes, which is how axum signals a client disconnect. Dropping
+/// the future also drops the in-flight task, so no further LLM or Tavily calls
+/// are made for the abandoned request.
+struct CancellationGuard {
+    storage: Arc<dyn ExtendedSessionStorage>,
+    session_id: String,
+    idempotency_key: Option<String>,
+    armed: bool,
+}
+
+impl CancellationGuard {
+    fn new(storage: Arc<dyn ExtendedSessionStorage>, session_id: String, idempotency_key: Option<String>) -> Self {
+        Self { storage, session_id, idempotency_key, armed: true }
+    }
+
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancellationGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        tracing::warn!("Client disconnected, cancelling session {}", self.session_id);
+        let storage = self.storage.clone();
+        let session_id = self.session_id.clone();
+        let idempotency_key = self.idempotency_key.clone();
+        sampling::spawn(async move {
+            if let Some(key) = idempotency_key {
+                let _ = storage.release_idempotency_key(&key).await;
+            }
+            if let Ok(Some(mut session)) = storage.get(&session_id).await {
+                session.status_message = Some("cancelled: client disconnected".to_string());
+                if let Err(e) = storage.save(session).await {
+                    tracing::error!("Failed to mark session {} as cancelled: {}", session_id, e);
+                }
+            }
+        });
+    }
+}
+
+async fn run_research(
+    state: &AppState,
+    session_id: String,
+    req: ResearchRequest,
+    cancel: &CancellationToken,
+) -> Result<ResearchResponse, ResearchError> {
+    let start_time = std::time::Instant::now();
+    let context = prepare_research(&session_id, req).await?;
+    start_research(state, &session_id, context, start_time, cancel).await
+}
+
+/// Validates `req` and screens its topic, returning the context the workflow
+/// starts from. Nothing is stored, so a rejected request leaves no trace of
+/// `session_id` behind.
+async fn prepare_research(session_id: &str, mut req: ResearchRequest) -> Result<ResearchContext, ResearchError> {
+    req.topic = normalize_text(&req.topic);
+
+    let questions = match req.questions.clone() {
+        Some(questions) => {
+            let questions: Vec<String> = questions
+                .into_iter()
+                .map(|q| q.trim().to_string())
+                .filter(|q| !q.is_empty())
+                .collect();
+            if questions.is_empty() || questions.len() > MAX_QUESTIONS {
+                tracing::warn!("Rejecting request with {} provided questions", questions.len());
+                return Err(StatusCode::BAD_REQUEST.into());
+            }
+            questions
+        }
+        None => vec![],
+    };
+
+    if let Err(reason) = req.config.validate() {
+        tracing::warn!("Rejecting request with invalid config: {}", reason);
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    if let Some(url) = &req.callback_url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            tracing::warn!("Rejecting request with invalid callback_url {:?}", url);
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+    }
+
+    let output_languages = distinct_languages(&req.output_languages);
+    if output_languages.len() > MAX_OUTPUT_LANGUAGES {
+        tracing::warn!("Rejecting request with {} output languages", output_languages.len());
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let tags: Vec<String> = req
+        .tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let run_group = req.run_group.as_deref().map(str::trim).filter(|group| !group.is_empty());
+    let too_long = |label: &str| label.chars().count() > MAX_TAG_CHARS;
+    if tags.len() > MAX_TAGS || tags.iter().any(|tag| too_long(tag)) || run_group.is_some_and(too_long) {
+        tracing::warn!("Rejecting request with {} tags or an overlong tag or run_group", tags.len());
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let documents_bytes: usize = req.documents.iter().map(|d| d.len()).sum();
+    if documents_bytes > MAX_DOCUMENTS_BYTES {
+        tracing::warn!("Rejecting request with {} bytes of documents", documents_bytes);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+    
+    if LLM_BREAKER.is_open() {
+        tracing::warn!("LLM circuit breaker is open, rejecting session {}", session_id);
+        return Err(StatusCode::SERVICE_UNAVAILABLE.into());
+    }
+
+    check_moderation(session_id, &req.topic).await?;
+
+    Ok(ResearchContext {
+        topic: req.topic.clone(),
+        callback_url: req.callback_url.clone(),
+        depth: req.depth,
+        config: presets::resolve(&req.config, req.depth),
+        detected_language: detect_language(&req.topic),
+        extraction_skipped: !questions.is_empty(),
+        questions,
+        audience: req.audience.clone(),
+        documents: req.documents.clone(),
+        include_domains: valid_domains(&req.include_domains),
+        exclude_domains: valid_domains(&req.exclude_domains),
+        report_format: req.report_format,
+        output_languages,
+        tags,
+        run_group: run_group.map(str::to_string),
+        ..Default::default()
+    })
+}
+
+/// Trims `languages` and drops blanks and case-insensitive repeats, keeping
+/// the first spelling, so each language gets one report and counts once
+/// towards `MAX_OUTPUT_LANGUAGES`.
+fn distinct_languages(languages: &[String]) -> Vec<String> {
+    let mut distinct: Vec<String> = Vec::new();
+    for language in languages.iter().map(|language| language.trim()) {
+        if !language.is_empty() && !distinct.iter().any(|seen| seen.eq_ignore_ascii_case(language)) {
+            distinct.push(language.to_string());
+        }
+    }
+    distinct
+}
+
+/// Stores a session for a prepared `context` and runs it to completion.
+async fn start_research(
+    state: &AppState,
+    session_id: &str,
+    context: ResearchContext,
+    start_time: std::time::Instant,
+    cancel: &CancellationToken,
+) -> Result<ResearchResponse, ResearchError> {
+    info!("Starting research workflow for session {}", session_id);
+
+    let session = Session::new_from_task(session_id.to_string(), graph::START_TASK);
+    set_research_context(&session.context, context).await;
+    (*state.storage).save(session).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    drive_workflow(state, session_id, start_time, cancel).await
+}
+
+/// Screens `topic` when `ENABLE_MODERATION` is set, recording the verdict in
+/// the audit log.
+async fn check_moderation(session_id: &str, topic: &str) -> Result<(), ResearchError> {
+    if !config::settings().enable_moderation {
+        return Ok(());
+    }
+
+    let moderation = moderate(topic).await;
+    info!(
+        target: "audit",
+        session_id = %session_id,
+        topic = %topic,
+        flagged = moderation.flagged,
+        source = moderation.source,
+        reason = moderation.reason.as_deref().unwrap_or(""),
+        "Topic moderation"
+    );
+    if moderation.flagged {
+        return Err(ResearchError::Moderated {
+            reason: moderation.reason.unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
+/// Runs the stored session until it completes, fails, or stops to wait for
+/// human approval, then builds the response from the stored context.
+/// `cancel` is only triggered by a failed `fail_fast` batch sibling; the
+/// running task is abandoned and the session marked `cancelled: batch fail_fast`.
+async fn drive_workflow(
+    state: &AppState,
+    session_id: &str,
+    start_time: std::time::Instant,
+    cancel: &CancellationToken,
+) -> Result<ResearchResponse, ResearchError> {
+    let deadline = state
+        .config
+        .workflow_deadline_secs
+        .map(|secs| tokio::time::Instant::now() + std::time::Duration::from_secs(secs));
+    let callback_url = match (*state.storage).get(session_id).await {
+        Ok(Some(session)) => get_research_context(&session.context)
+            .await
+            .and_then(|context| context.callback_url),
+        _ => None,
+    };
+
+    // Tasks return `NextAction::Continue`, so each `run` executes a single node
+    // and the runner saves the session before returning. Partial progress such as
+    // the researcher's findings is therefore persisted even if a later task fails.
+    // Tasks only see the `Context`, not the storage, so this is where saving
+    // after every node has to happen rather than in each task.
+    // `INTER_TASK_DELAY_MS` spaces out consecutive tasks for providers that
+    // penalize bursts; the pauses are reported so latency analysis can remove them.
+    let inter_task_delay = state
+        .config
+        .inter_task_delay_ms
+        .filter(|ms| *ms > 0)
+        .map(std::time::Duration::from_millis);
+    let mut injected_delay = std::time::Duration::ZERO;
+    let mut first_task = true;
+    let mut task_usage: HashMap<String, TokenUsage> = HashMap::new();
+    let mut capped_tasks: Vec<String> = Vec::new();
+    let workflow = track_usage(async {
+        loop {
+            if let Some(delay) = inter_task_delay.filter(|_| !first_task) {
+                tokio::time::sleep(delay).await;
+                injected_delay += delay;
+            }
+            first_task = false;
+
+            let task_id = current_task(state, session_id).await;
+            let usage_before = current_usage();
+            let capped_before = capped_calls();
+
+            let run = state.runner.run(session_id);
+            let run = async {
+                match deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline, run).await.ok(),
+                    None => Some(run.await),
+                }
+            };
+            let result = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => return Err(cancel_session(state, session_id).await),
+                result = run => match result {
+                    Some(result) => result.map_err(|e| workflow_error(&e.to_string()))?,
+                    None => return Err(deadline_exceeded(state, session_id, start_time).await),
+                },
+            };
+
+            let calls = take_calls();
+            if !calls.is_empty() {
+                store_llm_calls(state, session_id, task_id.as_deref().unwrap_or("unknown"), calls).await;
+            }
+            let usage_after = current_usage();
+            let usage = task_usage
+                .entry(task_id.clone().unwrap_or_else(|| "unknown".to_string()))
+                .or_default();
+            usage.prompt_tokens += usage_after.prompt_tokens - usage_before.prompt_tokens;
+            usage.completion_tokens += usage_after.completion_tokens - usage_before.completion_tokens;
+            usage.total_tokens = usage.prompt_tokens + usage.completion_tokens;
+            if let Some(task_id) = task_id.as_ref().filter(|_| capped_calls() > capped_before) {
+                if !capped_tasks.contains(task_id) {
+                    capped_tasks.push(task_id.clone());
+                }
+            }
+
+            if let (Some(url), Some(task_id)) = (&callback_url, &task_id) {
+                send_task_callback(state, session_id, url, task_id).await;
+            }
+
+            match &result.status {
+                graph_flow::ExecutionStatus::Completed => {
+                    info!(
+                        session_id = %session_id,
+                        elapsed_ms = start_time.elapsed().as_millis() as u64,
+                        "Workflow completed"
+                    );
+                    return Ok(());
+                }
+                graph_flow::ExecutionStatus::Paused { next_task_id, .. } => {
+                    info!("Workflow paused, next task: {}", next_task_id);
+                    continue;
+                }
+                graph_flow::ExecutionStatus::WaitingForInput => {
+                    info!(session_id = %session_id, "Workflow waiting for approval");
+                    return Ok(());
+                }
+                graph_flow::ExecutionStatus::Error(e) => {
+                    tracing::error!("Workflow error: {}", e);
+                    return Err(workflow_error(e));
+                }
+                _ => continue,
+            }
+        }
+    });
+    let budget = state.config.request_retry_budget;
+    let ((outcome, token_usage), retry_budget_remaining) = with_retry_budget(budget, workflow).await;
+    if let Err(ResearchError::Workflow { kind, .. }) = &outcome {
+        state.stats.lock().unwrap().record_error(*kind);
+    }
+    outcome?;
+
+    let mut response = load_response(state, session_id, start_time).await?;
+    response.token_usage = token_usage;
+    response.token_capped_tasks = capped_tasks;
+    if state.config.cost_breakdown {
+        response.cost_breakdown = cost_breakdown(&task_usage, llm::model_name(&response.config));
+    }
+    response.retry_budget_remaining = retry_budget_remaining;
+    response.injected_delay_ms = injected_delay.as_millis() as u64;
+    if response.awaiting_approval {
+        return Ok(response);
+    }
+
+    info!(
+        session_id = %session_id,
+        total_time_ms = response.total_time_ms,
+        prompt_tokens = token_usage.prompt_tokens,
+        completion_tokens = token_usage.completion_tokens,
+        total_tokens = token_usage.total_tokens,
+        "Research request completed"
+    );
+
+    state
+        .stats
+        .lock()
+        .unwrap()
+        .record(&response.task_times, response.total_time_ms);
+
+    Ok(response)
+}
+
+/// Appends a task's captured LLM calls to the stored session.
+async fn store_llm_calls(state: &AppState, session_id: &str, task_id: &str, calls: Vec<(String, String)>) {
+    let Ok(Some(session)) = (*state.storage).get(session_id).await else {
+        return;
+    };
+    let mut stored: Vec<LlmCall> = context_keys::get(&session.context, LLM_CALLS).await.unwrap_or_default();
+    stored.extend(calls.into_iter().map(|(prompt, response)| LlmCall {
+        task: task_id.to_string(),
+        prompt: Some(prompt),
+        response: Some(response),
+    }));
+    context_keys::set(&session.context, LLM_CALLS, stored).await;
+    if let Err(e) = (*state.storage).save(session).await {
+        warn!(session_id = %session_id, "Failed to store captured LLM calls: {}", e);
+    }
+}
+
+async fn current_task(state: &AppState, session_id: &str) -> Option<String> {
+    (*state.storage)
+        .get(session_id)
+        .await
+        .ok()
+        .flatten()
+        .map(|session| session.current_task_id)
+}
+
+/// Sends the context as it stands after `task_id` to the request's webhook.
+async fn send_task_callback(state: &AppState, session_id: &str, url: &str, task_id: &str) {
+    let Ok(Some(session)) = (*state.storage).get(session_id).await else {
+        return;
+    };
+    if let Some(context) = get_research_context(&session.context).await {
+        webhook::notify(
+            url.to_string(),
+            &TaskCallback {
+                session_id,
+                task_id,
+                context: &context,
+            },
+        );
+    }
+}
+
+/// Builds the response from whatever the stored session currently holds.
+async fn load_response(
+    state: &AppState,
+    session_id: &str,
+    start_time: std::time::Instant,
+) -> Result<ResearchResponse, StatusCode> {
+    let session = (*state.storage).get(session_id).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let context = get_research_context(&session.context).await
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stage_models = graph::LLM_TASKS
+        .iter()
+        .map(|task| {
+            let model = llm::model_name(&context.config.for_task(task)).to_string();
+            (task.to_string(), model)
+        })
+        .collect();
+
+    Ok(ResearchResponse {
+        session_id: session_id.to_string(),
+        replayed_from: context.replayed_from,
+        tags: context.tags,
+        run_group: context.run_group,
+        status_message: session.status_message.clone(),
+        topic: context.topic,
+        depth: context.depth,
+        config: context.config,
+        category: context.category,
+        detected_language: context.detected_language,
+        questions: context.questions,
+        extraction_skipped: context.extraction_skipped,
+        extraction_attempts: context.extraction_attempts,
+        question_errors: context.question_errors,
+        skipped_questions: context.skipped_questions,
+        irrelevant_questions: context.irrelevant_questions,
+        research_iterations: context.research_iterations,
+        search_unavailable: context.search_unavailable,
+        summary: context.summary,
+        summary_score: context.summary_score,
+        key_points: context.key_points,
+        unsupported_claims: context.unsupported_claims,
+        grounding: context.grounding,
+        contradictions: context.contradictions,
+        audience: context.audience,
+        report: context.report,
+        report_format: context.report_format,
+        reports: context.reports,
+        structured_report: context.structured_report,
+        report_truncated_input: context.report_truncated_input,
+        report_retried: context.report_retried,
+        report_versions: context.report_versions,
+        awaiting_approval: context.awaiting_approval,
+        followups: context.followups,
+        total_time_ms: start_time.elapsed().as_millis() as u64,
+        task_times: context_keys::get(&session.context, TASK_TIMES).await.unwrap_or_default(),
+        subcall_times: context_keys::get(&session.context, SUBCALL_TIMES).await.unwrap_or_default(),
+        stage_models,
+        ttft_ms: context_keys::get(&session.context, TTFT_MS).await.unwrap_or_default(),
+        token_usage: Default::default(),
+        token_capped_tasks: Vec::new(),
+        cost_breakdown: None,
+        retry_budget_remaining: None,
+        injected_delay_ms: 0,
+        findings: None,
+        llm_calls: None,
+    })
+}
+
+/// Called when `WORKFLOW_DEADLINE_SECS` elapses. The in-flight task has been
+/// dropped, so the stored session still points at it as the current task.
+async fn deadline_exceeded(
+    state: &AppState,
+    session_id: &str,
+    start_time: std::time::Instant,
+) -> ResearchError {
+    let task = match (*state.storage).get(session_id).await {
+        Ok(Some(session)) => session.current_task_id,
+        _ => "unknown".to_string(),
+    };
+    tracing::error!("Workflow deadline exceeded for session {} while running {}", session_id, task);
+
+    match load_response(state, session_id, start_time).await {
+        Ok(partial) => ResearchError::DeadlineExceeded {
+            task,
+            partial: Box::new(partial),
+        },
+        Err(status) => status.into(),
+    }
+}
+
+/// Marks a session stopped by a failed `fail_fast` batch sibling.
+async fn cancel_session(state: &AppState, session_id: &str) -> ResearchError {
+    const REASON: &str = "batch fail_fast";
+    warn!(session_id = %session_id, "Cancelling research: {}", REASON);
+    if let Ok(Some(mut session)) = (*state.storage).get(session_id).await {
+        session.status_message = Some(format!("cancelled: {}", REASON));
+        if let Err(e) = (*state.storage).save(session).await {
+            tracing::error!("Failed to mark session {} as cancelled: {}", session_id, e);
+        }
+    }
+    ResearchError::Cancelled { reason: REASON.to_string() }
+}
+
+/// Categorizes a failed workflow and reports it as 503 when the LLM circuit
+/// breaker tripped, so clients can tell a provider outage apart from a bug.
+fn workflow_error(message: &str) -> ResearchError {
+    let kind = ErrorKind::classify(message);
+    let status = if LLM_BREAKER.is_open() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    tracing::warn!(error_kind = %kind, "Workflow failed");
+    ResearchError::Workflow { kind, status }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tools::llm::Model;
+
+    fn test_state() -> AppState {
+        let store = Arc::new(InMemorySessionStore::new());
+        let graph = Arc::new(graph::build_graph());
+        AppState {
+            runner: Arc::new(FlowRunner::new(graph, store.clone() as Arc<dyn SessionStorage>)),
+            storage: store,
+            stats: Arc::new(Mutex::new(LatencyStats::default())),
+            errors: Arc::new(Mutex::new(RecentErrors::new(10))),
+            config: Arc::new(Config::default()),
+            shutdown: Arc::new(tokio::sync::Notify::new()),
+            queue: None,
+        }
+    }
+
+    /// A state that runs `graph` instead of the research workflow.
+    fn state_with_graph(graph: graph_flow::Graph) -> AppState {
+        let state = test_state();
+        AppState {
+            runner: Arc::new(FlowRunner::new(Arc::new(graph), state.storage.clone() as Arc<dyn SessionStorage>)),
+            ..state
+        }
+    }
+
+    /// A state whose queue accepts jobs without running them, so requests
+    /// on the async path stop after validation and storage.
+    fn queued_state() -> AppState {
+        let mut state = test_state();
+        state.queue = Some(Arc::new(ResearchQueue::start(1, 10, |_job| async {})));
+        state
+    }
+
+    async fn post_research(state: &AppState, req: ResearchRequest) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert("prefer", "respond-async".parse().unwrap());
+        research(State(state.clone()), Query(ResearchQuery::default()), headers, Json(req))
+            .await
+            .into_response()
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn request_with_id(id: &str) -> ResearchRequest {
+        ResearchRequest {
+            topic: "Rust async runtimes".to_string(),
+            session_id: Some(id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn session_id_format() {
+        assert!(valid_session_id("run-42_a.b"));
+        assert!(!valid_session_id(""));
+        assert!(!valid_session_id("has space"));
+        assert!(!valid_session_id("../etc"));
+        assert!(!valid_session_id(&"x".repeat(MAX_SESSION_ID_CHARS + 1)));
+    }
+
+    #[tokio::test]
+    async fn provided_session_id_is_used() {
+        let state = queued_state();
+        let response = post_research(&state, request_with_id("experiment-1")).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(json_body(response).await["session_id"], "experiment-1");
+        assert!((*state.storage).get("experiment-1").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn provided_session_id_collision_is_rejected() {
+        let state = queued_state();
+        let first = post_research(&state, request_with_id("taken")).await;
+        assert_eq!(first.status(), StatusCode::ACCEPTED);
+        let second = post_research(&state, request_with_id("taken")).await;
+        assert_eq!(second.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn rejected_request_does_not_claim_its_session_id() {
+        let state = queued_state();
+        let invalid = ResearchRequest {
+            questions: Some(vec!["q".to_string(); MAX_QUESTIONS + 1]),
+            ..request_with_id("retry-me")
+        };
+        assert_eq!(post_research(&state, invalid).await.status(), StatusCode::BAD_REQUEST);
+        assert!((*state.storage).get("retry-me").await.unwrap().is_none());
+
+        let retry = post_research(&state, request_with_id("retry-me")).await;
+        assert_eq!(retry.status(), StatusCode::ACCEPTED);
+    }
+
+    /// Stores a session paused at the approval step with `summary`.
+    async fn paused_session(state: &AppState, id: &str, summary: &str) {
+        let session = Session::new_from_task(id.to_string(), "approval");
+        let context = ResearchContext {
+            topic: "Rust async runtimes".to_string(),
+            summary: summary.to_string(),
+            awaiting_approval: true,
+            ..Default::default()
+        };
+        set_research_context(&session.context, context).await;
+        (*state.storage).save(session).await.unwrap();
+    }
+
+    fn approval_with(summary: &str) -> Json<ApprovalRequest> {
+        Json(ApprovalRequest {
+            summary: Some(summary.to_string()),
+        })
+    }
+
+    #[tokio::test]
+    async fn approval_with_edit_replaces_the_summary() {
+        let state = test_state();
+        paused_session(&state, "paused", "generated").await;
+
+        // The reporter may fail afterwards without an LLM; the approval and
+        // the edit are stored before it runs.
+        let _ = approve(State(state.clone()), Path("paused".to_string()), approval_with("edited")).await;
+        let session = (*state.storage).get("paused").await.unwrap().unwrap();
+        let context = get_research_context(&session.context).await.unwrap();
+        assert_eq!(context.summary, "edited");
+        assert!(context.approved);
+        assert!(!context.awaiting_approval);
+
+        let again = approve(State(state.clone()), Path("paused".to_string()), approval_with("late")).await;
+        assert_eq!(again.err().map(|e| e.status()), Some(StatusCode::CONFLICT));
+    }
+
+    #[tokio::test]
+    async fn concurrent_approvals_admit_one() {
+        let state = test_state();
+        paused_session(&state, "raced", "generated").await;
+
+        let (first, second) = tokio::join!(
+            approve(State(state.clone()), Path("raced".to_string()), approval_with("first")),
+            approve(State(state.clone()), Path("raced".to_string()), approval_with("second")),
+        );
+        let conflicts = [first, second]
+            .into_iter()
+            .filter(|result| matches!(result, Err(e) if e.status() == StatusCode::CONFLICT))
+            .count();
+        assert_eq!(conflicts, 1);
+    }
+
+    #[test]
+    fn replay_model_replaces_the_original_task_models() {
+        let original = WorkflowConfig {
+            model: Some(Model::Gpt4oMini),
+            task_models: HashMap::from([("summarizer".to_string(), Model::Gpt4o)]),
+            ..Default::default()
+        };
+
+        let unchanged = replay_config(&original, &ReplayRequest::default());
+        assert_eq!(unchanged, original);
+
+        let replayed = replay_config(
+            &original,
+            &ReplayRequest {
+                model: Some(Model::Gpt41Mini),
+                ..Default::default()
+            },
+        );
+        assert_eq!(llm::model_name(&replayed.for_task("summarizer")), "gpt-4.1-mini");
+        assert_eq!(llm::model_name(&replayed.for_task("researcher")), "gpt-4.1-mini");
+
+        let per_task = replay_config(
+            &original,
+            &ReplayRequest {
+                model: Some(Model::Gpt41Mini),
+                task_models: HashMap::from([("reporter".to_string(), Model::Gpt41)]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(llm::model_name(&per_task.for_task("reporter")), "gpt-4.1");
+        assert_eq!(llm::model_name(&per_task.for_task("summarizer")), "gpt-4.1-mini");
+    }
+
+    #[tokio::test]
+    async fn approval_claim_is_exclusive_until_released() {
+        let storage = InMemorySessionStore::new();
+        assert!(storage.claim_approval("s").await.unwrap());
+        assert!(!storage.claim_approval("s").await.unwrap());
+        storage.release_approval("s").await.unwrap();
+        assert!(storage.claim_approval("s").await.unwrap());
+    }
+
+    /// A batch run that fails at once when its topic is "fails" and otherwise
+    /// works for `work` unless it is cancelled first.
+    async fn batch_run(topic: &String, cancel: CancellationToken, work: std::time::Duration) -> Result<(), ResearchError> {
+        if topic == "fails" {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => Err(ResearchError::Cancelled { reason: "batch fail_fast".to_string() }),
+            _ = tokio::time::sleep(work) => Ok(()),
+        }
+    }
+
+    #[tokio::test]
+    async fn fail_fast_cancels_the_siblings() {
+        let topics = vec!["waits".to_string(), "fails".to_string()];
+        let outcomes = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            run_batch(&topics, true, |topic, cancel| batch_run(topic, cancel, std::time::Duration::from_secs(60))),
+        )
+        .await
+        .expect("the sibling should be cancelled, not run to completion");
+        assert_eq!(outcomes[0].as_ref().unwrap_err().to_string(), "cancelled: batch fail_fast");
+        assert_eq!(outcomes[1].as_ref().unwrap_err().status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let outcomes = run_batch(&topics, false, |topic, cancel| {
+            batch_run(topic, cancel, std::time::Duration::from_millis(10))
+        })
+        .await;
+        assert!(outcomes[0].is_ok());
+    }
+
+    #[tokio::test]
+    async fn cancelled_run_marks_its_session() {
+        let state = test_state();
+        let session = Session::new_from_task("sibling".to_string(), graph::START_TASK);
+        (*state.storage).save(session).await.unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let result = drive_workflow(&state, "sibling", std::time::Instant::now(), &cancel).await;
+        assert!(matches!(result, Err(ResearchError::Cancelled { .. })));
+        let session = (*state.storage).get("sibling").await.unwrap().unwrap();
+        assert_eq!(session.status_message.as_deref(), Some("cancelled: batch fail_fast"));
+        assert_eq!(session.current_task_id, graph::START_TASK);
+    }
+
+    #[tokio::test]
+    async fn repeated_output_languages_get_one_report_each() {
+        let mut languages = vec!["French".to_string(); MAX_OUTPUT_LANGUAGES + 1];
+        languages.extend([" french ".to_string(), "FRENCH".to_string(), "Japanese".to_string(), " ".to_string()]);
+        let req = ResearchRequest {
+            output_languages: languages,
+            ..request_with_id("languages")
+        };
+        let context = prepare_research("languages", req).await.unwrap();
+        assert_eq!(context.output_languages, ["French", "Japanese"]);
+
+        let too_many = ResearchRequest {
+            output_languages: (0..=MAX_OUTPUT_LANGUAGES).map(|i| format!("Language {}", i)).collect(),
+            ..request_with_id("too-many")
+        };
+        assert!(prepare_research("too-many", too_many).await.is_err());
+    }
+
+    /// Stands in for the researcher: records a finding and hands over.
+    struct FindingsTask;
+
+    #[async_trait::async_trait]
+    impl Task for FindingsTask {
+        fn id(&self) -> &str {
+            "researcher"
+        }
+
+        async fn run(&self, context: graph_flow::Context) -> graph_flow::Result<graph_flow::TaskResult> {
+            let research_context = ResearchContext {
+                research_results: vec![models::ResearchResult {
+                    question: "What is Tokio?".to_string(),
+                    findings: vec![],
+                    unanswerable: true,
+                }],
+                ..Default::default()
+            };
+            set_research_context(&context, research_context).await;
+            Ok(graph_flow::TaskResult::new(None, graph_flow::NextAction::Continue))
+        }
+    }
+
+    /// A task with the given id that always fails.
+    struct FailingTask(&'static str);
+
+    #[async_trait::async_trait]
+    impl Task for FailingTask {
+        fn id(&self) -> &str {
+            self.0
+        }
+
+        async fn run(&self, _context: graph_flow::Context) -> graph_flow::Result<graph_flow::TaskResult> {
+            Err(graph_flow::GraphError::Other(anyhow::anyhow!("{} crashed", self.0)))
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_is_saved_before_a_later_task_fails() {
+        let graph = graph_flow::GraphBuilder::new("persistence")
+            .add_task(Arc::new(FindingsTask))
+            .add_task(Arc::new(FailingTask("reporter")))
+            .add_edge("researcher", "reporter")
+            .build();
+        let store = Arc::new(InMemorySessionStore::new());
+        let runner = FlowRunner::new(Arc::new(graph), store.clone() as Arc<dyn SessionStorage>);
+        store.save(Session::new_from_task("crash".to_string(), "researcher")).await.unwrap();
+
+        runner.run("crash").await.unwrap();
+        // The failure surfaces as an error or an `Error` status depending on
+        // where it is caught; either way the earlier save must survive.
+        let failed = runner.run("crash").await;
+        assert!(failed.map_or(true, |result| matches!(result.status, graph_flow::ExecutionStatus::Error(_))));
+
+        let session = store.get("crash").await.unwrap().unwrap();
+        let saved = get_research_context(&session.context).await.unwrap();
+        assert_eq!(saved.research_results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_warmup_is_logged_not_fatal() {
+        let graph = graph_flow::GraphBuilder::new("warmup")
+            .add_task(Arc::new(FailingTask(graph::START_TASK)))
+            .build();
+        let state = state_with_graph(graph);
+        warmup(&state, "Rust async runtimes".to_string()).await;
+        assert_eq!(state.stats.lock().unwrap().errors.get(&ErrorKind::Other), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn dropped_request_cancels_its_session() {
+        let state = test_state();
+        for id in ["dropped", "finished"] {
+            (*state.storage).save(Session::new_from_task(id.to_string(), graph::START_TASK)).await.unwrap();
+        }
+        state.storage.claim_idempotency_key("retry", "dropped").await.unwrap();
+
+        CancellationGuard::new(state.storage.clone(), "finished".to_string(), None).disarm();
+        drop(CancellationGuard::new(state.storage.clone(), "dropped".to_string(), Some("retry".to_string())));
+
+        // The guard cleans up on a spawned task.
+        let status = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                let session = (*state.storage).get("dropped").await.unwrap().unwrap();
+                if let Some(status) = session.status_message {
+                    return status;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(status, "cancelled: client disconnected");
+        assert_eq!(state.storage.claim_idempotency_key("retry", "again").await.unwrap(), None);
+        let finished = (*state.storage).get("finished").await.unwrap().unwrap();
+        assert_eq!(finished.status_message, None);
+    }
+
+    /// Stores a finished session with `summary` and `report` and loads its response.
+    async fn finished_response(state: &AppState, id: &str, summary: &str, report: &str) -> ResearchResponse {
+        let session = Session::new_from_task(id.to_string(), "reporter");
+        let context = ResearchContext {
+            topic: "Rust async runtimes".to_string(),
+            summary: summary.to_string(),
+            report: report.to_string(),
+            ..Default::default()
+        };
+        set_research_context(&session.context, context).await;
+        (*state.storage).save(session).await.unwrap();
+        load_response(state, id, std::time::Instant::now()).await.unwrap()
+    }
+
+    async fn text_body(response: Response) -> (String, String) {
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (content_type, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn output_format_picks_the_body() {
+        let state = test_state();
+        let response = finished_response(&state, "shaped", "The summary", "# The report").await;
+
+        let (content_type, body) = text_body(shape_response(response.clone(), OutputFormat::Report)).await;
+        assert_eq!((content_type.as_str(), body.as_str()), ("text/markdown; charset=utf-8", "# The report"));
+        let (content_type, body) = text_body(shape_response(response.clone(), OutputFormat::Summary)).await;
+        assert_eq!((content_type.as_str(), body.as_str()), ("text/plain; charset=utf-8", "The summary"));
+        let full = json_body(shape_response(response, OutputFormat::Full)).await;
+        assert_eq!(full["report"], "# The report");
+        assert_eq!(full["summary"], "The summary");
+    }
+
+    /// A task with the given id that sleeps for the given time.
+    struct SleepingTask(&'static str, std::time::Duration);
+
+    #[async_trait::async_trait]
+    impl Task for SleepingTask {
+        fn id(&self) -> &str {
+            self.0
+        }
+
+        async fn run(&self, _context: graph_flow::Context) -> graph_flow::Result<graph_flow::TaskResult> {
+            tokio::time::sleep(self.1).await;
+            Ok(graph_flow::TaskResult::new(None, graph_flow::NextAction::Continue))
+        }
+    }
+
+    #[tokio::test]
+    async fn deadline_stops_the_workflow_with_the_partial_result() {
+        let graph = graph_flow::GraphBuilder::new("slow")
+            .add_task(Arc::new(SleepingTask(graph::START_TASK, std::time::Duration::from_secs(30))))
+            .build();
+        let state = AppState {
+            config: Arc::new(Config {
+                workflow_deadline_secs: Some(1),
+                ..Config::default()
+            }),
+            ..state_with_graph(graph)
+        };
+        let session = Session::new_from_task("slow".to_string(), graph::START_TASK);
+        let context = ResearchContext {
+            topic: "Rust async runtimes".to_string(),
+            ..Default::default()
+        };
+        set_research_context(&session.context, context).await;
+        (*state.storage).save(session).await.unwrap();
+
+        let started = std::time::Instant::now();
+        let result = drive_workflow(&state, "slow", started, &CancellationToken::new()).await;
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        match result {
+            Err(ResearchError::DeadlineExceeded { task, partial }) => {
+                assert_eq!(task, graph::START_TASK);
+                assert_eq!(partial.topic, "Rust async runtimes");
+            }
+            other => panic!("expected a deadline error, got {:?}", other.map(|r| r.topic)),
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_documents_are_rejected() {
+        let req = ResearchRequest {
+            documents: vec!["x".repeat(MAX_DOCUMENTS_BYTES + 1)],
+            ..request_with_id("documents")
+        };
+        let error = prepare_research("documents", req).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    async fn post_with_key(state: &AppState, key: &str) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert("prefer", "respond-async".parse().unwrap());
+        headers.insert("idempotency-key", key.parse().unwrap());
+        let req = ResearchRequest {
+            topic: "Rust async runtimes".to_string(),
+            ..Default::default()
+        };
+        research(State(state.clone()), Query(ResearchQuery::default()), headers, Json(req))
+            .await
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn duplicate_idempotency_key_returns_the_first_run() {
+        let state = queued_state();
+        let first = post_with_key(&state, "nightly-1").await;
+        assert_eq!(first.status(), StatusCode::ACCEPTED);
+        let session_id = json_body(first).await["session_id"].as_str().unwrap().to_string();
+
+        assert_eq!(post_with_key(&state, "nightly-1").await.status(), StatusCode::CONFLICT);
+
+        let session = (*state.storage).get(&session_id).await.unwrap().unwrap();
+        let mut context = get_research_context(&session.context).await.unwrap();
+        context.report = "# Report".to_string();
+        set_research_context(&session.context, context).await;
+        (*state.storage).save(session).await.unwrap();
+
+        let replayed = post_with_key(&state, "nightly-1").await;
+        assert_eq!(replayed.status(), StatusCode::OK);
+        let body = json_body(replayed).await;
+        assert_eq!(body["session_id"], session_id.as_str());
+        assert_eq!(body["report"], "# Report");
+        let page = state.storage.list_sessions(10, 0, &SessionFilter::default()).await.unwrap();
+        assert_eq!(page.total, 1);
+    }
+
+    /// A one-task workflow whose report names the search depth it ran with.
+    struct DepthReportTask;
+
+    #[async_trait::async_trait]
+    impl Task for DepthReportTask {
+        fn id(&self) -> &str {
+            graph::START_TASK
+        }
+
+        async fn run(&self, context: graph_flow::Context) -> graph_flow::Result<graph_flow::TaskResult> {
+            let mut research_context = get_research_context(&context).await.unwrap_or_default();
+            let depth = research_context.config.search_depth.clone().unwrap_or_default();
+            research_context.report = format!("searched {}", depth);
+            set_research_context(&context, research_context).await;
+            Ok(graph_flow::TaskResult::new(None, graph_flow::NextAction::End))
+        }
+    }
+
+    #[tokio::test]
+    async fn compare_runs_each_variant_in_its_own_session() {
+        let graph = graph_flow::GraphBuilder::new("compare")
+            .add_task(Arc::new(DepthReportTask))
+            .build();
+        let state = state_with_graph(graph);
+        let variant = |depth: &str| WorkflowConfig {
+            search_depth: Some(depth.to_string()),
+            ..Default::default()
+        };
+        let req = CompareRequest {
+            topic: "Rust async runtimes".to_string(),
+            variants: vec![variant("basic"), variant("advanced")],
+        };
+
+        let Json(response) = compare(State(state.clone()), Json(req)).await.unwrap();
+        let [basic, advanced] = &response.results[..] else {
+            panic!("expected two results, got {}", response.results.len());
+        };
+        assert_ne!(basic.response.session_id, advanced.response.session_id);
+        assert_eq!(basic.response.report, "searched basic");
+        assert_eq!(advanced.response.report, "searched advanced");
+        let page = state.storage.list_sessions(10, 0, &SessionFilter::default()).await.unwrap();
+        assert_eq!(page.total, 2);
+    }
+
+    #[tokio::test]
+    async fn compare_needs_exactly_two_variants() {
+        let req = CompareRequest {
+            topic: "Rust async runtimes".to_string(),
+            variants: vec![WorkflowConfig::default()],
+        };
+        let error = compare(State(test_state()), Json(req)).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn shutdown_needs_the_admin_token() {
+        let state = test_state();
+        let bearer = |token: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            headers
+        };
+
+        assert_eq!(request_shutdown(&state, Some("s3cret"), &HeaderMap::new()), StatusCode::UNAUTHORIZED);
+        assert_eq!(request_shutdown(&state, Some("s3cret"), &bearer("guess")), StatusCode::UNAUTHORIZED);
+        assert_eq!(request_shutdown(&state, None, &bearer("s3cret")), StatusCode::UNAUTHORIZED);
+        assert_eq!(request_shutdown(&state, Some(""), &bearer("")), StatusCode::UNAUTHORIZED);
+        let notified = tokio::time::timeout(std::time::Duration::from_millis(50), state.shutdown.notified());
+        assert!(notified.await.is_err());
+
+        assert_eq!(request_shutdown(&state, Some("s3cret"), &bearer("s3cret")), StatusCode::ACCEPTED);
+        let notified = tokio::time::timeout(std::time::Duration::from_secs(1), state.shutdown.notified());
+        assert!(notified.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn followups_need_a_finished_session_and_accumulate() {
+        let state = test_state();
+        let ask = |id: &str, question: &str| {
+            followup(
+                State(state.clone()),
+                Path(id.to_string()),
+                Json(FollowupRequest { question: question.to_string() }),
+            )
+        };
+        assert_eq!(ask("missing", "Why?").await.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+        let running = Session::new_from_task("running".to_string(), graph::START_TASK);
+        set_research_context(&running.context, ResearchContext::default()).await;
+        (*state.storage).save(running).await.unwrap();
+        assert_eq!(ask("running", "Why?").await.unwrap_err().status(), StatusCode::CONFLICT);
+
+        finished_response(&state, "done", "The summary", "# The report").await;
+        assert_eq!(ask("done", "   ").await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+        for (question, answer) in [("Why Tokio?", "Ecosystem."), ("Why not smol?", "Smaller.")] {
+            let followup = Followup { question: question.to_string(), answer: answer.to_string() };
+            store_followup(&state, "done", followup).await;
+        }
+        let session = (*state.storage).get("done").await.unwrap().unwrap();
+        let context = get_research_context(&session.context).await.unwrap();
+        let questions: Vec<&str> = context.followups.iter().map(|f| f.question.as_str()).collect();
+        assert_eq!(questions, ["Why Tokio?", "Why not smol?"]);
+        assert_eq!(context.report, "# The report");
+    }
+
+    #[tokio::test]
+    async fn each_task_posts_its_snapshot_to_the_callback_url() {
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+        let receiver = Router::new().route(
+            "/hook",
+            post(move |Json(body): Json<serde_json::Value>| {
+                let sender = sender.clone();
+                async move {
+                    let _ = sender.send(body);
+                    StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let graph = graph_flow::GraphBuilder::new("hooked")
+            .add_task(Arc::new(FindingsTask))
+            .add_task(Arc::new(SleepingTask("reporter", std::time::Duration::ZERO)))
+            .add_edge("researcher", "reporter")
+            .build();
+        let state = state_with_graph(graph);
+        let session = Session::new_from_task("hooked".to_string(), "researcher");
+        let context = ResearchContext {
+            callback_url: Some(url),
+            ..Default::default()
+        };
+        set_research_context(&session.context, context).await;
+        (*state.storage).save(session).await.unwrap();
+        drive_workflow(&state, "hooked", std::time::Instant::now(), &CancellationToken::new())
+            .await
+            .unwrap();
+
+        let mut callbacks = Vec::new();
+        for _ in 0..2 {
+            let callback = tokio::time::timeout(std::time::Duration::from_secs(5), received.recv()).await;
+            callbacks.push(callback.unwrap().unwrap());
+        }
+        // Callbacks are sent in the background, so they may arrive in either order.
+        callbacks.sort_by_key(|callback| callback["task_id"].as_str().unwrap().to_string());
+        assert_eq!(callbacks[0]["task_id"], "reporter");
+        assert_eq!(callbacks[1]["task_id"], "researcher");
+        assert!(callbacks.iter().all(|callback| callback["session_id"] == "hooked"));
+        assert_eq!(callbacks[1]["context"]["research_results"][0]["question"], "What is Tokio?");
+    }
+
+    /// An extractor that answers with fixed questions and counts its runs.
+    #[derive(Default)]
+    struct CannedExtractor(std::sync::atomic::AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl Task for CannedExtractor {
+        fn id(&self) -> &str {
+            "question_extractor"
+        }
+
+        async fn run(&self, context: graph_flow::Context) -> graph_flow::Result<graph_flow::TaskResult> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let mut research_context = get_research_context(&context).await.unwrap();
+            research_context.questions = vec!["What is Tokio?".to_string(), "Who maintains it?".to_string()];
+            research_context.extraction_attempts = 1;
+            set_research_context(&context, research_context).await;
+            Ok(graph_flow::TaskResult::new(None, graph_flow::NextAction::Continue))
+        }
+    }
+
+    #[tokio::test]
+    async fn questions_run_only_the_extractor() {
+        let extractor = CannedExtractor::default();
+        let req = QuestionsRequest {
+            topic: "Rust async runtimes".to_string(),
+            depth: Some(presets::Depth::Quick),
+            ..Default::default()
+        };
+        let response = extract_questions(&extractor, req).await.unwrap();
+        assert_eq!(response.topic, "Rust async runtimes");
+        assert_eq!(response.questions, ["What is Tokio?", "Who maintains it?"]);
+        assert_eq!(response.extraction_attempts, 1);
+        // The extractor is the only task run; no researcher means no searches.
+        assert_eq!(extractor.0.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    /// A reporter whose report names the settings it was asked for.
+    struct SettingsReporter;
+
+    #[async_trait::async_trait]
+    impl Task for SettingsReporter {
+        fn id(&self) -> &str {
+            "reporter"
+        }
+
+        async fn run(&self, context: graph_flow::Context) -> graph_flow::Result<graph_flow::TaskResult> {
+            let mut research_context = get_research_context(&context).await.unwrap();
+            research_context.report = format!(
+                "{} words for {}",
+                research_context.report_max_words.unwrap_or_default(),
+                research_context.audience.as_deref().unwrap_or("anyone")
+            );
+            set_research_context(&context, research_context).await;
+            Ok(graph_flow::TaskResult::new(None, graph_flow::NextAction::End))
+        }
+    }
+
+    #[tokio::test]
+    async fn report_variants_are_kept_as_versions() {
+        let state = test_state();
+        finished_response(&state, "rewrite", "The summary", "# Original").await;
+        let regenerate = |id: &'static str, audience: &str, max_words: usize, overwrite: bool| {
+            let req = RegenerateReportRequest {
+                audience: Some(audience.to_string()),
+                max_words: Some(max_words),
+                overwrite,
+                ..Default::default()
+            };
+            rerun_reporter(&state, &SettingsReporter, id, req)
+        };
+
+        let short = regenerate("rewrite", "executives", 200, false).await.unwrap();
+        assert_eq!((short.version, short.report.as_str()), (2, "200 words for executives"));
+        let long = regenerate("rewrite", "engineers", 900, true).await.unwrap();
+        assert_eq!((long.version, long.report.as_str()), (3, "900 words for engineers"));
+
+        let session = (*state.storage).get("rewrite").await.unwrap().unwrap();
+        let context = get_research_context(&session.context).await.unwrap();
+        let reports: Vec<&str> = context.report_versions.iter().map(|v| v.report.as_str()).collect();
+        assert_eq!(reports, ["# Original", "200 words for executives", "900 words for engineers"]);
+        assert_eq!(context.report, "900 words for engineers");
+        assert_eq!(context.audience.as_deref(), Some("engineers"));
+
+        let missing = regenerate("missing", "engineers", 900, true).await;
+        assert_eq!(missing.unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn replay_reuses_the_findings_under_a_new_model() {
+        let graph = graph_flow::GraphBuilder::new("replay")
+            .add_task(Arc::new(DepthReportTask))
+            .build();
+        let state = state_with_graph(graph);
+        let original = Session::new_from_task("original".to_string(), "reporter");
+        let research_results = vec![models::ResearchResult {
+            question: "What is Tokio?".to_string(),
+            findings: vec![],
+            unanswerable: true,
+        }];
+        let context = ResearchContext {
+            topic: "Rust async runtimes".to_string(),
+            questions: vec!["What is Tokio?".to_string()],
+            research_results: research_results.clone(),
+            report: "# Original".to_string(),
+            config: presets::resolve(&WorkflowConfig::default(), None),
+            ..Default::default()
+        };
+        set_research_context(&original.context, context).await;
+        (*state.storage).save(original).await.unwrap();
+
+        let req = ReplayRequest {
+            model: Some(Model::Gpt4o),
+            reuse_findings: true,
+            ..Default::default()
+        };
+        let response = replay(State(state.clone()), Path("original".to_string()), Json(req)).await.unwrap();
+        let body = json_body(response).await;
+        assert_eq!(body["replayed_from"], "original");
+        let replay_id = body["session_id"].as_str().unwrap();
+        assert_ne!(replay_id, "original");
+
+        let session = (*state.storage).get(replay_id).await.unwrap().unwrap();
+        let replayed = get_research_context(&session.context).await.unwrap();
+        assert!(replayed.findings_reused && replayed.extraction_skipped);
+        assert_eq!(replayed.questions, ["What is Tokio?"]);
+        assert_eq!(replayed.research_results.len(), research_results.len());
+        assert_eq!(replayed.research_results[0].question, "What is Tokio?");
+        assert_eq!(replayed.config.model, Some(Model::Gpt4o));
+        assert_eq!(replayed.report, "searched advanced");
+    }
+
+    #[tokio::test]
+    async fn pretty_flag_indents_json_responses() {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route("/json", get(|| async { Json(serde_json::json!({"topic": "tokio"})) }))
+            .route("/text", get(|| async { "plain" }))
+            .layer(axum::middleware::from_fn(pretty_json));
+        let body = |uri: &str, pretty_header: Option<&str>| {
+            let mut request = axum::http::Request::builder().uri(uri);
+            if let Some(value) = pretty_header {
+                request = request.header("x-pretty", value);
+            }
+            let response = app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap());
+            async move {
+                let bytes = axum::body::to_bytes(response.await.unwrap().into_body(), usize::MAX).await.unwrap();
+                String::from_utf8(bytes.to_vec()).unwrap()
+            }
+        };
+
+        let indented = "{\n  \"topic\": \"tokio\"\n}";
+        assert_eq!(body("/json", None).await, r#"{"topic":"tokio"}"#);
+        assert_eq!(body("/json?pretty=true", None).await, indented);
+        assert_eq!(body("/json", Some("yes")).await, indented);
+        assert_eq!(body("/json?prettyish=1", None).await, r#"{"topic":"tokio"}"#);
+        assert_eq!(body("/text?pretty=true", None).await, "plain");
+    }
+
+    /// Stores a finished session whose report cites `urls`.
+    async fn session_citing(state: &AppState, id: &str, report: &str, urls: &[&str]) {
+        let session = Session::new_from_task(id.to_string(), "reporter");
+        let findings = urls
+            .iter()
+            .map(|url| models::Finding {
+                title: url.to_string(),
+                url: url.to_string(),
+                content: String::new(),
+                score: 0.5,
+                query: None,
+                confidence: None,
+            })
+            .collect();
+        let context = ResearchContext {
+            research_results: vec![models::ResearchResult {
+                question: "What is Tokio?".to_string(),
+                findings,
+                unanswerable: false,
+            }],
+            report: report.to_string(),
+            ..Default::default()
+        };
+        set_research_context(&session.context, context).await;
+        (*state.storage).save(session).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn report_diff_splits_shared_and_unique_sources() {
+        let state = test_state();
+        let before = ["https://tokio.rs", "https://docs.rs/tokio"];
+        session_citing(&state, "before", "Tokio is a runtime.", &before).await;
+        let after = ["https://docs.rs/tokio", "https://lib.rs"];
+        session_citing(&state, "after", "Tokio is a widely used async runtime.", &after).await;
+
+        let req = ReportDiffRequest {
+            first: "before".to_string(),
+            second: "after".to_string(),
+            judge: false,
+        };
+        let Json(diff) = diff_reports(State(state.clone()), Json(req)).await.unwrap();
+        assert_eq!((diff.first_words, diff.second_words, diff.length_delta), (4, 6, 2));
+        assert_eq!(diff.shared_sources, ["https://docs.rs/tokio"]);
+        assert_eq!(diff.only_in_first, ["https://tokio.rs"]);
+        assert_eq!(diff.only_in_second, ["https://lib.rs"]);
+        assert!(diff.judgment.is_none());
+
+        let req = ReportDiffRequest {
+            first: "before".to_string(),
+            second: "missing".to_string(),
+            judge: false,
+        };
+        let missing = diff_reports(State(state), Json(req)).await.unwrap_err();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn panicking_handler_returns_a_json_500() {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route("/panic", get(|| async { panic!("unwrapped a None") }))
+            .layer(CatchPanicLayer::custom(panic_response));
+        let request = axum::http::Request::builder().uri("/panic").body(axum::body::Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = json_body(response).await;
+        assert_eq!(body["error"], "internal server error");
+        assert_eq!(body["kind"], "panic");
+        assert!(Uuid::parse_str(body["request_id"].as_str().unwrap()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_requests_show_up_on_the_errors_endpoint() {
+        let graph = graph_flow::GraphBuilder::new("errors")
+            .add_task(Arc::new(FailingTask(graph::START_TASK)))
+            .build();
+        let state = state_with_graph(graph);
+        for id in ["first", "second", "third"] {
+            let result = research(
+                State(state.clone()),
+                Query(ResearchQuery::default()),
+                HeaderMap::new(),
+                Json(request_with_id(id)),
+            )
+            .await;
+            assert!(result.is_err());
+        }
+
+        let query = ListQuery { limit: Some(2), ..Default::default() };
+        let Json(errors) = recent_errors(State(state), Query(query)).await;
+        let sessions: Vec<_> = errors.iter().map(|e| e.session_id.as_str()).collect();
+        assert_eq!(sessions, ["third", "second"]);
+        assert!(errors[0].message.contains("crashed"));
+    }
+
+    #[tokio::test]
+    async fn shedding_queue_rejects_research_and_fails_readiness() {
+        let release = Arc::new(tokio::sync::Semaphore::new(0));
+        let queue = {
+            let release = release.clone();
+            ResearchQueue::start(1, 10, move |_job| {
+                let release = release.clone();
+                async move {
+                    let _ = release.acquire().await;
+                }
+            })
+        }
+        .with_watermarks(1, 1);
+        let mut state = test_state();
+        state.queue = Some(Arc::new(queue));
+
+        // The worker holds one job; two more wait, above the high-water mark.
+        for id in ["held", "first", "second"] {
+            post_research(&state, request_with_id(id)).await;
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let shed = post_research(&state, request_with_id("shed")).await;
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let (status, Json(body)) = readiness(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["queue_depth"], 2);
+
+        release.close();
+        for _ in 0..100 {
+            if readiness(State(state.clone())).await.0 == StatusCode::OK {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let (status, Json(body)) = readiness(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(post_research(&state, request_with_id("accepted")).await.status(), StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_gzipped_when_the_client_accepts_it() {
+        use tower::ServiceExt;
+
+        let report = "Tokio is an asynchronous runtime for Rust. ".repeat(500);
+        let app = Router::new()
+            .route("/report", get(move || async move { Json(serde_json::json!({ "report": report })) }))
+            .route(
+                "/events",
+                get(|| async { ([(header::CONTENT_TYPE, "text/event-stream")], "data: progress\n\n".repeat(50)) }),
+            )
+            .layer(axum::middleware::from_fn(pretty_json))
+            .layer(CompressionLayer::new());
+        let get_with = |uri: &str, encoding: Option<&str>| {
+            let mut request = axum::http::Request::builder().uri(uri);
+            if let Some(encoding) = encoding {
+                request = request.header(header::ACCEPT_ENCODING, encoding);
+            }
+            app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap())
+        };
+        let encoding = |response: &Response| response.headers().get(header::CONTENT_ENCODING).cloned();
+
+        let plain = get_with("/report", None).await.unwrap();
+        assert_eq!(encoding(&plain), None);
+        let plain_len = axum::body::to_bytes(plain.into_body(), usize::MAX).await.unwrap().len();
+
+        let gzipped = get_with("/report", Some("gzip")).await.unwrap();
+        assert_eq!(encoding(&gzipped).unwrap(), "gzip");
+        let gzipped_len = axum::body::to_bytes(gzipped.into_body(), usize::MAX).await.unwrap().len();
+        assert!(gzipped_len * 10 < plain_len, "{gzipped_len} bytes gzipped vs {plain_len} plain");
+
+        let events = get_with("/events", Some("gzip")).await.unwrap();
+        assert_eq!(encoding(&events), None);
+    }
+
+    #[tokio::test]
+    async fn inter_task_delay_is_applied_between_tasks_and_reported() {
+        let graph = graph_flow::GraphBuilder::new("paced")
+            .add_task(Arc::new(FindingsTask))
+            .add_task(Arc::new(SleepingTask("summarizer", std::time::Duration::ZERO)))
+            .add_task(Arc::new(SleepingTask("reporter", std::time::Duration::ZERO)))
+            .add_edge("researcher", "summarizer")
+            .add_edge("summarizer", "reporter")
+            .build();
+        let state = AppState {
+            config: Arc::new(Config {
+                inter_task_delay_ms: Some(40),
+                ..Config::default()
+            }),
+            ..state_with_graph(graph)
+        };
+        (*state.storage).save(Session::new_from_task("paced".to_string(), "researcher")).await.unwrap();
+
+        let started = std::time::Instant::now();
+        let response = drive_workflow(&state, "paced", started, &CancellationToken::new()).await.unwrap();
+        // Three tasks, so two pauses: none before the first.
+        assert_eq!(response.injected_delay_ms, 80);
+        assert!(started.elapsed() >= std::time::Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    async fn report_sections_are_served_one_at_a_time() {
+        let state = test_state();
+        let report = "## Tokio\n\nThe default.\n\n## smol\n\nSmall.";
+        finished_response(&state, "paged", "The summary", report).await;
+        let section = |section| {
+            get_session(
+                State(state.clone()),
+                Path("paged".to_string()),
+                Query(SessionQuery {
+                    section: Some(section),
+                    ..Default::default()
+                }),
+            )
+        };
+
+        let page = json_body(section(1).await.unwrap()).await;
+        assert_eq!(page["title"], "smol");
+        assert_eq!(page["content"], "## smol\n\nSmall.");
+        assert_eq!(page["sections"], serde_json::json!(["Tokio", "smol"]));
+        assert_eq!(section(2).await.unwrap_err().into_response().status(), StatusCode::NOT_FOUND);
+
+        let whole = get_session(State(state.clone()), Path("paged".to_string()), Query(SessionQuery::default()));
+        assert_eq!(json_body(whole.await.unwrap()).await["report"], report);
+    }
+
+    #[tokio::test]
+    async fn include_returns_only_the_requested_artifacts() {
+        let state = test_state();
+        let session = Session::new_from_task("artifacts".to_string(), "reporter");
+        let context = ResearchContext {
+            topic: "Rust async runtimes".to_string(),
+            questions: vec!["What is Tokio?".to_string()],
+            summary: "Tokio leads.".to_string(),
+            research_results: vec![models::ResearchResult {
+                question: "What is Tokio?".to_string(),
+                findings: vec![],
+                unanswerable: true,
+            }],
+            ..Default::default()
+        };
+        set_research_context(&session.context, context).await;
+        (*state.storage).save(session).await.unwrap();
+        store_llm_calls(&state, "artifacts", "reporter", vec![("the prompt".to_string(), "the reply".to_string())]).await;
+        let fetch = |include: Option<&str>| {
+            let query = SessionQuery {
+                include: include.map(str::to_string),
+                ..Default::default()
+            };
+            get_session(State(state.clone()), Path("artifacts".to_string()), Query(query))
+        };
+
+        let subset = json_body(fetch(Some("questions,prompts")).await.unwrap()).await;
+        assert_eq!(subset["questions"], serde_json::json!(["What is Tokio?"]));
+        assert_eq!(subset["summary"], "");
+        assert!(subset.get("findings").is_none());
+        assert_eq!(subset["llm_calls"][0]["prompt"], "the prompt");
+        assert!(subset["llm_calls"][0].get("response").is_none());
+
+        let default = json_body(fetch(None).await.unwrap()).await;
+        assert_eq!(default["summary"], "Tokio leads.");
+        assert!(default.get("findings").is_none());
+        assert!(default.get("llm_calls").is_none());
+
+        let findings = json_body(fetch(Some("findings")).await.unwrap()).await;
+        assert_eq!(findings["findings"][0]["question"], "What is Tokio?");
+        assert!(findings["questions"].as_array().unwrap().is_empty());
+
+        let unknown = fetch(Some("questions,secrets")).await.unwrap_err();
+        assert_eq!(unknown.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn exported_sessions_import_unchanged_under_a_new_id() {
+        let state = test_state();
+        let session = Session::new_from_task("original".to_string(), "reporter");
+        let context = ResearchContext {
+            topic: "Rust async runtimes".to_string(),
+            questions: vec!["What is Tokio?".to_string()],
+            summary: "Tokio leads.".to_string(),
+            report: "# Rust async runtimes".to_string(),
+            ..Default::default()
+        };
+        set_research_context(&session.context, context).await;
+        context_keys::set(&session.context, TASK_TIMES, HashMap::from([("reporter".to_string(), 1200u64)])).await;
+        (*state.storage).save(session).await.unwrap();
+        store_llm_calls(&state, "original", "reporter", vec![("the prompt".to_string(), "the reply".to_string())]).await;
+
+        let Json(exported) = export_session(State(state.clone()), Path("original".to_string())).await.unwrap();
+        // Through JSON, as the bundle would travel between servers.
+        let bundle: SessionBundle = serde_json::from_value(serde_json::to_value(&exported).unwrap()).unwrap();
+        let Json(imported) = import_session(State(state.clone()), Json(bundle.clone())).await.unwrap();
+        assert_ne!(imported.session_id, "original");
+        assert_eq!(imported.report, "# Rust async runtimes");
+
+        let Json(reexported) = export_session(State(state.clone()), Path(imported.session_id.clone())).await.unwrap();
+        let without_id = |bundle: &SessionBundle| {
+            let mut value = serde_json::to_value(bundle).unwrap();
+            value.as_object_mut().unwrap().remove("session_id");
+            value
+        };
+        assert_eq!(without_id(&reexported), without_id(&exported));
+
+        let future_version = SessionBundle {
+            version: SESSION_BUNDLE_VERSION + 1,
+            ..bundle
+        };
+        let rejected = import_session(State(state), Json(future_version)).await.unwrap_err();
+        assert_eq!(rejected.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_listed_by_tag_and_run_group() {
+        let state = test_state();
+        for (id, tags, run_group) in [
+            ("a", vec!["baseline", "gpt"], Some("nightly")),
+            ("b", vec!["candidate"], Some("nightly")),
+            ("c", vec!["baseline"], None),
+        ] {
+            let session = Session::new_from_task(id.to_string(), graph::START_TASK);
+            let context = ResearchContext {
+                topic: "Rust async runtimes".to_string(),
+                tags: tags.into_iter().map(str::to_string).collect(),
+                run_group: run_group.map(str::to_string),
+                ..Default::default()
+            };
+            set_research_context(&session.context, context).await;
+            (*state.storage).save(session).await.unwrap();
+        }
+
+        let ids = |page: SessionPage| {
+            let mut ids: Vec<String> = page.sessions.into_iter().map(|s| s.session_id).collect();
+            ids.sort();
+            ids
+        };
+        let by_tag = ListQuery {
+            tag: Some("baseline".to_string()),
+            ..Default::default()
+        };
+        let Json(page) = list_sessions(State(state.clone()), Query(by_tag)).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(ids(page), ["a", "c"]);
+
+        let by_group = ListQuery {
+            run_group: Some("nightly".to_string()),
+            ..Default::default()
+        };
+        let Json(page) = list_sessions(State(state.clone()), Query(by_group)).await.unwrap();
+        assert_eq!(ids(page), ["a", "b"]);
+
+        let Json(page) = list_sessions(State(state), Query(ListQuery::default())).await.unwrap();
+        assert_eq!(page.total, 3);
+    }
+
+    #[tokio::test]
+    async fn too_many_tags_are_rejected() {
+        let req = ResearchRequest {
+            tags: (0..=MAX_TAGS).map(|i| format!("tag-{i}")).collect(),
+            ..request_with_id("tagged")
+        };
+        let error = prepare_research("tagged", req).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// A task whose one completion runs into its `max_tokens` cap.
+    struct CappedTask;
+
+    #[async_trait::async_trait]
+    impl Task for CappedTask {
+        fn id(&self) -> &str {
+            "reporter"
+        }
+
+        async fn run(&self, _context: graph_flow::Context) -> graph_flow::Result<graph_flow::TaskResult> {
+            tools::usage::record_capped();
+            Ok(graph_flow::TaskResult::new(None, graph_flow::NextAction::Continue))
+        }
+    }
+
+    #[tokio::test]
+    async fn capped_completions_flag_their_task() {
+        let graph = graph_flow::GraphBuilder::new("capped")
+            .add_task(Arc::new(FindingsTask))
+            .add_task(Arc::new(CappedTask))
+            .add_edge("researcher", "reporter")
+            .build();
+        let state = state_with_graph(graph);
+        (*state.storage).save(Session::new_from_task("capped".to_string(), "researcher")).await.unwrap();
+
+        let response = drive_workflow(&state, "capped", std::time::Instant::now(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(response.token_capped_tasks, ["reporter"]);
+    }
+
+    #[test]
+    fn tokens_compare_in_constant_time() {
+        assert!(constant_time_eq(b"s3cret", b"s3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3creT"));
+        assert!(!constant_time_eq(b"s3cret", b"s3cre"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
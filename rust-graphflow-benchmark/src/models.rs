@@ -1,42 +1,754 @@
+use crate::presets::{Depth, DEFAULT_FINDINGS_PER_QUESTION, DEFAULT_SEARCH_DEPTH};
+use crate::tools::llm::{task_max_tokens, Model};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Upper bound on the number of research questions, whether extracted or provided.
+pub const MAX_QUESTIONS: usize = 5;
+
+/// Most `output_languages` a request may ask for.
+pub const MAX_OUTPUT_LANGUAGES: usize = 5;
+
+/// Most `tags` a request may carry, and the longest a tag or `run_group` may be.
+pub const MAX_TAGS: usize = 10;
+pub const MAX_TAG_CHARS: usize = 64;
+
+/// Longest client-provided `session_id`.
+pub const MAX_SESSION_ID_CHARS: usize = 64;
+
+/// Upper bound on the combined size of seed documents in a request.
+pub const MAX_DOCUMENTS_BYTES: usize = 200_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct ResearchRequest {
     pub topic: String,
+    /// Id for the new session instead of a generated UUID: 1-64 ASCII
+    /// letters, digits, `-`, `_` or `.`. Rejected with 409 if a session with
+    /// this id already exists.
+    pub session_id: Option<String>,
+    /// Research questions to use as-is, skipping categorization and the
+    /// extraction step.
+    pub questions: Option<Vec<String>>,
+    /// Who the report is written for, e.g. "executive", "researcher" or "general".
+    pub audience: Option<String>,
+    /// Text documents researched alongside the web search results.
+    #[serde(default)]
+    pub documents: Vec<String>,
+    /// Markup of the generated report; markdown when omitted.
+    #[serde(default)]
+    pub report_format: ReportFormat,
+    /// Only search these domains, e.g. `["arxiv.org", "nature.com"]`.
+    #[serde(default)]
+    pub include_domains: Vec<String>,
+    /// Never return results from these domains.
+    #[serde(default)]
+    pub exclude_domains: Vec<String>,
+    /// URL that receives a POST with the task id and a context snapshot after
+    /// each task completes.
+    pub callback_url: Option<String>,
+    /// Preset for question count, findings per question, search depth and
+    /// model. Fields set explicitly in `config` take precedence.
+    pub depth: Option<Depth>,
+    /// Model and search settings for this run.
+    #[serde(default)]
+    pub config: WorkflowConfig,
+    /// Extra languages to write the report in, e.g. `["French", "Japanese"]`,
+    /// up to `MAX_OUTPUT_LANGUAGES` distinct ones. Research still runs once,
+    /// in the topic's language.
+    #[serde(default)]
+    pub output_languages: Vec<String>,
+    /// Free-form labels for slicing results, e.g. `["baseline", "gpt-4o"]`;
+    /// `GET /research?tag=` filters on them.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Experiment cohort this run belongs to; `GET /research?run_group=`
+    /// filters on it.
+    pub run_group: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Per-request knobs for the workflow. Unset fields use the server defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WorkflowConfig {
+    /// OpenAI model used for every LLM call, e.g. `gpt-4o`, or `custom:<name>`
+    /// for models not known to the server; `LLM_MODEL` or `gpt-4o-mini` when omitted.
+    #[schema(value_type = Option<String>)]
+    pub model: Option<Model>,
+    /// Tavily search depth, `basic` or `advanced`; `advanced` when omitted.
+    pub search_depth: Option<String>,
+    /// Most research questions to extract, up to 5.
+    pub max_questions: Option<usize>,
+    /// Most findings kept per question; 3 when omitted.
+    pub findings_per_question: Option<usize>,
+    /// Sampling temperature, 0.0-2.0; the provider default when omitted.
+    pub temperature: Option<f64>,
+    /// Nucleus sampling cutoff, above 0.0 and at most 1.0.
+    pub top_p: Option<f64>,
+    /// Completion length limit for each LLM call.
+    pub max_tokens: Option<u64>,
+    /// Model per task id, e.g. `{"researcher": "gpt-4o-mini", "summarizer":
+    /// "gpt-4o"}`, overriding `model` for those tasks only. Defaults come from
+    /// `TASK_MODELS`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[schema(value_type = HashMap<String, String>)]
+    pub task_models: HashMap<String, Model>,
+}
+
+impl WorkflowConfig {
+    /// The settings `task` runs with: this config, with `model` replaced by
+    /// the task's entry in `task_models` if there is one and `max_tokens`
+    /// lowered to the task's cap.
+    pub fn for_task(&self, task: &str) -> WorkflowConfig {
+        let task_cap = task_max_tokens(task);
+        WorkflowConfig {
+            model: self.task_models.get(task).cloned().or_else(|| self.model.clone()),
+            max_tokens: Some(self.max_tokens.map_or(task_cap, |requested| requested.min(task_cap))),
+            ..self.clone()
+        }
+    }
+
+    pub fn search_depth(&self) -> &str {
+        self.search_depth.as_deref().unwrap_or(DEFAULT_SEARCH_DEPTH)
+    }
+
+    pub fn max_questions(&self) -> usize {
+        self.max_questions.unwrap_or(MAX_QUESTIONS)
+    }
+
+    pub fn findings_per_question(&self) -> usize {
+        self.findings_per_question.unwrap_or(DEFAULT_FINDINGS_PER_QUESTION)
+    }
+
+    /// Checks the sampling parameters against the ranges the API accepts.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+            return Err(format!("temperature {:?} is outside 0.0-2.0", self.temperature));
+        }
+        if self.top_p.is_some_and(|p| !(p > 0.0 && p <= 1.0)) {
+            return Err(format!("top_p {:?} is outside (0.0, 1.0]", self.top_p));
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ApprovalRequest {
+    /// Replacement summary for the reporter to use instead of the generated one.
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FollowupRequest {
+    pub question: String,
+}
+
+/// A question asked after the report, answered from the session's findings.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Followup {
+    pub question: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct QuestionsRequest {
+    pub topic: String,
+    pub depth: Option<Depth>,
+    #[serde(default)]
+    pub config: WorkflowConfig,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuestionsResponse {
+    pub topic: String,
+    pub questions: Vec<String>,
+    pub extraction_attempts: u32,
+    pub total_time_ms: u64,
+}
+
+/// Parameters for re-running only the reporter on a completed session.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct RegenerateReportRequest {
+    /// Keeps the session's current format when omitted.
+    pub report_format: Option<ReportFormat>,
+    /// Keeps the session's current audience when omitted.
+    pub audience: Option<String>,
+    pub max_words: Option<usize>,
+    /// Make the new report the session's main `report`; otherwise it is only
+    /// added to `report_versions`.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// One generated report. Version 1 is the report from the original run.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReportVersion {
+    pub version: usize,
+    pub report: String,
+    pub report_format: ReportFormat,
+    pub audience: Option<String>,
+    pub max_words: Option<usize>,
+}
+
+/// Re-runs a stored session's topic with a different model.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ReplayRequest {
+    /// Model for every task of the replay, replacing the original session's
+    /// per-task models as well; the original models when omitted.
+    #[schema(value_type = Option<String>)]
+    pub model: Option<Model>,
+    /// Per-task models for the replay, applied on top of `model`.
+    #[serde(default)]
+    #[schema(value_type = HashMap<String, String>)]
+    pub task_models: HashMap<String, Model>,
+    /// Research the original extracted questions instead of extracting new
+    /// ones. Defaults to `true`.
+    pub reuse_questions: Option<bool>,
+    /// Skip the researcher and reuse the original findings, so only the LLM
+    /// steps differ. Implies `reuse_questions`.
+    #[serde(default)]
+    pub reuse_findings: bool,
+}
+
+/// Bumped whenever `SessionBundle` changes incompatibly.
+pub const SESSION_BUNDLE_VERSION: u32 = 1;
+
+/// A whole session as one self-contained document, served by
+/// `GET /research/{id}/export` and accepted by `POST /research/import`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SessionBundle {
+    /// `SESSION_BUNDLE_VERSION` at export time; imports reject other versions.
+    pub version: u32,
+    /// The session the bundle was exported from.
+    pub session_id: String,
+    /// The task the session would run next.
+    pub current_task: String,
+    /// Topic, config, questions, findings, summary and reports.
+    #[schema(value_type = Object)]
+    pub context: ResearchContext,
+    #[serde(default)]
+    pub task_times: HashMap<String, u64>,
+    #[serde(default)]
+    pub ttft_ms: HashMap<String, u64>,
+    #[serde(default)]
+    pub subcall_times: HashMap<String, BTreeMap<String, u64>>,
+    #[serde(default)]
+    pub llm_calls: Vec<LlmCall>,
+}
+
+/// Returned with 202 when a request is queued via `Prefer: respond-async`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QueuedResponse {
+    pub session_id: String,
+    /// 1-based position among requests waiting for a worker.
+    pub queue_position: usize,
+}
+
+/// Body POSTed to a request's `callback_url` after each task.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskCallback<'a> {
+    pub session_id: &'a str,
+    pub task_id: &'a str,
+    pub context: &'a ResearchContext,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CompareRequest {
+    pub topic: String,
+    /// Exactly two configurations to run side by side.
+    pub variants: Vec<WorkflowConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CompareResponse {
+    pub topic: String,
+    pub results: Vec<VariantResult>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VariantResult {
+    pub config: WorkflowConfig,
+    pub response: ResearchResponse,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub config: WorkflowConfig,
+    /// Cancel the remaining topics as soon as one topic fails, instead of
+    /// running them all. Cancelled topics report `cancelled: batch fail_fast`.
+    #[serde(default)]
+    pub fail_fast: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BatchResponse {
+    /// One entry per topic, in request order.
+    pub results: Vec<BatchResult>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BatchResult {
+    pub topic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<ResearchResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ReportDiffRequest {
+    pub first: String,
+    pub second: String,
+    /// Also ask the LLM which report is better. Off by default because it
+    /// sends both reports to the model.
+    #[serde(default)]
+    pub judge: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReportDiffResponse {
+    pub first: String,
+    pub second: String,
+    pub first_words: usize,
+    pub second_words: usize,
+    /// `second_words - first_words`.
+    pub length_delta: i64,
+    pub shared_sources: Vec<String>,
+    pub only_in_first: Vec<String>,
+    pub only_in_second: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judgment: Option<String>,
+}
+
+/// Token counts estimated from prompt and completion length (about four
+/// characters per token), since the completion API does not report usage.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Html,
+    Plain,
+}
+
+impl ReportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "text/markdown; charset=utf-8",
+            ReportFormat::Html => "text/html; charset=utf-8",
+            ReportFormat::Plain => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The complete `ResearchResponse` as JSON.
+    #[default]
+    Full,
+    /// Only the report, served with the content type of its `ReportFormat`.
+    Report,
+    /// Only the prose summary, served as `text/plain`.
+    Summary,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResearchQuery {
+    pub format: Option<OutputFormat>,
+    /// Comma-separated `Artifact`s to return instead of the default set.
+    pub include: Option<String>,
+}
+
+/// Intermediate results a client can ask for with `?include=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    Questions,
+    Findings,
+    Summary,
+    RawResponses,
+    Prompts,
+}
+
+impl Artifact {
+    /// Parses a comma-separated `include` list, rejecting unknown names.
+    pub fn parse_list(include: &str) -> Result<Vec<Artifact>, String> {
+        include
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| match name {
+                "questions" => Ok(Artifact::Questions),
+                "findings" => Ok(Artifact::Findings),
+                "summary" => Ok(Artifact::Summary),
+                "raw_responses" => Ok(Artifact::RawResponses),
+                "prompts" => Ok(Artifact::Prompts),
+                other => Err(format!("unknown artifact {:?}", other)),
+            })
+            .collect()
+    }
+}
+
+/// One LLM call made while running a task, captured under `CAPTURE_LLM_CALLS`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LlmCall {
+    pub task: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Only sessions carrying this tag.
+    pub tag: Option<String>,
+    /// Only sessions in this run group.
+    pub run_group: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SessionQuery {
+    /// Zero-based report section to return instead of the full session.
+    pub section: Option<usize>,
+    /// Comma-separated `Artifact`s to return instead of the default set.
+    pub include: Option<String>,
+}
+
+/// One top-level section of a markdown report, served by
+/// `GET /research/{id}?section=`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReportSectionPage {
+    pub session_id: String,
+    pub section: usize,
+    pub title: String,
+    pub content: String,
+    /// Titles of every section, in order; an untitled leading section is "".
+    pub sections: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+}
+
+/// Estimated tokens and USD cost for one task, or for the whole run.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+pub struct TaskCost {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CostBreakdown {
+    pub tasks: HashMap<String, TaskCost>,
+    /// Sum of the `tasks` entries.
+    pub total: TaskCost,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ResearchResponse {
     pub session_id: String,
+    /// The session this run replayed, for runs started via `/research/{id}/replay`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replayed_from: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_group: Option<String>,
+    /// Why the run stopped without a report, e.g. `failed: llm_unavailable`
+    /// for a queued run or `cancelled: client disconnected`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
     pub topic: String,
+    pub depth: Option<Depth>,
+    /// Settings the run actually used, after applying `depth` and defaults.
+    pub config: WorkflowConfig,
+    pub category: String,
+    pub detected_language: String,
     pub questions: Vec<String>,
+    pub extraction_skipped: bool,
+    pub extraction_attempts: u32,
+    pub research_iterations: u32,
+    pub question_errors: Vec<QuestionError>,
+    /// Questions left unresearched because of `MAX_RESEARCHED_QUESTIONS`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_questions: Vec<String>,
+    /// Questions whose findings `RELEVANCE_GATE` judged off-topic and left out
+    /// of the summary and report.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub irrelevant_questions: Vec<String>,
+    /// Why web search could not be used, e.g. an exhausted Tavily quota.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_unavailable: Option<String>,
     pub summary: String,
+    /// The model's own 0-10 rating of the summary, when `SELF_SCORE` is set.
+    pub summary_score: Option<f32>,
+    pub key_points: Vec<String>,
+    pub unsupported_claims: Vec<String>,
+    /// Claim-to-source mapping from the summarizer, when `REQUIRE_GROUNDING` is set.
+    pub grounding: Vec<GroundedClaim>,
+    pub contradictions: Vec<Contradiction>,
+    pub audience: Option<String>,
     pub report: String,
+    pub report_format: ReportFormat,
+    /// The report in each of the request's `output_languages`.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub reports: HashMap<String, String>,
+    pub structured_report: Option<Report>,
+    pub report_truncated_input: bool,
+    /// The first report came back shorter than `MIN_REPORT_CHARS` and was
+    /// regenerated with a stricter prompt.
+    pub report_retried: bool,
+    pub report_versions: Vec<ReportVersion>,
+    /// The workflow is paused before the reporter until `POST /research/{id}/approve`.
+    pub awaiting_approval: bool,
+    pub followups: Vec<Followup>,
     pub total_time_ms: u64,
     pub task_times: HashMap<String, u64>,
+    /// Time-to-first-token per task, for tasks whose main LLM call was streamed.
+    pub ttft_ms: HashMap<String, u64>,
+    /// The model each LLM-backed task used.
+    pub stage_models: BTreeMap<String, String>,
+    /// Milliseconds per sub-call within each task, e.g. `"<question> / search"`
+    /// for the researcher, when `DETAILED_TIMINGS` is set.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub subcall_times: HashMap<String, BTreeMap<String, u64>>,
+    pub token_usage: TokenUsage,
+    /// Tasks with at least one completion cut off at its `max_tokens` cap.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub token_capped_tasks: Vec<String>,
+    /// Per-task token usage and cost, when `COST_BREAKDOWN` is enabled and the
+    /// model's price is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_breakdown: Option<CostBreakdown>,
+    /// Retries left from `REQUEST_RETRY_BUDGET`, when one is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_budget_remaining: Option<u32>,
+    /// Time spent in `INTER_TASK_DELAY_MS` pauses, included in `total_time_ms`.
+    pub injected_delay_ms: u64,
+    /// Per-question findings, only with `?include=findings`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub findings: Option<Vec<ResearchResult>>,
+    /// Captured LLM calls, only with `?include=prompts` or `?include=raw_responses`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub llm_calls: Option<Vec<LlmCall>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResearchContext {
     pub topic: String,
+    pub replayed_from: Option<String>,
+    pub tags: Vec<String>,
+    pub run_group: Option<String>,
+    pub findings_reused: bool,
+    pub callback_url: Option<String>,
+    pub depth: Option<Depth>,
+    pub config: WorkflowConfig,
+    pub category: String,
+    pub detected_language: String,
     pub questions: Vec<String>,
+    pub extraction_skipped: bool,
+    pub extraction_attempts: u32,
+    pub research_attempts: u32,
+    /// Completed research and summary passes, more than one when
+    /// `MAX_RESEARCH_ITERATIONS` lets the summarizer ask follow-up questions.
+    pub research_iterations: u32,
+    /// Follow-up questions for the researcher's next pass.
+    pub next_questions: Vec<String>,
+    pub documents: Vec<String>,
+    pub include_domains: Vec<String>,
+    pub exclude_domains: Vec<String>,
     pub research_results: Vec<ResearchResult>,
+    pub question_errors: Vec<QuestionError>,
+    pub skipped_questions: Vec<String>,
+    pub irrelevant_questions: Vec<String>,
+    pub search_unavailable: Option<String>,
+    /// Untrimmed reporter input, formatted while the summarizer runs.
+    pub prepared_research_data: Option<String>,
     pub summary: String,
+    pub summary_score: Option<f32>,
+    pub key_points: Vec<String>,
+    pub unsupported_claims: Vec<String>,
+    pub grounding: Vec<GroundedClaim>,
+    pub contradictions: Vec<Contradiction>,
+    pub audience: Option<String>,
+    pub output_languages: Vec<String>,
     pub report: String,
+    pub report_format: ReportFormat,
+    pub reports: HashMap<String, String>,
+    pub structured_report: Option<Report>,
+    pub report_truncated_input: bool,
+    pub report_retried: bool,
+    pub report_max_words: Option<usize>,
+    pub report_versions: Vec<ReportVersion>,
+    pub awaiting_approval: bool,
+    pub approved: bool,
+    pub followups: Vec<Followup>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ResearchContext {
+    /// Distinct finding URLs across all questions, in first-seen order.
+    pub fn sources(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.research_results
+            .iter()
+            .flat_map(|result| &result.findings)
+            .filter(|finding| seen.insert(finding.url.as_str()))
+            .map(|finding| finding.url.clone())
+            .collect()
+    }
+
+    /// Renders the research results as plain text for inclusion in prompts.
+    pub fn findings_text(&self) -> String {
+        self.findings_text_limited(None)
+    }
+
+    /// Like `findings_text`, but keeps only the `max_per_question` highest-scoring
+    /// findings of each question when set.
+    pub fn findings_text_limited(&self, max_per_question: Option<usize>) -> String {
+        self.research_results
+            .iter()
+            .map(|result| {
+                format!(
+                    "Question: {}\nFindings:\n{}",
+                    result.question,
+                    result
+                        .top_findings(max_per_question)
+                        .iter()
+                        .map(|f| format!("- {} ({}){}: {}", f.title, f.url, f.confidence_note(), f.content))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+// `Report`, `ReportSection`, `ExtractedQuestions` and the summarizer's
+// `Summary` are the schemas for `STRUCTURED_OUTPUT` function calling.
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, JsonSchema)]
+pub struct Report {
+    pub executive_summary: String,
+    pub sections: Vec<ReportSection>,
+    pub conclusion: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, JsonSchema)]
+pub struct ReportSection {
+    pub question: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExtractedQuestions {
+    pub questions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ResearchResult {
     pub question: String,
     pub findings: Vec<Finding>,
+    /// Set under `NO_FINDINGS_POLICY=mark` when the question found nothing,
+    /// so the report says so instead of guessing.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unanswerable: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ResearchResult {
+    /// The `limit` highest-scoring findings, best first, or all of them in
+    /// their original order when `limit` is `None`. A model-assigned
+    /// confidence ranks ahead of the Tavily score.
+    pub fn top_findings(&self, limit: Option<usize>) -> Vec<&Finding> {
+        let mut findings: Vec<&Finding> = self.findings.iter().collect();
+        if let Some(limit) = limit {
+            let rank = |f: &Finding| f.confidence.unwrap_or(f.score);
+            findings.sort_by(|a, b| rank(b).total_cmp(&rank(a)));
+            findings.truncate(limit);
+        }
+        findings
+    }
+}
+
+/// Findings that disagree, found when `DETECT_CONTRADICTIONS` is set.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Contradiction {
+    /// What the sources disagree about.
+    pub description: String,
+    pub claims: Vec<SourcedClaim>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SourcedClaim {
+    pub claim: String,
+    pub url: String,
+}
+
+/// A summary claim with the question and source URLs it was drawn from,
+/// produced under `REQUIRE_GROUNDING`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, JsonSchema)]
+pub struct GroundedClaim {
+    pub claim: String,
+    pub question: String,
+    pub urls: Vec<String>,
+    /// Whether every cited URL is one of the run's findings. Filled in after
+    /// parsing, not by the model.
+    #[serde(default)]
+    #[schemars(skip)]
+    pub grounded: bool,
+}
+
+/// A research question whose search failed or timed out.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QuestionError {
+    pub question: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Finding {
     pub title: String,
     pub url: String,
     pub content: String,
+    /// Tavily relevance score, or 0.0 when the model did not echo one back.
+    #[serde(default)]
+    pub score: f64,
+    /// The search query that returned this result, when it came from Tavily.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Model-assigned 0-1 rating of how well this finding answers its
+    /// question, set when `SCORE_FINDINGS` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+}
+
+impl Finding {
+    /// A ` [confidence 0.85]` suffix for prompts, empty when unscored.
+    pub fn confidence_note(&self) -> String {
+        self.confidence
+            .map(|confidence| format!(" [confidence {:.2}]", confidence))
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +757,10 @@ pub struct TavilySearchRequest {
     pub max_results: i32,
     pub search_depth: String,
     pub include_raw_content: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_domains: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_domains: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,4 +774,48 @@ pub struct TavilyResult {
     pub url: String,
     pub content: String,
     pub score: f64,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(title: &str, score: f64, confidence: Option<f64>) -> Finding {
+        Finding {
+            title: title.to_string(),
+            url: format!("https://example.com/{}", title),
+            content: format!("{} content", title),
+            score,
+            query: None,
+            confidence,
+        }
+    }
+
+    #[test]
+    fn only_the_top_findings_per_question_reach_the_summarizer() {
+        let context = ResearchContext {
+            research_results: vec![
+                ResearchResult {
+                    question: "What is Tokio?".to_string(),
+                    findings: vec![finding("weak", 0.2, None), finding("strong", 0.9, None), finding("mid", 0.5, None)],
+                    unanswerable: false,
+                },
+                ResearchResult {
+                    question: "Who uses Tokio?".to_string(),
+                    // A model-assigned confidence outranks the search score.
+                    findings: vec![finding("scored", 0.95, Some(0.1)), finding("trusted", 0.3, Some(0.8))],
+                    unanswerable: false,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let limited = context.findings_text_limited(Some(1));
+        assert!(limited.contains("strong content") && limited.contains("trusted content"));
+        assert!(!["weak", "mid", "scored"].iter().any(|title| limited.contains(&format!("{} content", title))));
+
+        let everything = context.findings_text();
+        assert_eq!(everything.matches(" content").count(), 5);
+        assert!(everything.find("weak content") < everything.find("strong content"));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::models::{
+    ApprovalRequest, BatchRequest, BatchResponse, BatchResult, CompareRequest, CompareResponse, Contradiction,
+    CostBreakdown, Finding, Followup, FollowupRequest, GroundedClaim, LlmCall, OutputFormat, QuestionError,
+    QuestionsRequest, QuestionsResponse, QueuedResponse, RegenerateReportRequest, ReplayRequest, Report,
+    ReportDiffRequest, ReportDiffResponse, ReportFormat, ReportSection, ReportSectionPage, ReportVersion,
+    ResearchRequest, ResearchResponse, ResearchResult, SessionBundle, SourcedClaim, TaskCost, TokenUsage, VariantResult,
+    WorkflowConfig,
+};
+use crate::presets::Depth;
+use crate::storage::{SessionPage, SessionSummary};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Rust GraphFlow research benchmark"),
+    paths(
+        crate::health,
+        crate::research,
+        crate::list_sessions,
+        crate::get_session,
+        crate::approve,
+        crate::followup,
+        crate::followup_stream,
+        crate::questions,
+        crate::regenerate_report,
+        crate::replay,
+        crate::export_session,
+        crate::import_session,
+        crate::compare,
+        crate::batch,
+        crate::diff_reports
+    ),
+    components(schemas(
+        ResearchRequest,
+        ResearchResponse,
+        QuestionError,
+        Contradiction,
+        SourcedClaim,
+        GroundedClaim,
+        LlmCall,
+        ResearchResult,
+        Finding,
+        OutputFormat,
+        Report,
+        ReportFormat,
+        ReportSection,
+        ApprovalRequest,
+        FollowupRequest,
+        QuestionsRequest,
+        QuestionsResponse,
+        QueuedResponse,
+        RegenerateReportRequest,
+        ReportVersion,
+        ReplayRequest,
+        SessionBundle,
+        Followup,
+        Depth,
+        WorkflowConfig,
+        TokenUsage,
+        CostBreakdown,
+        TaskCost,
+        CompareRequest,
+        CompareResponse,
+        VariantResult,
+        BatchRequest,
+        BatchResponse,
+        BatchResult,
+        ReportDiffRequest,
+        ReportDiffResponse,
+        ReportSectionPage,
+        SessionPage,
+        SessionSummary
+    ))
+)]
+struct ApiDoc;
+
+pub fn spec() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_lists_the_research_endpoints() {
+        let spec = spec();
+        for path in ["/health", "/research", "/research/{id}", "/research/{id}/approve", "/benchmark/batch"] {
+            assert!(spec.paths.paths.contains_key(path), "missing {}", path);
+        }
+        assert!(spec.paths.paths["/research"].operations.len() >= 2);
+        let schemas = spec.components.unwrap().schemas;
+        assert!(schemas.contains_key("ResearchRequest"));
+        assert!(schemas.contains_key("ResearchResponse"));
+    }
+}
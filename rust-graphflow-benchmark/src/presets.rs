@@ -0,0 +1,113 @@
+use crate::models::{WorkflowConfig, MAX_QUESTIONS};
+use crate::tools::llm::{task_models, Model, DEFAULT_MODEL};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Findings kept per question when neither the request nor a preset says otherwise.
+pub const DEFAULT_FINDINGS_PER_QUESTION: usize = 3;
+
+pub const DEFAULT_SEARCH_DEPTH: &str = "advanced";
+
+/// Named bundles of workflow settings, so callers can pick a depth instead of
+/// tuning each knob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Depth {
+    /// 2 questions, 2 findings each, basic search, `gpt-4o-mini`.
+    Quick,
+    /// 4 questions, 3 findings each, advanced search, `gpt-4o-mini`.
+    Standard,
+    /// 5 questions, 5 findings each, advanced search, `gpt-4o`.
+    Deep,
+}
+
+impl Depth {
+    pub fn preset(self) -> WorkflowConfig {
+        let (max_questions, findings_per_question, search_depth, model) = match self {
+            Depth::Quick => (2, 2, "basic", Model::Gpt4oMini),
+            Depth::Standard => (4, 3, "advanced", Model::Gpt4oMini),
+            Depth::Deep => (5, 5, "advanced", Model::Gpt4o),
+        };
+        WorkflowConfig {
+            model: Some(model),
+            search_depth: Some(search_depth.to_string()),
+            max_questions: Some(max_questions),
+            findings_per_question: Some(findings_per_question),
+            ..Default::default()
+        }
+    }
+}
+
+/// Fills the fields `config` leaves unset from the `depth` preset, if any, and
+/// then from the server defaults, so the result records every parameter the
+/// run actually used.
+pub fn resolve(config: &WorkflowConfig, depth: Option<Depth>) -> WorkflowConfig {
+    let preset = depth.map(Depth::preset).unwrap_or_default();
+    WorkflowConfig {
+        model: config
+            .model
+            .clone()
+            .or(preset.model)
+            .or_else(|| Some(DEFAULT_MODEL.clone())),
+        search_depth: config
+            .search_depth
+            .clone()
+            .or(preset.search_depth)
+            .or_else(|| Some(DEFAULT_SEARCH_DEPTH.to_string())),
+        max_questions: config
+            .max_questions
+            .or(preset.max_questions)
+            .map(|n| n.clamp(1, MAX_QUESTIONS))
+            .or(Some(MAX_QUESTIONS)),
+        findings_per_question: config
+            .findings_per_question
+            .or(preset.findings_per_question)
+            .or(Some(DEFAULT_FINDINGS_PER_QUESTION)),
+        temperature: config.temperature,
+        top_p: config.top_p,
+        max_tokens: config.max_tokens,
+        task_models: task_models()
+            .iter()
+            .chain(&config.task_models)
+            .map(|(task, model)| (task.clone(), model.clone()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_match_their_documented_parameters() {
+        let documented = [
+            (Depth::Quick, 2, 2, "basic", Model::Gpt4oMini),
+            (Depth::Standard, 4, 3, "advanced", Model::Gpt4oMini),
+            (Depth::Deep, 5, 5, "advanced", Model::Gpt4o),
+        ];
+        for (depth, questions, findings, search_depth, model) in documented {
+            let preset = depth.preset();
+            assert_eq!(preset.max_questions, Some(questions), "{:?}", depth);
+            assert_eq!(preset.findings_per_question, Some(findings), "{:?}", depth);
+            assert_eq!(preset.search_depth.as_deref(), Some(search_depth), "{:?}", depth);
+            assert_eq!(preset.model, Some(model), "{:?}", depth);
+        }
+    }
+
+    #[test]
+    fn explicit_settings_override_the_preset() {
+        let config = WorkflowConfig {
+            max_questions: Some(3),
+            ..Default::default()
+        };
+        let resolved = resolve(&config, Some(Depth::Deep));
+        assert_eq!(resolved.max_questions, Some(3));
+        assert_eq!(resolved.findings_per_question, Some(5));
+        assert_eq!(resolved.model, Some(Model::Gpt4o));
+
+        let defaults = resolve(&WorkflowConfig::default(), None);
+        assert_eq!(defaults.search_depth.as_deref(), Some(DEFAULT_SEARCH_DEPTH));
+        assert_eq!(defaults.findings_per_question, Some(DEFAULT_FINDINGS_PER_QUESTION));
+        assert_eq!(defaults.max_questions, Some(MAX_QUESTIONS));
+    }
+}
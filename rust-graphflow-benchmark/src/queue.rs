@@ -0,0 +1,223 @@
+use crate::models::ResearchContext;
+use crate::sampling;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
+
+/// A validated research request accepted on the async path, waiting for a
+/// worker.
+pub struct QueuedResearch {
+    pub session_id: String,
+    pub context: ResearchContext,
+    pub idempotency_key: Option<String>,
+}
+
+/// Bounded FIFO queue drained by a fixed pool of workers, so bursts wait for
+/// a slot instead of all hitting the providers at once.
+pub struct ResearchQueue {
+    /// Each job travels with its request's sampling decision, since workers
+    /// run outside the request.
+    sender: mpsc::Sender<(Option<bool>, QueuedResearch)>,
+    depth: Arc<AtomicUsize>,
+    /// `(high, low)` queue depths for load shedding, if enabled.
+    watermarks: Option<(usize, usize)>,
+    shedding: AtomicBool,
+}
+
+impl ResearchQueue {
+    /// Starts `workers` tasks that each take the oldest queued job and pass it
+    /// to `handler`, holding at most `capacity` jobs that have not started yet.
+    pub fn start<F, Fut>(workers: usize, capacity: usize, handler: F) -> Self
+    where
+        F: Fn(QueuedResearch) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let depth = Arc::new(AtomicUsize::new(0));
+        let handler = Arc::new(handler);
+
+        for worker in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            let depth = depth.clone();
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                loop {
+                    let job = receiver.lock().await.recv().await;
+                    let Some((sampled, job)) = job else { break };
+                    depth.fetch_sub(1, Ordering::Relaxed);
+                    sampling::within(sampled, async {
+                        info!(worker, session_id = %job.session_id, "Worker picked up queued research");
+                        handler(job).await;
+                    })
+                    .await;
+                }
+            });
+        }
+
+        info!(workers, capacity, "Started research queue");
+        Self {
+            sender,
+            depth,
+            watermarks: None,
+            shedding: AtomicBool::new(false),
+        }
+    }
+
+    /// Sheds load once more than `high` jobs are waiting and keeps doing so
+    /// until fewer than `low` are, so the server does not flap around a
+    /// single threshold.
+    pub fn with_watermarks(mut self, high: usize, low: usize) -> Self {
+        self.watermarks = Some((high, low.min(high)));
+        self
+    }
+
+    /// Whether new research requests should be turned away right now.
+    pub fn is_shedding(&self) -> bool {
+        let Some((high, low)) = self.watermarks else {
+            return false;
+        };
+        let depth = self.depth();
+        if depth > high {
+            if !self.shedding.swap(true, Ordering::Relaxed) {
+                warn!(depth, high, "Queue above high-water mark, shedding load");
+            }
+        } else if depth < low && self.shedding.swap(false, Ordering::Relaxed) {
+            info!(depth, low, "Queue below low-water mark, accepting requests again");
+        }
+        self.shedding.load(Ordering::Relaxed)
+    }
+
+    /// Queues `job`, returning its 1-based position, or hands it back when the
+    /// queue is full.
+    pub fn try_enqueue(&self, job: QueuedResearch) -> Result<usize, QueuedResearch> {
+        let position = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.sender.try_send((sampling::current(), job)) {
+            Ok(()) => Ok(position),
+            Err(e) => {
+                self.depth.fetch_sub(1, Ordering::Relaxed);
+                Err(e.into_inner().1)
+            }
+        }
+    }
+
+    /// Jobs waiting for a worker.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::{Notify, Semaphore};
+
+    fn job(session_id: &str) -> QueuedResearch {
+        QueuedResearch {
+            session_id: session_id.to_string(),
+            context: ResearchContext::default(),
+            idempotency_key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn jobs_run_in_arrival_order() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let queue = ResearchQueue::start(1, 10, move |job| {
+            let recorded = recorded.clone();
+            async move { recorded.lock().unwrap().push(job.session_id) }
+        });
+
+        for id in ["a", "b", "c", "d"] {
+            assert!(queue.try_enqueue(job(id)).is_ok());
+        }
+        for _ in 0..100 {
+            if seen.lock().unwrap().len() == 4 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(*seen.lock().unwrap(), vec!["a", "b", "c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn full_queue_hands_the_job_back() {
+        let started = Arc::new(Notify::new());
+        let release = Arc::new(Semaphore::new(0));
+        let queue = {
+            let (started, release) = (started.clone(), release.clone());
+            ResearchQueue::start(1, 2, move |_job| {
+                let (started, release) = (started.clone(), release.clone());
+                async move {
+                    started.notify_one();
+                    let _ = release.acquire().await;
+                }
+            })
+        };
+
+        // The only worker is busy with the first job, so two more fill the
+        // queue and a fourth is rejected.
+        queue.try_enqueue(job("running")).ok().unwrap();
+        started.notified().await;
+        assert_eq!(queue.try_enqueue(job("first")).ok(), Some(1));
+        assert_eq!(queue.try_enqueue(job("second")).ok(), Some(2));
+        let rejected = queue.try_enqueue(job("third")).err().unwrap();
+        assert_eq!(rejected.session_id, "third");
+        assert_eq!(queue.depth(), 2);
+
+        release.close();
+    }
+
+    async fn wait_for_depth(queue: &ResearchQueue, depth: usize) {
+        for _ in 0..200 {
+            if queue.depth() == depth {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("queue depth stayed at {} instead of {}", queue.depth(), depth);
+    }
+
+    #[tokio::test]
+    async fn shedding_starts_above_high_water_and_stops_below_low_water() {
+        let started = Arc::new(Notify::new());
+        let release = Arc::new(Semaphore::new(0));
+        let queue = {
+            let (started, release) = (started.clone(), release.clone());
+            ResearchQueue::start(1, 10, move |_job| {
+                let (started, release) = (started.clone(), release.clone());
+                async move {
+                    started.notify_one();
+                    // One permit per job, so each release lets exactly one finish.
+                    if let Ok(permit) = release.acquire().await {
+                        permit.forget();
+                    }
+                }
+            })
+        }
+        .with_watermarks(2, 1);
+
+        queue.try_enqueue(job("running")).ok().unwrap();
+        started.notified().await;
+        for id in ["a", "b"] {
+            queue.try_enqueue(job(id)).ok().unwrap();
+        }
+        assert!(!queue.is_shedding(), "at the high-water mark is not above it");
+        queue.try_enqueue(job("c")).ok().unwrap();
+        assert!(queue.is_shedding());
+
+        // Draining to the low-water mark is not enough to recover.
+        release.add_permits(2);
+        wait_for_depth(&queue, 1).await;
+        assert!(queue.is_shedding());
+
+        release.add_permits(1);
+        wait_for_depth(&queue, 0).await;
+        assert!(!queue.is_shedding());
+        release.close();
+    }
+}
@@ -0,0 +1,113 @@
+use crate::config::settings;
+use rand::Rng;
+use std::future::Future;
+use tokio::task::JoinHandle;
+use tracing::{Level, Metadata};
+use tracing_subscriber::filter::{filter_fn, FilterFn};
+
+tokio::task_local! {
+    static SAMPLED: bool;
+}
+
+/// Decides once per request whether it is traced, keeping the fraction
+/// `TRACE_SAMPLE_RATE` (0.0-1.0) of requests; unset means every request.
+/// Stats and usage counters are recorded either way; only log output is
+/// dropped.
+pub async fn sample_request(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let sampled = is_sampled(settings().trace_sample_rate, &mut rand::thread_rng());
+    SAMPLED.scope(sampled, next.run(request)).await
+}
+
+fn is_sampled<R: Rng>(rate: Option<f64>, rng: &mut R) -> bool {
+    match rate {
+        Some(rate) => rng.gen::<f64>() < rate,
+        None => true,
+    }
+}
+
+/// The current request's sampling decision, or `None` outside a request.
+pub fn current() -> Option<bool> {
+    SAMPLED.try_with(|sampled| *sampled).ok()
+}
+
+/// Runs `future` under a decision taken earlier with `current`, for work that
+/// outlives the request's scope, such as a queued job.
+pub async fn within<F: Future>(sampled: Option<bool>, future: F) -> F::Output {
+    match sampled {
+        Some(sampled) => SAMPLED.scope(sampled, future).await,
+        None => future.await,
+    }
+}
+
+/// `tokio::spawn` that carries the current request's decision into the new
+/// task, where the task-local would otherwise be unset and every line logged.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(within(current(), future))
+}
+
+/// `tokio::task::spawn_blocking` that carries the current request's decision.
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let sampled = current();
+    tokio::task::spawn_blocking(move || match sampled {
+        Some(sampled) => SAMPLED.sync_scope(sampled, f),
+        None => f(),
+    })
+}
+
+/// Global filter that hides spans and events of unsampled requests. Warnings
+/// and errors always pass, as does anything outside a request, such as
+/// startup and session cleanup. Work a request spawns or queues keeps the
+/// request's decision when started through `spawn`, `spawn_blocking` or the
+/// research queue.
+pub fn filter() -> FilterFn<impl Fn(&Metadata<'_>) -> bool> {
+    filter_fn(|metadata| {
+        *metadata.level() <= Level::WARN || SAMPLED.try_with(|sampled| *sampled).unwrap_or(true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn sampler_keeps_roughly_the_configured_fraction() {
+        let mut rng = StdRng::seed_from_u64(383);
+        let requests = 10_000;
+        let kept = (0..requests).filter(|_| is_sampled(Some(0.25), &mut rng)).count();
+        let fraction = kept as f64 / requests as f64;
+        assert!((0.23..=0.27).contains(&fraction), "kept {}", fraction);
+
+        assert!((0..100).all(|_| is_sampled(None, &mut rng)));
+        assert!((0..100).all(|_| is_sampled(Some(1.0), &mut rng)));
+        assert!((0..100).all(|_| !is_sampled(Some(0.0), &mut rng)));
+    }
+
+    #[tokio::test]
+    async fn spawned_work_keeps_the_request_decision() {
+        let (spawned, blocking) = SAMPLED
+            .scope(false, async {
+                let spawned = spawn(async { current() }).await.unwrap();
+                let blocking = spawn_blocking(current).await.unwrap();
+                (spawned, blocking)
+            })
+            .await;
+        assert_eq!((spawned, blocking), (Some(false), Some(false)));
+
+        assert_eq!(spawn(async { current() }).await.unwrap(), None);
+        assert_eq!(within(Some(true), async { current() }).await, Some(true));
+    }
+}
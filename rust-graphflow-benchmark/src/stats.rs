@@ -0,0 +1,139 @@
+use crate::error::ErrorKind;
+use crate::tools::circuit_breaker::BreakerStatus;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+/// Smoothing factor for the latency EMAs; higher values weight recent requests more.
+const EMA_ALPHA: f64 = 0.2;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Ema {
+    pub ema_ms: f64,
+    pub last_ms: u64,
+    pub samples: u64,
+}
+
+impl Ema {
+    fn update(&mut self, sample_ms: u64) {
+        self.ema_ms = if self.samples == 0 {
+            sample_ms as f64
+        } else {
+            EMA_ALPHA * sample_ms as f64 + (1.0 - EMA_ALPHA) * self.ema_ms
+        };
+        self.last_ms = sample_ms;
+        self.samples += 1;
+    }
+}
+
+/// Running latency averages across all completed requests, served on `/stats`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyStats {
+    pub total: Ema,
+    pub tasks: HashMap<String, Ema>,
+    /// Failed workflows by error category.
+    pub errors: HashMap<ErrorKind, u64>,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, task_times: &HashMap<String, u64>, total_ms: u64) {
+        self.total.update(total_ms);
+        for (task, elapsed) in task_times {
+            self.tasks.entry(task.clone()).or_default().update(*elapsed);
+        }
+    }
+
+    pub fn record_error(&mut self, kind: ErrorKind) {
+        *self.errors.entry(kind).or_default() += 1;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+    pub timestamp: DateTime<Utc>,
+    pub session_id: String,
+    /// Error kind for workflow failures, otherwise e.g. `moderated` or `http_400`.
+    pub category: String,
+    pub message: String,
+}
+
+/// The most recent request failures, oldest dropped first, served on `/errors`.
+#[derive(Debug)]
+pub struct RecentErrors {
+    entries: VecDeque<RecentError>,
+    capacity: usize,
+}
+
+impl RecentErrors {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, session_id: &str, category: String, message: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(RecentError {
+            timestamp: Utc::now(),
+            session_id: session_id.to_string(),
+            category,
+            message,
+        });
+    }
+
+    /// Up to `limit` entries, newest first.
+    pub fn latest(&self, limit: usize) -> Vec<RecentError> {
+        self.entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsResponse {
+    #[serde(flatten)]
+    pub latency: LatencyStats,
+    pub llm_circuit_breaker: BreakerStatus,
+    /// Requests waiting for a worker, when the research queue is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_depth: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ema_starts_at_the_first_sample_and_then_smooths() {
+        let mut stats = LatencyStats::default();
+        stats.record(&HashMap::from([("researcher".to_string(), 1000)]), 2000);
+        assert_eq!(stats.tasks["researcher"].ema_ms, 1000.0);
+        assert_eq!(stats.total.ema_ms, 2000.0);
+
+        stats.record(&HashMap::from([("researcher".to_string(), 2000)]), 2000);
+        let researcher = &stats.tasks["researcher"];
+        assert!((researcher.ema_ms - 1200.0).abs() < 1e-9);
+        assert_eq!(researcher.last_ms, 2000);
+        assert_eq!(researcher.samples, 2);
+        assert_eq!(stats.total.samples, 2);
+    }
+
+    #[test]
+    fn recent_errors_are_newest_first_and_bounded() {
+        let mut errors = RecentErrors::new(3);
+        for i in 0..4 {
+            errors.push(&format!("s{i}"), "other".to_string(), format!("failure {i}"));
+        }
+        let sessions = |entries: Vec<RecentError>| entries.into_iter().map(|e| e.session_id).collect::<Vec<_>>();
+        assert_eq!(sessions(errors.latest(10)), ["s3", "s2", "s1"]);
+        assert_eq!(sessions(errors.latest(2)), ["s3", "s2"]);
+
+        let mut disabled = RecentErrors::new(0);
+        disabled.push("s0", "other".to_string(), "failure".to_string());
+        assert!(disabled.latest(10).is_empty());
+    }
+}
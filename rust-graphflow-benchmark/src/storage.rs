@@ -0,0 +1,427 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crate::config::settings;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use graph_flow::{Session, SessionStorage};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+use crate::context_keys::get_research_context;
+use crate::models::ResearchContext;
+
+/// Session storage with the queries the server needs beyond `graph_flow`'s
+/// `SessionStorage`, which only supports lookups by id.
+#[async_trait]
+pub trait ExtendedSessionStorage: SessionStorage {
+    /// Returns sessions matching `filter` newest first, skipping `offset` and
+    /// returning at most `limit`.
+    async fn list_sessions(&self, limit: usize, offset: usize, filter: &SessionFilter)
+        -> graph_flow::Result<SessionPage>;
+
+    /// Ids of every stored session, newest first, as of the call.
+    async fn session_ids(&self) -> graph_flow::Result<Vec<String>>;
+
+    /// The listing entry for session `id`, if it is still stored.
+    async fn session_summary(&self, id: &str) -> graph_flow::Result<Option<SessionSummary>>;
+
+    /// Atomically maps an unexpired idempotency key to `session_id`. If the key
+    /// is already mapped, leaves it alone and returns the existing session id.
+    async fn claim_idempotency_key(&self, key: &str, session_id: &str) -> graph_flow::Result<Option<String>>;
+
+    /// Saves `session` only if no session with its id exists yet, returning
+    /// whether it was saved.
+    async fn create_session(&self, session: Session) -> graph_flow::Result<bool>;
+
+    /// Forgets an idempotency key so the request can be run again.
+    async fn release_idempotency_key(&self, key: &str) -> graph_flow::Result<()>;
+
+    /// Atomically marks session `id` as being approved, returning false if
+    /// another approval already holds it. Of several concurrent approvals
+    /// only one gets past this; the rest are answered with 409.
+    async fn claim_approval(&self, id: &str) -> graph_flow::Result<bool>;
+
+    /// Ends an approval started with `claim_approval`.
+    async fn release_approval(&self, id: &str) -> graph_flow::Result<()>;
+
+    /// Removes finished sessions last updated more than `ttl` ago, and the
+    /// idempotency keys pointing at them, returning how many were removed.
+    /// Sessions still running or awaiting approval are kept.
+    async fn evict_expired(&self, ttl: Duration) -> graph_flow::Result<usize>;
+}
+
+/// Narrows `list_sessions` to sessions with a tag or run group. Unset fields
+/// match everything.
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub tag: Option<String>,
+    pub run_group: Option<String>,
+}
+
+impl SessionFilter {
+    fn matches(&self, context: Option<&ResearchContext>) -> bool {
+        let tag_matches = self.tag.as_ref().is_none_or(|tag| context.is_some_and(|c| c.tags.contains(tag)));
+        let group_matches = self
+            .run_group
+            .as_ref()
+            .is_none_or(|group| context.is_some_and(|c| c.run_group.as_ref() == Some(group)));
+        tag_matches && group_matches
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionPage {
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    pub sessions: Vec<SessionSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub topic: String,
+    /// `in_progress`, `awaiting_approval`, `completed`, `failed` or `cancelled`.
+    pub status: String,
+    pub current_task: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_group: Option<String>,
+}
+
+struct StoredSession {
+    session: Session,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+pub struct InMemorySessionStore {
+    sessions: DashMap<String, StoredSession>,
+    idempotency_keys: DashMap<String, (String, Instant)>,
+    idempotency_ttl: Duration,
+    /// Sessions with an approval in progress.
+    approvals: DashMap<String, ()>,
+}
+
+impl InMemorySessionStore {
+    /// Idempotency keys expire after `IDEMPOTENCY_TTL_SECS` (default 24 hours).
+    pub fn new() -> Self {
+        Self {
+            sessions: DashMap::new(),
+            idempotency_keys: DashMap::new(),
+            idempotency_ttl: Duration::from_secs(settings().idempotency_ttl_secs.unwrap_or(86_400)),
+            approvals: DashMap::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStorage for InMemorySessionStore {
+    async fn save(&self, session: Session) -> graph_flow::Result<()> {
+        let created_at = self
+            .sessions
+            .get(&session.id)
+            .map(|stored| stored.created_at)
+            .unwrap_or_else(Utc::now);
+        self.sessions.insert(
+            session.id.clone(),
+            StoredSession {
+                session,
+                created_at,
+                updated_at: Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> graph_flow::Result<Option<Session>> {
+        Ok(self.sessions.get(id).map(|stored| stored.session.clone()))
+    }
+
+    async fn delete(&self, id: &str) -> graph_flow::Result<()> {
+        self.sessions.remove(id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExtendedSessionStorage for InMemorySessionStore {
+    async fn list_sessions(
+        &self,
+        limit: usize,
+        offset: usize,
+        filter: &SessionFilter,
+    ) -> graph_flow::Result<SessionPage> {
+        let mut stored: Vec<(Session, DateTime<Utc>)> = self
+            .sessions
+            .iter()
+            .map(|entry| (entry.session.clone(), entry.created_at))
+            .collect();
+        stored.sort_by(|a, b| b.1.cmp(&a.1));
+
+        // Tags live in the research context, so filtering has to read it
+        // before the page boundaries are known.
+        let mut matching = Vec::new();
+        for (session, created_at) in stored {
+            let context = get_research_context(&session.context).await;
+            if filter.matches(context.as_ref()) {
+                matching.push((session, context, created_at));
+            }
+        }
+
+        let total = matching.len();
+        let sessions = matching
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(session, context, created_at)| summarize(session, context, created_at))
+            .collect();
+
+        Ok(SessionPage {
+            total,
+            limit,
+            offset,
+            sessions,
+        })
+    }
+
+    async fn session_ids(&self) -> graph_flow::Result<Vec<String>> {
+        let mut ids: Vec<(String, DateTime<Utc>)> = self
+            .sessions
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.created_at))
+            .collect();
+        ids.sort_by(|a, b| b.1.cmp(&a.1));
+        Ok(ids.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn session_summary(&self, id: &str) -> graph_flow::Result<Option<SessionSummary>> {
+        let Some((session, created_at)) = self
+            .sessions
+            .get(id)
+            .map(|stored| (stored.session.clone(), stored.created_at))
+        else {
+            return Ok(None);
+        };
+        let context = get_research_context(&session.context).await;
+        Ok(Some(summarize(session, context, created_at)))
+    }
+
+    async fn claim_idempotency_key(&self, key: &str, session_id: &str) -> graph_flow::Result<Option<String>> {
+        let ttl = self.idempotency_ttl;
+        self.idempotency_keys
+            .retain(|_, (_, claimed_at)| claimed_at.elapsed() < ttl);
+
+        match self.idempotency_keys.entry(key.to_string()) {
+            Entry::Occupied(entry) => Ok(Some(entry.get().0.clone())),
+            Entry::Vacant(entry) => {
+                entry.insert((session_id.to_string(), Instant::now()));
+                Ok(None)
+            }
+        }
+    }
+
+    async fn create_session(&self, session: Session) -> graph_flow::Result<bool> {
+        match self.sessions.entry(session.id.clone()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                let now = Utc::now();
+                entry.insert(StoredSession {
+                    session,
+                    created_at: now,
+                    updated_at: now,
+                });
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release_idempotency_key(&self, key: &str) -> graph_flow::Result<()> {
+        self.idempotency_keys.remove(key);
+        Ok(())
+    }
+
+    async fn claim_approval(&self, id: &str) -> graph_flow::Result<bool> {
+        match self.approvals.entry(id.to_string()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert(());
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release_approval(&self, id: &str) -> graph_flow::Result<()> {
+        self.approvals.remove(id);
+        Ok(())
+    }
+
+    async fn evict_expired(&self, ttl: Duration) -> graph_flow::Result<usize> {
+        let Ok(ttl) = chrono::Duration::from_std(ttl) else {
+            return Ok(0);
+        };
+        let cutoff = Utc::now() - ttl;
+        let stale: Vec<Session> = self
+            .sessions
+            .iter()
+            .filter(|entry| entry.updated_at < cutoff)
+            .map(|entry| entry.session.clone())
+            .collect();
+
+        // Whether a session finished lives in its context, which can't be
+        // read while holding a map guard.
+        let mut evicted = Vec::new();
+        for session in stale {
+            let context = get_research_context(&session.context).await;
+            if !session_status(&session, context.as_ref()).is_finished() {
+                continue;
+            }
+            // A save since the snapshot makes the session current again.
+            if self.sessions.remove_if(&session.id, |_, stored| stored.updated_at < cutoff).is_some() {
+                self.approvals.remove(&session.id);
+                evicted.push(session.id);
+            }
+        }
+        self.idempotency_keys
+            .retain(|_, (session_id, _)| !evicted.contains(session_id));
+        Ok(evicted.len())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionStatus {
+    InProgress,
+    AwaitingApproval,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl SessionStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            SessionStatus::InProgress => "in_progress",
+            SessionStatus::AwaitingApproval => "awaiting_approval",
+            SessionStatus::Completed => "completed",
+            SessionStatus::Failed => "failed",
+            SessionStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, SessionStatus::Completed | SessionStatus::Failed | SessionStatus::Cancelled)
+    }
+}
+
+/// Failures and cancellations are only recorded in `status_message`
+/// (`failed: <category>`, `cancelled: <reason>`), so they are checked before
+/// the context.
+fn session_status(session: &Session, context: Option<&ResearchContext>) -> SessionStatus {
+    let message = session.status_message.as_deref().unwrap_or_default();
+    if message.starts_with("cancelled") {
+        SessionStatus::Cancelled
+    } else if message.starts_with("failed") {
+        SessionStatus::Failed
+    } else if context.is_some_and(|c| !c.report.is_empty()) {
+        SessionStatus::Completed
+    } else if context.is_some_and(|c| c.awaiting_approval) {
+        SessionStatus::AwaitingApproval
+    } else {
+        SessionStatus::InProgress
+    }
+}
+
+fn summarize(session: Session, context: Option<ResearchContext>, created_at: DateTime<Utc>) -> SessionSummary {
+    let status = session_status(&session, context.as_ref()).as_str();
+
+    let (topic, tags, run_group) = context
+        .map(|c| (c.topic, c.tags, c.run_group))
+        .unwrap_or_default();
+    SessionSummary {
+        session_id: session.id,
+        topic,
+        status: status.to_string(),
+        current_task: session.current_task_id,
+        created_at,
+        tags,
+        run_group,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context_keys::set_research_context;
+
+    async fn store(store: &InMemorySessionStore, id: &str, status_message: Option<&str>, context: ResearchContext) {
+        let mut session = Session::new_from_task(id.to_string(), "categorizer");
+        session.status_message = status_message.map(str::to_string);
+        set_research_context(&session.context, context).await;
+        store.save(session).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn listing_derives_each_status() {
+        let storage = InMemorySessionStore::new();
+        let reported = ResearchContext {
+            report: "# Report".to_string(),
+            ..Default::default()
+        };
+        store(&storage, "running", None, ResearchContext::default()).await;
+        store(&storage, "done", None, reported.clone()).await;
+        store(&storage, "failed", Some("failed: llm_timeout"), ResearchContext::default()).await;
+        store(&storage, "cancelled", Some("cancelled: client disconnected"), reported).await;
+        let awaiting = ResearchContext {
+            awaiting_approval: true,
+            ..Default::default()
+        };
+        store(&storage, "paused", None, awaiting).await;
+
+        let page = storage.list_sessions(10, 0, &SessionFilter::default()).await.unwrap();
+        let status = |id: &str| page.sessions.iter().find(|s| s.session_id == id).unwrap().status.clone();
+        assert_eq!(page.total, 5);
+        assert_eq!(status("running"), "in_progress");
+        assert_eq!(status("done"), "completed");
+        assert_eq!(status("failed"), "failed");
+        assert_eq!(status("cancelled"), "cancelled");
+        assert_eq!(status("paused"), "awaiting_approval");
+    }
+
+    fn age(storage: &InMemorySessionStore, id: &str, created: chrono::Duration, updated: chrono::Duration) {
+        let mut stored = storage.sessions.get_mut(id).unwrap();
+        stored.created_at = Utc::now() - created;
+        stored.updated_at = Utc::now() - updated;
+    }
+
+    #[tokio::test]
+    async fn eviction_keeps_unfinished_and_recently_updated_sessions() {
+        let storage = InMemorySessionStore::new();
+        let reported = ResearchContext {
+            report: "# Report".to_string(),
+            ..Default::default()
+        };
+        store(&storage, "stale", None, reported.clone()).await;
+        store(&storage, "running", None, ResearchContext::default()).await;
+        store(&storage, "touched", Some("failed: other"), ResearchContext::default()).await;
+        let hours = chrono::Duration::hours;
+        age(&storage, "stale", hours(3), hours(2));
+        age(&storage, "running", hours(3), hours(2));
+        age(&storage, "touched", hours(3), chrono::Duration::zero());
+        storage.claim_idempotency_key("stale-key", "stale").await.unwrap();
+        storage.claim_idempotency_key("running-key", "running").await.unwrap();
+
+        let evicted = storage.evict_expired(Duration::from_secs(3600)).await.unwrap();
+        assert_eq!(evicted, 1);
+        assert!(storage.get("stale").await.unwrap().is_none());
+        assert!(storage.get("running").await.unwrap().is_some());
+        assert!(storage.get("touched").await.unwrap().is_some());
+        assert_eq!(storage.claim_idempotency_key("stale-key", "new").await.unwrap(), None);
+        assert_eq!(
+            storage.claim_idempotency_key("running-key", "new").await.unwrap().as_deref(),
+            Some("running")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::config::settings;
+use crate::context_keys::{get_research_context, set_research_context};
+use async_trait::async_trait;
+use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
+use tracing::{info, instrument};
+
+/// Holds the workflow before the reporter until a human approves the summary
+/// via `POST /research/{id}/approve`. Passes straight through unless
+/// `REQUIRE_APPROVAL` is set.
+pub struct ApprovalTask {
+    required: bool,
+}
+
+impl ApprovalTask {
+    pub fn new(required: bool) -> Self {
+        Self { required }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(settings().require_approval)
+    }
+}
+
+#[async_trait]
+impl Task for ApprovalTask {
+    fn id(&self) -> &str {
+        "approval"
+    }
+
+    #[instrument(skip(self, context))]
+    async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
+        let mut research_context = get_research_context(&context)
+            .await
+            .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
+
+        if research_context.approved || !self.required {
+            return Ok(TaskResult::new(None, NextAction::Continue));
+        }
+
+        info!("Waiting for summary approval");
+        research_context.awaiting_approval = true;
+        set_research_context(&context, research_context).await;
+
+        Ok(TaskResult::new(
+            Some("Waiting for summary approval".to_string()),
+            NextAction::WaitForInput,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ResearchContext;
+
+    async fn context_with(research_context: ResearchContext) -> Context {
+        let context = Context::new();
+        set_research_context(&context, research_context).await;
+        context
+    }
+
+    #[tokio::test]
+    async fn pauses_until_approved() {
+        let context = context_with(ResearchContext::default()).await;
+        let result = ApprovalTask::new(true).run(context.clone()).await.unwrap();
+        assert!(matches!(result.next_action, NextAction::WaitForInput));
+        assert!(get_research_context(&context).await.unwrap().awaiting_approval);
+
+        let approved = context_with(ResearchContext {
+            approved: true,
+            ..Default::default()
+        })
+        .await;
+        let result = ApprovalTask::new(true).run(approved).await.unwrap();
+        assert!(matches!(result.next_action, NextAction::Continue));
+    }
+
+    #[tokio::test]
+    async fn passes_through_when_not_required() {
+        let context = context_with(ResearchContext::default()).await;
+        let result = ApprovalTask::new(false).run(context.clone()).await.unwrap();
+        assert!(matches!(result.next_action, NextAction::Continue));
+        assert!(!get_research_context(&context).await.unwrap().awaiting_approval);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::context_keys::{get_research_context, set_research_context};
+use crate::tasks::{record_task_time, record_ttft};
+use crate::tools::llm::{get_llm, prompt_llm_streaming};
+use crate::tools::pools;
+use async_trait::async_trait;
+use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
+use tracing::{info, instrument};
+
+const CATEGORIES: &[&str] = &[
+    "technology",
+    "science",
+    "health",
+    "finance",
+    "business",
+    "politics",
+    "education",
+    "environment",
+    "culture",
+    "sports",
+    "other",
+];
+
+pub struct CategorizerTask;
+
+#[async_trait]
+impl Task for CategorizerTask {
+    fn id(&self) -> &str {
+        "categorizer"
+    }
+
+    #[instrument(skip(self, context))]
+    async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
+        let start_time = std::time::Instant::now();
+        let _permit = pools::acquire("categorizer").await;
+        info!("Starting categorization task");
+
+        let mut research_context = get_research_context(&context)
+            .await
+            .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
+
+        // With provided questions the caller wants no LLM work before the
+        // research itself; the category is only informational.
+        if research_context.extraction_skipped {
+            info!("Questions were provided, skipping categorization");
+            research_context.category = "other".to_string();
+            set_research_context(&context, research_context).await;
+            return Ok(TaskResult::new(
+                Some("Using provided questions".to_string()),
+                NextAction::Continue,
+            ));
+        }
+
+        let prompt = format!(
+            r#"Classify the following research topic into exactly one category: "{}"
+
+Categories: {}
+
+Format: Return only the category name, in lowercase"#,
+            research_context.topic,
+            CATEGORIES.join(", ")
+        );
+
+        let agent = get_llm(&research_context.config.for_task("categorizer")).map_err(GraphError::Other)?;
+        let response = prompt_llm_streaming(&agent, &prompt).await.map_err(GraphError::Other)?;
+        record_ttft(&context, "categorizer", response.ttft_ms).await;
+
+        let category = normalize_category(&response.text);
+
+        info!(task = "categorizer", category = %category, "Categorized topic");
+        research_context.category = category;
+        set_research_context(&context, research_context).await;
+
+        record_task_time(&context, "categorizer", start_time).await;
+
+        Ok(TaskResult::new(
+            Some("Topic categorized successfully".to_string()),
+            NextAction::Continue,
+        ))
+    }
+}
+
+fn normalize_category(response: &str) -> String {
+    let candidate = response
+        .trim()
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    if CATEGORIES.contains(&candidate.as_str()) {
+        candidate
+    } else {
+        "other".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ResearchContext;
+    use crate::tasks::QuestionExtractorTask;
+    use crate::tools::usage::track_usage;
+
+    #[tokio::test]
+    async fn provided_questions_skip_every_llm_call() {
+        let context = Context::new();
+        let questions = vec!["What is Rust?".to_string(), "Who maintains Rust?".to_string()];
+        set_research_context(
+            &context,
+            ResearchContext {
+                topic: "Rust".to_string(),
+                questions: questions.clone(),
+                extraction_skipped: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // An LLM call would either fail without an API key or show up in usage.
+        let (results, usage) = track_usage(async {
+            let categorized = CategorizerTask.run(context.clone()).await;
+            let extracted = QuestionExtractorTask.run(context.clone()).await;
+            (categorized, extracted)
+        })
+        .await;
+        assert!(results.0.is_ok());
+        assert!(results.1.is_ok());
+        assert_eq!(usage.total_tokens, 0);
+
+        let research_context = get_research_context(&context).await.unwrap();
+        assert_eq!(research_context.category, "other");
+        assert_eq!(research_context.questions, questions);
+    }
+
+    #[test]
+    fn unknown_categories_become_other() {
+        assert_eq!(normalize_category(" Science.\n"), "science");
+        assert_eq!(normalize_category("astrology"), "other");
+    }
+
+    #[test]
+    fn every_listed_category_is_kept() {
+        for category in CATEGORIES {
+            assert_eq!(normalize_category(category), *category);
+            assert_eq!(normalize_category(&format!("\"{}\"", category.to_uppercase())), *category);
+        }
+    }
+}
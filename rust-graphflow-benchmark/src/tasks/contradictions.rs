@@ -0,0 +1,94 @@
+use crate::config::settings;
+use crate::context_keys::{get_research_context, set_research_context};
+use crate::models::Contradiction;
+use crate::tasks::{record_task_time, record_ttft};
+use crate::tools::llm::{get_llm, parse_json, prompt_llm_streaming};
+use crate::tools::pools;
+use async_trait::async_trait;
+use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
+use tracing::{info, instrument, warn};
+
+/// Looks for findings that disagree with each other. Passes straight through
+/// unless `DETECT_CONTRADICTIONS` is set.
+pub struct ContradictionsTask;
+
+#[async_trait]
+impl Task for ContradictionsTask {
+    fn id(&self) -> &str {
+        "contradictions"
+    }
+
+    #[instrument(skip(self, context))]
+    async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
+        if !settings().detect_contradictions {
+            return Ok(TaskResult::new(None, NextAction::Continue));
+        }
+
+        let start_time = std::time::Instant::now();
+        let _permit = pools::acquire("contradictions").await;
+        info!("Starting contradiction detection task");
+
+        let mut research_context = get_research_context(&context)
+            .await
+            .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
+
+        let prompt = format!(
+            r#"You are a research analyst. Find claims in the research findings below that contradict each other.
+
+Findings:
+{}
+
+Requirements:
+- Only report genuine conflicts, such as different figures, dates or conclusions about the same thing
+- Each claim must come from a finding, with that finding's URL
+- Format: Return only a JSON array of objects like {{"description": "what the sources disagree about", "claims": [{{"claim": "...", "url": "..."}}]}}, or [] if the findings agree"#,
+            research_context.findings_text()
+        );
+
+        let agent = get_llm(&research_context.config.for_task("contradictions")).map_err(GraphError::Other)?;
+        let response = prompt_llm_streaming(&agent, &prompt).await.map_err(GraphError::Other)?;
+        record_ttft(&context, "contradictions", response.ttft_ms).await;
+
+        let contradictions = parse_contradictions(&response.text);
+
+        info!(task = "contradictions", contradictions = contradictions.len(), "Contradiction detection completed");
+        research_context.contradictions = contradictions;
+        set_research_context(&context, research_context).await;
+
+        record_task_time(&context, "contradictions", start_time).await;
+
+        Ok(TaskResult::new(
+            Some("Contradiction detection completed successfully".to_string()),
+            NextAction::Continue,
+        ))
+    }
+}
+
+fn parse_contradictions(text: &str) -> Vec<Contradiction> {
+    parse_json(text).unwrap_or_else(|| {
+        warn!("Failed to parse contradictions response, assuming none");
+        vec![]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contradictions_are_parsed_from_the_reply() {
+        let reply = r#"```json
+[{"description": "Release year",
+  "claims": [{"claim": "Tokio 1.0 shipped in 2020", "url": "https://a.example"},
+             {"claim": "Tokio 1.0 shipped in 2021", "url": "https://b.example"}]}]
+```"#;
+        let contradictions = parse_contradictions(reply);
+        assert_eq!(contradictions.len(), 1);
+        assert_eq!(contradictions[0].description, "Release year");
+        let urls: Vec<_> = contradictions[0].claims.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, ["https://a.example", "https://b.example"]);
+
+        assert!(parse_contradictions("[]").is_empty());
+        assert!(parse_contradictions("The findings agree.").is_empty());
+    }
+}
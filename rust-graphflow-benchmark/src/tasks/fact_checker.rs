@@ -0,0 +1,64 @@
+use crate::context_keys::{get_research_context, set_research_context};
+use crate::tasks::{record_task_time, record_ttft};
+use crate::tools::llm::{get_llm, parse_json, prompt_llm_streaming};
+use crate::tools::pools;
+use async_trait::async_trait;
+use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
+use tracing::{info, instrument, warn};
+
+pub struct FactCheckerTask;
+
+#[async_trait]
+impl Task for FactCheckerTask {
+    fn id(&self) -> &str {
+        "fact_checker"
+    }
+
+    #[instrument(skip(self, context))]
+    async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
+        let start_time = std::time::Instant::now();
+        let _permit = pools::acquire("fact_checker").await;
+        info!("Starting fact checking task");
+
+        let mut research_context = get_research_context(&context)
+            .await
+            .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
+
+        let prompt = format!(
+            r#"You are a fact checker. Check every claim in the following summary against the research findings below.
+
+Summary:
+{}
+
+Findings:
+{}
+
+Requirements:
+- A claim is unsupported if none of the findings back it up
+- Quote each unsupported claim exactly as it appears in the summary
+- Format: Return only a JSON array of strings, or [] if every claim is supported"#,
+            research_context.summary,
+            research_context.findings_text()
+        );
+
+        let agent = get_llm(&research_context.config.for_task("fact_checker")).map_err(GraphError::Other)?;
+        let response = prompt_llm_streaming(&agent, &prompt).await.map_err(GraphError::Other)?;
+        record_ttft(&context, "fact_checker", response.ttft_ms).await;
+
+        let unsupported_claims: Vec<String> = parse_json(&response.text).unwrap_or_else(|| {
+            warn!("Failed to parse fact check response, assuming no unsupported claims");
+            vec![]
+        });
+
+        info!(task = "fact_checker", unsupported_claims = unsupported_claims.len(), "Fact check completed");
+        research_context.unsupported_claims = unsupported_claims;
+        set_research_context(&context, research_context).await;
+
+        record_task_time(&context, "fact_checker", start_time).await;
+
+        Ok(TaskResult::new(
+            Some("Fact check completed successfully".to_string()),
+            NextAction::Continue,
+        ))
+    }
+}
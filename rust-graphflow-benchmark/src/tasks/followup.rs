@@ -0,0 +1,36 @@
+use crate::models::ResearchContext;
+use crate::tools::llm::{get_llm, prompt_llm};
+use anyhow::Result;
+
+/// Answers a follow-up question from a completed session's findings with a
+/// single LLM call, without re-running any part of the workflow.
+pub async fn answer_followup(context: &ResearchContext, question: &str) -> Result<String> {
+    let agent = get_llm(&context.config)?;
+    prompt_llm(&agent, &followup_prompt(context, question)).await
+}
+
+/// The prompt behind `answer_followup`, shared with the streaming endpoint.
+pub fn followup_prompt(context: &ResearchContext, question: &str) -> String {
+    format!(
+        r#"You are a research assistant. Earlier research about "{}" produced the summary and findings below. Answer the follow-up question using only this material.
+
+Summary:
+{}
+
+Findings:
+{}
+
+Follow-up question: {}
+
+Requirements:
+- Answer in one to three paragraphs
+- Cite source URLs where appropriate
+- If the findings do not answer the question, say so instead of guessing
+- Write the answer in {}"#,
+        context.topic,
+        context.summary,
+        context.findings_text(),
+        question,
+        context.detected_language
+    )
+}
@@ -1,9 +1,183 @@
+mod approval;
+mod categorizer;
+mod contradictions;
+mod fact_checker;
+mod followup;
 mod question_extractor;
 mod researcher;
 mod summarizer;
 mod reporter;
+mod report_judge;
+mod summarization;
 
-pub use question_extractor::QuestionExtractorTask;
-pub use researcher::ResearcherTask;
+use crate::config::settings;
+use crate::context_keys::{self, SUBCALL_TIMES, TASK_TIMES, TTFT_MS};
+use graph_flow::Context;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+pub use approval::ApprovalTask;
+pub use categorizer::CategorizerTask;
+pub use contradictions::ContradictionsTask;
+pub use fact_checker::FactCheckerTask;
+pub use followup::{answer_followup, followup_prompt};
+pub use question_extractor::{QuestionExtractorTask, QuestionValidation};
+pub use researcher::{parse_researcher_tools, NoFindingsPolicy, ResearcherTask};
 pub use summarizer::SummarizerTask;
-pub use reporter::ReporterTask;
\ No newline at end of file
+pub use reporter::{split_sections, ReporterTask};
+pub use report_judge::judge_reports;
+pub use summarization::SummaryStrategy;
+
+/// Adds a task's elapsed time to `task_times` and logs it as structured fields.
+/// A task that runs more than once, such as a researcher retry or follow-up
+/// pass, accumulates the time of every run.
+async fn record_task_time(context: &Context, task: &str, start_time: Instant) {
+    let elapsed_ms = start_time.elapsed().as_millis() as u64;
+    let mut task_times: HashMap<String, u64> = context_keys::get(context, TASK_TIMES).await.unwrap_or_default();
+    *task_times.entry(task.to_string()).or_default() += elapsed_ms;
+    context_keys::set(context, TASK_TIMES, task_times).await;
+    info!(task, elapsed_ms, "Task completed");
+}
+
+/// Durations of the calls inside one task run, such as each question's search
+/// and LLM time in the researcher. Only collected under `DETAILED_TIMINGS`,
+/// otherwise `record` is a no-op.
+struct SubcallTimes {
+    enabled: bool,
+    times: Mutex<BTreeMap<String, u64>>,
+}
+
+impl SubcallTimes {
+    fn from_env() -> Self {
+        Self {
+            enabled: settings().detailed_timings,
+            times: Mutex::default(),
+        }
+    }
+
+    fn record(&self, label: String, elapsed: Duration) {
+        if self.enabled {
+            *self.times.lock().unwrap().entry(label).or_default() += elapsed.as_millis() as u64;
+        }
+    }
+
+    /// Adds the collected times under `task` in `subcall_times`.
+    async fn save(self, context: &Context, task: &str) {
+        let times = self.times.into_inner().unwrap();
+        if times.is_empty() {
+            return;
+        }
+        let mut all: HashMap<String, BTreeMap<String, u64>> =
+            context_keys::get(context, SUBCALL_TIMES).await.unwrap_or_default();
+        all.entry(task.to_string()).or_default().extend(times);
+        context_keys::set(context, SUBCALL_TIMES, all).await;
+    }
+}
+
+/// Records a task's time-to-first-token alongside `task_times`. Calls that
+/// were not streamed have no TTFT and are left out.
+async fn record_ttft(context: &Context, task: &str, ttft_ms: Option<u64>) {
+    if let Some(ttft_ms) = ttft_ms {
+        let mut ttfts: HashMap<String, u64> =
+            context_keys::get(context, TTFT_MS).await.unwrap_or_default();
+        ttfts.insert(task.to_string(), ttft_ms);
+        context_keys::set(context, TTFT_MS, ttfts).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_streamed_calls_record_a_ttft() {
+        let context = Context::new();
+        record_ttft(&context, "summarizer", Some(120)).await;
+        record_ttft(&context, "reporter", None).await;
+        record_ttft(&context, "categorizer", Some(40)).await;
+
+        let ttfts: HashMap<String, u64> = context_keys::get(&context, TTFT_MS).await.unwrap();
+        assert_eq!(ttfts, HashMap::from([("summarizer".to_string(), 120), ("categorizer".to_string(), 40)]));
+    }
+
+    /// Collects formatted log output in memory.
+    #[derive(Clone, Default)]
+    struct LogBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn task_times_are_logged_as_json_fields() {
+        let buffer = LogBuffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let context = Context::new();
+        record_task_time(&context, "summarizer", Instant::now()).await;
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(line["fields"]["message"], "Task completed");
+        assert_eq!(line["fields"]["task"], "summarizer");
+        assert!(line["fields"]["elapsed_ms"].is_u64());
+    }
+
+    fn subcall_times(enabled: bool) -> SubcallTimes {
+        SubcallTimes {
+            enabled,
+            times: Mutex::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn researcher_subcalls_are_nested_under_the_task() {
+        let context = Context::new();
+        let timings = subcall_times(true);
+        timings.record("What is Tokio? / search".to_string(), Duration::from_millis(300));
+        timings.record("What is Tokio? / search".to_string(), Duration::from_millis(200));
+        timings.record("What is Tokio? / llm".to_string(), Duration::from_millis(900));
+        timings.record("What is Tokio? / parse".to_string(), Duration::from_millis(2));
+        timings.save(&context, "researcher").await;
+
+        let disabled = subcall_times(false);
+        disabled.record("What is smol? / search".to_string(), Duration::from_millis(100));
+        disabled.save(&context, "researcher").await;
+
+        let all: HashMap<String, BTreeMap<String, u64>> = context_keys::get(&context, SUBCALL_TIMES).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(
+            all["researcher"],
+            BTreeMap::from([
+                ("What is Tokio? / llm".to_string(), 900),
+                ("What is Tokio? / parse".to_string(), 2),
+                ("What is Tokio? / search".to_string(), 500),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn repeated_runs_of_a_task_add_up() {
+        let context = Context::new();
+        let started = Instant::now() - Duration::from_millis(50);
+        record_task_time(&context, "researcher", started).await;
+        record_task_time(&context, "researcher", started).await;
+
+        let task_times: HashMap<String, u64> = context_keys::get(&context, TASK_TIMES).await.unwrap();
+        assert!(task_times["researcher"] >= 100);
+    }
+}
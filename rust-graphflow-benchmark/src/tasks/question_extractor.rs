@@ -1,9 +1,21 @@
-use crate::models::ResearchContext;
-use crate::tools::llm::get_llm;
+use crate::config::settings;
+use crate::context_keys::{get_research_context, set_research_context};
+use crate::models::{ExtractedQuestions, ResearchContext};
+use crate::tasks::{record_task_time, record_ttft};
+use crate::tools::llm::{
+    extract_structured, get_llm, prompt_llm_streaming, structured_or_prompted, structured_output_enabled,
+};
+use crate::tools::pools;
 use async_trait::async_trait;
 use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
-use rig::completion::Prompt;
-use tracing::{info, instrument};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Instant;
+use tracing::{debug, info, instrument, warn};
+
+/// Fewest questions a usable extraction must produce.
+const MIN_QUESTIONS: usize = 2;
 
 pub struct QuestionExtractorTask;
 
@@ -16,46 +28,257 @@ impl Task for QuestionExtractorTask {
     #[instrument(skip(self, context))]
     async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
         let start_time = std::time::Instant::now();
+        let _permit = pools::acquire("question_extractor").await;
         info!("Starting question extraction task");
 
-        let mut research_context: ResearchContext = context
-            .get("research_context")
+        let mut research_context = get_research_context(&context)
             .await
             .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
 
+        if research_context.extraction_skipped {
+            info!("Using {} provided research questions, skipping extraction", research_context.questions.len());
+            return Ok(TaskResult::new(
+                Some("Using provided questions".to_string()),
+                NextAction::Continue,
+            ));
+        }
+
+        let max_questions = research_context.config.max_questions();
+        let min_questions = MIN_QUESTIONS.min(max_questions);
+        let question_range = if max_questions <= 3 {
+            max_questions.to_string()
+        } else {
+            format!("3-{}", max_questions)
+        };
+
         let prompt = format!(
-            r#"You are a research assistant. Generate 3-5 specific research questions about the following topic: "{}"
+            r#"You are a research assistant. Generate {} specific research questions about the following topic: "{}"
 
 Requirements:
 - Questions should be factual and answerable through web research
 - Questions should cover different aspects of the topic
 - Questions should be clear and well-defined
+- Write the questions in {}
 - Format: Return only the questions, one per line, no numbering or bullets"#,
-            research_context.topic
+            question_range, research_context.topic, research_context.detected_language
         );
 
-        let agent = get_llm().map_err(GraphError::Other)?;
-        let response = agent.prompt(&prompt).await.map_err(|e| GraphError::Other(anyhow::anyhow!("Prompt error: {}", e)))?;
+        let validation = settings().question_validation;
+        let near_dedup = settings().question_near_dedup;
+        let config = research_context.config.for_task("question_extractor");
+        let (questions, attempts) = structured_or_prompted(
+            structured_output_enabled(),
+            "question extraction",
+            || async {
+                let extracted = extract_structured::<ExtractedQuestions>(&config, &prompt).await?;
+                let questions = parse_questions(&extracted.questions.join("\n"), max_questions, validation, near_dedup);
+                if questions.len() < min_questions {
+                    anyhow::bail!("only {} usable questions", questions.len());
+                }
+                Ok((questions, 1))
+            },
+            || async {
+                let agent = get_llm(&config)?;
+                let response = prompt_llm_streaming(&agent, &prompt).await?;
+                record_ttft(&context, "question_extractor", response.ttft_ms).await;
+
+                let max_retries = settings().question_extraction_retries.unwrap_or(2);
+                let mut attempts = 1;
+                let mut questions = parse_questions(&response.text, max_questions, validation, near_dedup);
+                while questions.len() < min_questions && attempts <= max_retries {
+                    warn!("Extraction attempt {} produced {} questions, retrying with stricter prompt", attempts, questions.len());
+                    let retry_prompt = format!(
+                        r#"Your previous answer could not be used as a list of research questions about "{}":
+
+{}
+
+Return {} research questions in {}.
+Put each question on its own line and end it with a question mark.
+Do not add numbering, bullets, headings or any other text."#,
+                        research_context.topic, response.text, question_range, research_context.detected_language
+                    );
+                    let retry_response = prompt_llm_streaming(&agent, &retry_prompt).await?;
+                    questions = parse_questions(&retry_response.text, max_questions, validation, near_dedup);
+                    attempts += 1;
+                }
+                Ok((questions, attempts))
+            },
+        )
+        .await
+        .map_err(GraphError::Other)?;
+
+        finish(&context, research_context, questions, attempts, start_time).await
+    }
+}
+
+async fn finish(
+    context: &Context,
+    mut research_context: ResearchContext,
+    questions: Vec<String>,
+    attempts: u32,
+    start_time: Instant,
+) -> Result<TaskResult, GraphError> {
+    info!(task = "question_extractor", question_count = questions.len(), attempts, "Extracted research questions");
+    research_context.questions = questions;
+    research_context.extraction_attempts = attempts;
+    set_research_context(context, research_context).await;
+
+    record_task_time(context, "question_extractor", start_time).await;
+
+    Ok(TaskResult::new(
+        Some("Questions extracted successfully".to_string()),
+        NextAction::Continue,
+    ))
+}
+
+/// How strictly extracted lines must look like questions, set by
+/// `QUESTION_VALIDATION`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionValidation {
+    /// Every non-empty line is kept.
+    Off,
+    /// Lines ending in a question mark or starting with an interrogative word.
+    #[default]
+    Lenient,
+    /// Only lines ending in a question mark.
+    Strict,
+}
 
-        let questions: Vec<String> = response
-            .split('\n')
-            .filter(|line| !line.trim().is_empty())
-            .map(|line| line.trim().to_string())
-            .collect();
+impl FromStr for QuestionValidation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(QuestionValidation::Off),
+            "strict" => Ok(QuestionValidation::Strict),
+            "lenient" => Ok(QuestionValidation::Lenient),
+            other => Err(format!("unknown question validation {:?}, expected off, lenient or strict", other)),
+        }
+    }
+}
+
+impl QuestionValidation {
+    fn accepts(self, line: &str) -> bool {
+        let ends_with_question_mark = line.ends_with('?') || line.ends_with('？');
+        match self {
+            QuestionValidation::Off => true,
+            QuestionValidation::Strict => ends_with_question_mark,
+            QuestionValidation::Lenient => ends_with_question_mark || starts_with_interrogative(line),
+        }
+    }
+}
 
-        info!("Extracted {} research questions", questions.len());
-        research_context.questions = questions;
-        context.set("research_context", research_context).await;
+const INTERROGATIVES: &[&str] = &[
+    "what", "how", "why", "when", "where", "which", "who", "whom", "whose", "is", "are", "was", "were", "does",
+    "do", "did", "can", "could", "should", "would", "will", "has", "have", "to what extent",
+];
+
+fn starts_with_interrogative(line: &str) -> bool {
+    let line = line.to_lowercase();
+    INTERROGATIVES.iter().any(|word| {
+        line.strip_prefix(word)
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_whitespace() || c == '\''))
+    })
+}
+
+/// Splits the response into accepted questions, drops duplicates and keeps
+/// the first `max_questions`, so repeats do not use up question slots.
+fn parse_questions(
+    response: &str,
+    max_questions: usize,
+    validation: QuestionValidation,
+    near_dedup: bool,
+) -> Vec<String> {
+    let questions: Vec<String> = response
+        .split('\n')
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter(|line| {
+            let accepted = validation.accepts(line);
+            if !accepted {
+                debug!("Dropping extracted line that is not a question: {}", line);
+            }
+            accepted
+        })
+        .map(str::to_string)
+        .collect();
+    let mut questions = dedup_questions(questions, near_dedup);
+    questions.truncate(max_questions);
+    questions
+}
+
+/// Removes repeated questions, keeping the first occurrence. Exact repeats
+/// always go; with `near` (`QUESTION_NEAR_DEDUP`), so do questions that only
+/// differ in case, punctuation or spacing.
+fn dedup_questions(questions: Vec<String>, near: bool) -> Vec<String> {
+    let total = questions.len();
+    let mut seen = HashSet::new();
+    let questions: Vec<String> = questions
+        .into_iter()
+        .filter(|question| {
+            let key = if near { normalize_question(question) } else { question.clone() };
+            seen.insert(key)
+        })
+        .collect();
+    if questions.len() < total {
+        info!(removed = total - questions.len(), "Removed duplicate questions");
+    }
+    questions
+}
+
+fn normalize_question(question: &str) -> String {
+    question
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prose_answers_fall_short_and_trigger_a_retry() {
+        let prose = "Here are some research directions:\n\n- Tokio internals\n- async-std adoption";
+        assert!(parse_questions(prose, 5, QuestionValidation::Lenient, false).len() < MIN_QUESTIONS);
+
+        let listed = "What is Tokio?\nHow widely is async-std used?";
+        assert_eq!(parse_questions(listed, 5, QuestionValidation::Lenient, false).len(), MIN_QUESTIONS);
+    }
+
+    #[test]
+    fn statements_are_filtered_by_strictness() {
+        let mixed = "## Research questions\nWhat is Tokio?\nHow does async-std compare\nTokio is popular.\nWho maintains smol?";
+        let parse = |validation| parse_questions(mixed, 5, validation, false);
+
+        assert_eq!(parse(QuestionValidation::Strict), ["What is Tokio?", "Who maintains smol?"]);
+        assert_eq!(
+            parse(QuestionValidation::Lenient),
+            ["What is Tokio?", "How does async-std compare", "Who maintains smol?"]
+        );
+        assert_eq!(parse(QuestionValidation::Off).len(), 5);
+        // Interrogatives must be whole words, not just prefixes.
+        assert!(!QuestionValidation::Lenient.accepts("Whatever the runtime, measure it"));
+        assert!("Strict".parse::<QuestionValidation>().is_ok());
+        assert!("picky".parse::<QuestionValidation>().is_err());
+    }
+
+    #[test]
+    fn too_few_questions_after_filtering_trigger_a_retry() {
+        let mostly_statements = "Tokio is popular.\nasync-std is smaller.\nWhat is smol?";
+        assert!(parse_questions(mostly_statements, 5, QuestionValidation::Strict, false).len() < MIN_QUESTIONS);
+    }
 
-        let elapsed = start_time.elapsed().as_millis() as u64;
-        let mut task_times: std::collections::HashMap<String, u64> = 
-            context.get("task_times").await.unwrap_or_default();
-        task_times.insert("question_extractor".to_string(), elapsed);
-        context.set("task_times", task_times).await;
+    #[test]
+    fn duplicate_questions_are_collapsed_before_research() {
+        let response = "What is Tokio?\nWhat is Tokio?\nwhat is  tokio\nWho maintains smol?\nWhat is Tokio ?\nHow fast is glommio?";
+        let parse = |near_dedup| parse_questions(response, 3, QuestionValidation::Off, near_dedup);
 
-        Ok(TaskResult::new(
-            Some("Questions extracted successfully".to_string()),
-            NextAction::ContinueAndExecute,
-        ))
+        // Duplicates are removed before the cap, so they do not take a slot.
+        assert_eq!(parse(true), ["What is Tokio?", "Who maintains smol?", "How fast is glommio?"]);
+        assert_eq!(parse(false), ["What is Tokio?", "what is  tokio", "Who maintains smol?"]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,27 @@
+use crate::models::ResearchContext;
+use crate::tools::llm::{get_llm, prompt_llm};
+use anyhow::Result;
+
+/// Asks the first session's model to compare two reports on the same topic.
+/// Used by `POST /reports/diff` only when the caller opts in, since it costs
+/// an extra LLM call with both reports in the prompt.
+pub async fn judge_reports(first: &ResearchContext, second: &ResearchContext) -> Result<String> {
+    let prompt = format!(
+        r#"You are evaluating two research reports. The first was written about "{}" and the second about "{}".
+
+Report A:
+{}
+
+Report B:
+{}
+
+Requirements:
+- Compare accuracy, coverage, structure and use of sources
+- Say which report is better overall, or that they are equivalent
+- Keep the comparison under 200 words"#,
+        first.topic, second.topic, first.report, second.report
+    );
+
+    let agent = get_llm(&first.config)?;
+    prompt_llm(&agent, &prompt).await
+}
@@ -1,9 +1,24 @@
-use crate::models::ResearchContext;
-use crate::tools::llm::get_llm;
+use crate::config::settings;
+use crate::context_keys::{get_research_context, set_research_context};
+use crate::models::{Report, ReportFormat, ResearchResult};
+use crate::tasks::{record_task_time, record_ttft};
+use crate::tools::llm::{
+    extract_structured, get_llm, parse_json, prompt_llm, prompt_llm_streaming, structured_or_prompted,
+    structured_output_enabled,
+};
+use crate::tools::pools;
+use crate::tools::usage::CHARS_PER_TOKEN;
 use async_trait::async_trait;
+use futures::future::join_all;
 use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
-use rig::completion::Prompt;
-use tracing::{info, instrument};
+use std::collections::HashMap;
+use tracing::{info, instrument, warn};
+
+const DEFAULT_MAX_PROMPT_TOKENS: usize = 100_000;
+/// Reports shorter than this are treated as failed generations.
+const DEFAULT_MIN_REPORT_CHARS: usize = 100;
+/// Rough size of the reporter's fixed instructions, including the persona line.
+const PROMPT_OVERHEAD_CHARS: usize = 2_000;
 
 pub struct ReporterTask;
 
@@ -16,14 +31,63 @@ impl Task for ReporterTask {
     #[instrument(skip(self, context))]
     async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
         let start_time = std::time::Instant::now();
+        let _permit = pools::acquire("reporter").await;
         info!("Starting report generation task");
 
-        let mut research_context: ResearchContext = context
-            .get("research_context")
+        let mut research_context = get_research_context(&context)
             .await
             .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
 
-        let prompt = format!(
+        let persona = research_context
+            .audience
+            .as_deref()
+            .map(|audience| format!("\n- {}", audience_instruction(audience)))
+            .unwrap_or_default();
+
+        // Estimate tokens at ~4 characters each and reserve room for the fixed
+        // instructions; the raw research data gets whatever budget is left.
+        let max_prompt_tokens = settings().report_max_prompt_tokens.unwrap_or(DEFAULT_MAX_PROMPT_TOKENS);
+        let fixed_chars = PROMPT_OVERHEAD_CHARS
+            + research_context.topic.len()
+            + research_context.questions.iter().map(|q| q.len() + 3).sum::<usize>()
+            + research_context.summary.len();
+        let data_budget = (max_prompt_tokens * CHARS_PER_TOKEN as usize).saturating_sub(fixed_chars);
+        let (research_data, trimmed) = research_data(
+            research_context.prepared_research_data.take(),
+            &research_context.research_results,
+            data_budget,
+        );
+        if trimmed > 0 {
+            warn!(
+                "Reporter prompt exceeded {} estimated tokens, trimmed {} lowest-scoring findings",
+                max_prompt_tokens, trimmed
+            );
+        }
+        research_context.report_truncated_input = trimmed > 0;
+
+        // HTML reports are generated as markdown and converted afterwards, which is
+        // more reliable than asking the model for well-formed HTML.
+        let (format_name, format_instruction) = match research_context.report_format {
+            ReportFormat::Markdown | ReportFormat::Html => {
+                ("markdown", "Use proper markdown formatting (headers, lists, etc.)")
+            }
+            ReportFormat::Plain => (
+                "plain-text",
+                "Do not use markdown or any other markup; separate sections with blank lines and UPPERCASE headings",
+            ),
+        };
+
+        let length = research_context
+            .report_max_words
+            .map(|words| format!("\n- Keep the report under {} words", words))
+            .unwrap_or_default();
+        let unanswerable = if research_context.research_results.iter().any(|result| result.unanswerable) {
+            "\n- For questions marked UNANSWERABLE, say briefly that no reliable sources were found instead of speculating"
+        } else {
+            ""
+        };
+
+        let report_prompt = |language: &str| format!(
             r#"You are a research assistant. Create a comprehensive research report about "{}" based on the following information:
 
 Research Questions:
@@ -36,31 +100,138 @@ Raw Research Data:
 {}
 
 Requirements:
-- Create a well-structured markdown report
+- Create a well-structured {} report
 - Include an executive summary
 - Organize findings by research question
 - Add a conclusion section
 - Include citations with URLs where appropriate
-- Use proper markdown formatting (headers, lists, etc.)
-- Make it professional and comprehensive"#,
+- {}
+- Make it professional and comprehensive
+- Write the report in {}{}{}{}"#,
+            research_context.topic,
+            research_context.questions.join("\n- "),
+            research_context.summary,
+            research_data,
+            format_name,
+            format_instruction,
+            language,
+            persona,
+            length,
+            unanswerable
+        );
+        let prompt = report_prompt(&research_context.detected_language);
+
+        let structured_prompt = format!(
+            r#"You are a research assistant. Create a structured research report about "{}" based on the following information:
+
+Research Questions:
+{}
+
+Summary of Findings:
+{}
+
+Raw Research Data:
+{}
+
+Requirements:
+- Write exactly one section per research question, in the order given
+- Keep each section to one or two paragraphs
+- Write the report in {}{}{}
+- Format: Return only a JSON object of the form {{"executive_summary": "...", "sections": [{{"question": "...", "content": "..."}}], "conclusion": "..."}}"#,
             research_context.topic,
             research_context.questions.join("\n- "),
             research_context.summary,
-            format_research_results(&research_context)
+            research_data,
+            research_context.detected_language,
+            persona,
+            unanswerable
+        );
+
+        let config = research_context.config.for_task("reporter");
+        let agent = get_llm(&config).map_err(GraphError::Other)?;
+        let structured_report = structured_or_prompted(
+            structured_output_enabled(),
+            "report extraction",
+            || async { extract_structured::<Report>(&config, &structured_prompt).await.map(Some) },
+            || async { prompt_llm(&agent, &structured_prompt).await.map(|response| parse_json::<Report>(&response)) },
         );
+        // Each extra output language gets its own report from the same
+        // research, generated alongside the main one.
+        let translations = join_all(
+            research_context
+                .output_languages
+                .iter()
+                .filter(|language| !language.eq_ignore_ascii_case(&research_context.detected_language))
+                .map(|language| {
+                    let prompt = report_prompt(language);
+                    let agent = &agent;
+                    async move { (language.clone(), prompt_llm(agent, &prompt).await) }
+                }),
+        );
+        let (report, structured, translations) =
+            futures::join!(prompt_llm_streaming(&agent, &prompt), structured_report, translations);
+        let report = report.map_err(GraphError::Other)?;
+        record_ttft(&context, "reporter", report.ttft_ms).await;
+
+        // An empty or few-character report would otherwise pass as success;
+        // regenerate it once with an explicit demand for the full report.
+        let min_chars = settings().min_report_chars.unwrap_or(DEFAULT_MIN_REPORT_CHARS);
+        let (report_text, retried) = retry_short_report(report.text, min_chars, || {
+            let stricter = format!(
+                "{}\n\nYour previous answer was empty or only a few characters long. Write the complete report now, with every section listed above.",
+                prompt
+            );
+            let agent = &agent;
+            async move { prompt_llm(agent, &stricter).await }
+        })
+        .await
+        .map_err(GraphError::Other)?;
+        research_context.report_retried = retried;
+        let format_report = |text: String| match research_context.report_format {
+            ReportFormat::Html => markdown_to_html(&text),
+            ReportFormat::Markdown | ReportFormat::Plain => text,
+        };
+        let report = format_report(report_text);
 
-        let agent = get_llm().map_err(GraphError::Other)?;
-        let report = agent.prompt(&prompt).await.map_err(|e| GraphError::Other(anyhow::anyhow!("Prompt error: {}", e)))?;
+        let mut reports = HashMap::new();
+        for language in &research_context.output_languages {
+            if language.eq_ignore_ascii_case(&research_context.detected_language) {
+                reports.insert(language.clone(), report.clone());
+            }
+        }
+        for (language, translated) in translations {
+            match translated {
+                Ok(text) => {
+                    reports.insert(language, format_report(text));
+                }
+                Err(e) => warn!("Report in {} failed, leaving it out: {}", language, e),
+            }
+        }
 
-        info!("Generated report with {} characters", report.len());
+        let structured_report = match structured {
+            Ok(report) => report,
+            Err(e) => {
+                warn!("Structured report prompt failed: {}", e);
+                None
+            }
+        };
+        match &structured_report {
+            Some(structured) if structured.sections.len() != research_context.questions.len() => warn!(
+                "Structured report has {} sections for {} questions",
+                structured.sections.len(),
+                research_context.questions.len()
+            ),
+            Some(_) => {}
+            None => warn!("Failed to parse structured report, returning markdown only"),
+        }
+
+        info!(task = "reporter", report_chars = report.len(), "Generated report");
         research_context.report = report;
-        context.set("research_context", research_context).await;
+        research_context.reports = reports;
+        research_context.structured_report = structured_report;
+        set_research_context(&context, research_context).await;
 
-        let elapsed = start_time.elapsed().as_millis() as u64;
-        let mut task_times: std::collections::HashMap<String, u64> = 
-            context.get("task_times").await.unwrap_or_default();
-        task_times.insert("reporter".to_string(), elapsed);
-        context.set("task_times", task_times).await;
+        record_task_time(&context, "reporter", start_time).await;
 
         Ok(TaskResult::new(
             Some("Report generated successfully".to_string()),
@@ -69,11 +240,129 @@ Requirements:
     }
 }
 
-fn format_research_results(context: &ResearchContext) -> String {
-    context
-        .research_results
+fn markdown_to_html(markdown: &str) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::all());
+    let mut html = String::with_capacity(markdown.len() * 3 / 2);
+    pulldown_cmark::html::push_html(&mut html, parser);
+    html
+}
+
+/// Replaces a report shorter than `min_chars` with one `retry`, returning the
+/// report to keep and whether it was retried.
+async fn retry_short_report<F, Fut>(report: String, min_chars: usize, retry: F) -> anyhow::Result<(String, bool)>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<String>>,
+{
+    let report_chars = report.trim().chars().count();
+    if report_chars >= min_chars {
+        return Ok((report, false));
+    }
+    warn!(report_chars, min_chars, "Report too short, retrying with a stricter prompt");
+    Ok((retry().await?, true))
+}
+
+/// Splits a markdown report at its top-level headers into `(title, content)`
+/// pairs. "Top-level" is the shallowest heading level used more than once, so a
+/// single `#` title above `##` sections still splits on the `##` headers. Text
+/// before the first header becomes an untitled leading section. Headers inside
+/// code blocks are ignored because the markdown is parsed rather than scanned.
+pub fn split_sections(markdown: &str) -> Vec<(String, String)> {
+    use pulldown_cmark::{Event, HeadingLevel, Tag, TagEnd};
+
+    let mut headings: Vec<(HeadingLevel, usize, String)> = Vec::new();
+    let mut current: Option<(HeadingLevel, usize, String)> = None;
+    for (event, range) in pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => current = Some((level, range.start, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, title)) = current.as_mut() {
+                    title.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+            _ => {}
+        }
+    }
+
+    let level = headings
+        .iter()
+        .map(|(level, ..)| *level)
+        .filter(|level| headings.iter().filter(|(other, ..)| other == level).count() > 1)
+        .min()
+        .or_else(|| headings.iter().map(|(level, ..)| *level).min());
+    let boundaries: Vec<(usize, String)> = headings
+        .into_iter()
+        .filter(|(heading_level, ..)| Some(*heading_level) == level)
+        .map(|(_, start, title)| (start, title.trim().to_string()))
+        .collect();
+
+    let mut sections = Vec::new();
+    let first = boundaries.first().map_or(markdown.len(), |(start, _)| *start);
+    if !markdown[..first].trim().is_empty() {
+        sections.push((String::new(), markdown[..first].trim().to_string()));
+    }
+    for (i, (start, title)) in boundaries.iter().enumerate() {
+        let end = boundaries.get(i + 1).map_or(markdown.len(), |(next, _)| *next);
+        sections.push((title.clone(), markdown[*start..end].trim().to_string()));
+    }
+    sections
+}
+
+/// Maps an audience to persona and reading-level instructions for the report.
+/// Unrecognized audiences are passed through as-is.
+fn audience_instruction(audience: &str) -> String {
+    match audience.trim().to_lowercase().as_str() {
+        "executive" | "executives" => "Write for busy executives: lead with business impact and recommended decisions, keep sections short and avoid technical jargon".to_string(),
+        "researcher" | "researchers" | "academic" => "Write for researchers: be precise, discuss methodology and limitations of the sources, and keep technical terminology".to_string(),
+        "general" | "general public" | "public" => "Write for the general public: use plain language at a high-school reading level and explain any technical terms".to_string(),
+        "technical" | "engineer" | "engineers" => "Write for engineers: focus on implementation details, trade-offs and concrete numbers".to_string(),
+        other => format!("Write for a {} audience, adjusting tone and reading level accordingly", other),
+    }
+}
+
+/// The research data for the prompt: what the summarizer prepared if it fits
+/// in `max_chars`, otherwise the results formatted and trimmed here.
+fn research_data(prepared: Option<String>, results: &[ResearchResult], max_chars: usize) -> (String, usize) {
+    match prepared {
+        Some(prepared) if prepared.len() <= max_chars => (prepared, 0),
+        _ => fit_research_results(results, max_chars),
+    }
+}
+
+/// Formats the research results within `max_chars`, dropping the lowest-scoring
+/// findings first. Returns the formatted data and how many findings were dropped.
+fn fit_research_results(results: &[ResearchResult], max_chars: usize) -> (String, usize) {
+    let mut results = results.to_vec();
+    let mut trimmed = 0;
+    loop {
+        let formatted = format_research_results(&results);
+        if formatted.len() <= max_chars {
+            return (formatted, trimmed);
+        }
+
+        let lowest = results
+            .iter()
+            .enumerate()
+            .flat_map(|(r, result)| result.findings.iter().enumerate().map(move |(f, finding)| (r, f, finding.score)))
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        match lowest {
+            Some((r, f, _)) => {
+                results[r].findings.remove(f);
+                trimmed += 1;
+            }
+            None => return (formatted, trimmed),
+        }
+    }
+}
+
+pub(super) fn format_research_results(results: &[ResearchResult]) -> String {
+    results
         .iter()
         .map(|result| {
+            if result.unanswerable {
+                return format!("Question: {}\nSources: none found (UNANSWERABLE)", result.question);
+            }
             format!(
                 "Question: {}\nSources:\n{}",
                 result.question,
@@ -87,4 +376,131 @@ fn format_research_results(context: &ResearchContext) -> String {
         })
         .collect::<Vec<_>>()
         .join("\n\n")
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structured_report_is_parsed_into_sections() {
+        let response = r#"{
+            "executive_summary": "Tokio leads.",
+            "sections": [{"question": "What is Tokio?", "content": "A runtime."}],
+            "conclusion": "Use Tokio."
+        }"#;
+        let report = parse_json::<Report>(response).unwrap();
+        assert_eq!(report.executive_summary, "Tokio leads.");
+        assert_eq!(report.sections.len(), 1);
+        assert_eq!(report.sections[0].question, "What is Tokio?");
+        assert_eq!(report.conclusion, "Use Tokio.");
+
+        assert!(parse_json::<Report>(r#"{"sections": []}"#).is_none());
+    }
+
+    #[test]
+    fn audiences_map_to_personas() {
+        assert!(audience_instruction(" Executives ").starts_with("Write for busy executives"));
+        assert!(audience_instruction("academic").starts_with("Write for researchers"));
+        assert_eq!(
+            audience_instruction("gardeners"),
+            "Write for a gardeners audience, adjusting tone and reading level accordingly"
+        );
+    }
+
+    fn finding(title: &str, score: f64) -> crate::models::Finding {
+        crate::models::Finding {
+            title: title.to_string(),
+            url: format!("https://example.com/{}", title),
+            content: "x".repeat(200),
+            score,
+            query: None,
+            confidence: None,
+        }
+    }
+
+    #[test]
+    fn oversized_research_drops_the_lowest_scoring_findings() {
+        let results = vec![ResearchResult {
+            question: "What is Tokio?".to_string(),
+            findings: vec![finding("weak", 0.1), finding("strong", 0.9), finding("middling", 0.5)],
+            unanswerable: false,
+        }];
+        let full = format_research_results(&results);
+        assert_eq!(fit_research_results(&results, full.len()), (full.clone(), 0));
+
+        let (fitted, trimmed) = fit_research_results(&results, full.len() / 2);
+        assert_eq!(trimmed, 2);
+        assert!(fitted.contains("strong"));
+        assert!(!fitted.contains("weak") && !fitted.contains("middling"));
+    }
+
+    #[test]
+    fn html_reports_are_rendered_from_markdown() {
+        let html = markdown_to_html("# Tokio\n\n- fast\n- | a |\n\n| x | y |\n|---|---|\n| 1 | 2 |");
+        assert!(html.contains("<h1>Tokio</h1>"));
+        assert!(html.contains("<li>fast</li>"));
+        assert!(html.contains("<table>"));
+        assert_eq!(serde_json::from_str::<ReportFormat>("\"html\"").unwrap(), ReportFormat::Html);
+        assert_eq!(ReportFormat::Plain.content_type(), "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn prepared_research_data_matches_formatting_it_here() {
+        let results = vec![ResearchResult {
+            question: "What is Tokio?".to_string(),
+            findings: vec![finding("strong", 0.9), finding("weak", 0.1)],
+            unanswerable: false,
+        }];
+        let prepared = format_research_results(&results);
+
+        let roomy = prepared.len() * 2;
+        assert_eq!(research_data(Some(prepared.clone()), &results, roomy), research_data(None, &results, roomy));
+
+        // Prepared data that no longer fits is re-trimmed rather than sent as is.
+        let tight = prepared.len() - 1;
+        assert_eq!(research_data(Some(prepared), &results, tight), research_data(None, &results, tight));
+        assert_eq!(research_data(None, &results, tight).1, 1);
+    }
+
+    #[test]
+    fn reports_split_at_their_top_level_headers() {
+        let report = "# Rust async runtimes\n\nAn overview.\n\n## Tokio\n\nThe default.\n\n```\n## not a header\n```\n\n## smol\n\n### Internals\n\nSmall.\n";
+        let sections = split_sections(report);
+        let titles: Vec<_> = sections.iter().map(|(title, _)| title.as_str()).collect();
+        assert_eq!(titles, ["", "Tokio", "smol"]);
+        assert_eq!(sections[0].1, "# Rust async runtimes\n\nAn overview.");
+        assert!(sections[1].1.contains("## not a header"));
+        assert!(sections[2].1.ends_with("Small."));
+
+        assert_eq!(split_sections("No headers at all."), [(String::new(), "No headers at all.".to_string())]);
+    }
+
+    #[test]
+    fn unanswerable_questions_are_flagged_in_the_research_data() {
+        let results = vec![ResearchResult {
+            question: "Who maintains glommio?".to_string(),
+            findings: vec![],
+            unanswerable: true,
+        }];
+        assert_eq!(
+            format_research_results(&results),
+            "Question: Who maintains glommio?\nSources: none found (UNANSWERABLE)"
+        );
+    }
+
+    #[tokio::test]
+    async fn too_short_reports_are_retried_once() {
+        let full = "# Rust async runtimes\n\nTokio is the most widely used runtime.".to_string();
+        let retried_report = full.clone();
+        let (report, retried) = retry_short_report("  ok ".to_string(), 20, || async { Ok(retried_report) })
+            .await
+            .unwrap();
+        assert_eq!((report.as_str(), retried), (full.as_str(), true));
+
+        let (report, retried) = retry_short_report(full.clone(), 20, || async { Err(anyhow::anyhow!("not retried")) })
+            .await
+            .unwrap();
+        assert_eq!((report, retried), (full, false));
+    }
+}
@@ -1,10 +1,74 @@
-use crate::models::{Finding, ResearchContext, ResearchResult};
-use crate::tools::{llm::get_llm_with_tool, tavily::TavilySearch};
+use crate::config::settings;
+use crate::context_keys::{get_research_context, set_research_context};
+use crate::models::{Finding, QuestionError, ResearchContext, ResearchResult, WorkflowConfig};
+use crate::tasks::{record_task_time, SubcallTimes};
+use crate::tools::dedup::semantic_dedup;
+use crate::tools::fetch_url::FetchUrl;
+use crate::tools::llm::{get_llm, parse_json, prompt_llm, LlmBuilder};
+use crate::tools::pools;
+use crate::tools::tavily::{is_quota_error, TavilySearch};
+use crate::tools::unicode::normalize_text;
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
 use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
-use rig::completion::Prompt;
-use tracing::{info, instrument};
+use rig::tool::Tool;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
+
+const DEFAULT_DEDUP_THRESHOLD: f64 = 0.92;
+
+/// The question under which provided documents are listed as findings.
+const DOCUMENTS_QUESTION: &str = "Provided documents";
+
+/// What to do with a question whose search came back empty, from
+/// `NO_FINDINGS_POLICY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoFindingsPolicy {
+    /// Keep the empty result as-is.
+    #[default]
+    Skip,
+    /// Search once more with a broader reformulation of the question.
+    Retry,
+    /// Flag the result as unanswerable for the reporter.
+    Mark,
+}
+
+impl FromStr for NoFindingsPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "retry" => Ok(NoFindingsPolicy::Retry),
+            "mark" => Ok(NoFindingsPolicy::Mark),
+            "" | "skip" => Ok(NoFindingsPolicy::Skip),
+            other => Err(format!("unknown policy {:?}, expected skip, retry or mark", other)),
+        }
+    }
+}
+
+impl NoFindingsPolicy {
+    /// Under `Retry`, replaces an empty first search with `broader_search`.
+    async fn recover<F, Fut>(self, findings: Vec<Finding>, broader_search: F) -> anyhow::Result<Vec<Finding>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = anyhow::Result<Vec<Finding>>>,
+    {
+        if findings.is_empty() && self == NoFindingsPolicy::Retry {
+            broader_search().await
+        } else {
+            Ok(findings)
+        }
+    }
+
+    /// Whether a result with these findings is reported as unanswerable.
+    fn marks_unanswerable(self, findings: &[Finding]) -> bool {
+        findings.is_empty() && self == NoFindingsPolicy::Mark
+    }
+}
 
 pub struct ResearcherTask;
 
@@ -19,64 +83,425 @@ impl Task for ResearcherTask {
         let start_time = std::time::Instant::now();
         info!("Starting research task");
 
-        let mut research_context: ResearchContext = context
-            .get("research_context")
+        let mut research_context = get_research_context(&context)
             .await
             .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
 
-        let search_futures = research_context.questions.iter().map(|question| {
-            let question = question.clone();
+        // Questions the summarizer asked for on an iterative pass are
+        // researched on top of the earlier results.
+        let next_questions = std::mem::take(&mut research_context.next_questions);
+        let iterating = !next_questions.is_empty();
+
+        if research_context.findings_reused && !iterating {
+            info!("Reusing {} research results from the replayed session", research_context.research_results.len());
+            return Ok(TaskResult::new(
+                Some("Using reused findings".to_string()),
+                NextAction::Continue,
+            ));
+        }
+
+        // A hung search or LLM call for one question is recorded as that
+        // question's error instead of holding up the whole batch.
+        let question_timeout = settings().question_timeout_secs.map(Duration::from_secs);
+        let no_findings = settings().no_findings_policy;
+        let config = research_context.config.for_task("researcher");
+        let config = &config;
+        let search = TavilySearch::new(config.search_depth()).with_domains(
+            research_context.include_domains.clone(),
+            research_context.exclude_domains.clone(),
+        );
+        let mut questions = if iterating {
+            info!(
+                iteration = research_context.research_iterations + 1,
+                "Researching {} follow-up questions",
+                next_questions.len()
+            );
+            research_context.questions.extend(next_questions.iter().cloned());
+            next_questions
+        } else {
+            research_context.research_results.clear();
+            research_context.question_errors.clear();
+            research_context.skipped_questions.clear();
+            research_context.questions.clone()
+        };
+        // Extraction may produce more questions than are worth paying to
+        // search; the rest are still reported, as skipped.
+        let skipped = cap_questions(&mut questions, settings().max_researched_questions);
+        research_context.skipped_questions.extend(skipped);
+        let subcall_times = SubcallTimes::from_env();
+        let subcalls = &subcall_times;
+        let mut pending: FuturesUnordered<_> = questions.into_iter().map(|question| {
+            let search = search.clone();
             async move {
+                let _permit = pools::acquire("researcher").await;
                 info!("Researching question: {}", question);
-                research_question(question).await
+                let started = std::time::Instant::now();
+                let research = in_question_span(
+                    &question,
+                    research_question(question.clone(), config, search, no_findings, subcalls),
+                );
+                let result = with_question_timeout(question_timeout, research).await;
+                subcalls.record(format!("{} / total", question), started.elapsed());
+                result.map_err(|e| QuestionError {
+                    question,
+                    error: e.to_string(),
+                })
             }
-        });
+        }).collect();
 
-        let results = join_all(search_futures).await;
-        
-        research_context.research_results = results
-            .into_iter()
-            .filter_map(|r| r.ok())
-            .collect();
+        record_as_completed(&context, &mut research_context, &mut pending).await;
+        drop(pending);
+        subcall_times.save(&context, "researcher").await;
 
-        info!("Completed research for {} questions", research_context.research_results.len());
-        context.set("research_context", research_context).await;
+        // Completion order varies from run to run; `ORDERED_RESULTS` restores
+        // question order so benchmark outputs diff cleanly.
+        if settings().ordered_results {
+            order_by_questions(&mut research_context.research_results, &research_context.questions);
+        }
 
-        let elapsed = start_time.elapsed().as_millis() as u64;
-        let mut task_times: std::collections::HashMap<String, u64> = 
-            context.get("task_times").await.unwrap_or_default();
-        task_times.insert("researcher".to_string(), elapsed);
-        context.set("task_times", task_times).await;
+        if settings().semantic_dedup {
+            let threshold = settings().semantic_dedup_threshold.unwrap_or(DEFAULT_DEDUP_THRESHOLD);
+            if let Err(e) = semantic_dedup(&mut research_context.research_results, threshold).await {
+                warn!("Semantic dedup failed, keeping all findings: {}", e);
+            }
+        }
+
+        if !research_context.documents.is_empty() && !iterating {
+            info!("Including {} provided documents as findings", research_context.documents.len());
+            research_context.research_results.push(document_results(&research_context.documents));
+        }
+
+        let finding_count = research_context.research_results.iter().map(|r| r.findings.len()).sum::<usize>();
+        let search_finding_count = count_search_findings(&research_context.research_results);
+        info!(
+            task = "researcher",
+            research_count = research_context.research_results.len(),
+            finding_count,
+            search_finding_count,
+            "Completed research"
+        );
+
+        let gate = findings_gate(
+            &mut research_context,
+            iterating,
+            search_finding_count,
+            settings().min_total_findings,
+            settings().min_findings_retries.unwrap_or(0),
+        );
+        match gate {
+            FindingsGate::Pass => {}
+            FindingsGate::Retry => {
+                set_research_context(&context, research_context).await;
+                record_task_time(&context, "researcher", start_time).await;
+                return Ok(TaskResult::new(
+                    Some("Too few findings, researching again".to_string()),
+                    NextAction::GoTo("researcher".to_string()),
+                ));
+            }
+            FindingsGate::Fail(min_findings) => {
+                return Err(GraphError::Other(anyhow::anyhow!(
+                    "Insufficient findings: {} of the required {}",
+                    search_finding_count,
+                    min_findings
+                )));
+            }
+        }
+        set_research_context(&context, research_context).await;
+
+        record_task_time(&context, "researcher", start_time).await;
 
         Ok(TaskResult::new(
             Some("Research completed successfully".to_string()),
-            NextAction::ContinueAndExecute,
+            NextAction::Continue,
         ))
     }
 }
 
-async fn research_question(question: String) -> anyhow::Result<ResearchResult> {
-    let tavily = TavilySearch;
-    let agent = get_llm_with_tool(tavily).map_err(anyhow::Error::from)?;
+/// What the `MIN_TOTAL_FINDINGS` gate makes of a researcher pass.
+#[derive(Debug, PartialEq, Eq)]
+enum FindingsGate {
+    Pass,
+    /// Search again, within `MIN_FINDINGS_RETRIES`.
+    Retry,
+    /// Fail the run for missing the minimum.
+    Fail(usize),
+}
+
+/// A handful of findings still yields a report, but a hollow one; gate on
+/// `min_findings` so such runs fail or search again instead. Provided
+/// documents are always there, so only search findings count. Follow-up
+/// passes add to results that already passed, so only the initial pass and
+/// its retries are counted against `retries` and gated.
+fn findings_gate(
+    research_context: &mut ResearchContext,
+    iterating: bool,
+    search_finding_count: usize,
+    min_findings: Option<usize>,
+    retries: u32,
+) -> FindingsGate {
+    if iterating {
+        return FindingsGate::Pass;
+    }
+    research_context.research_attempts += 1;
+    let Some(min_findings) = min_findings.filter(|min| search_finding_count < *min) else {
+        return FindingsGate::Pass;
+    };
+    if research_context.research_attempts <= retries {
+        warn!(
+            search_finding_count,
+            min_findings,
+            attempt = research_context.research_attempts,
+            "Too few findings, researching again"
+        );
+        FindingsGate::Retry
+    } else {
+        FindingsGate::Fail(min_findings)
+    }
+}
+
+/// Turns user-provided documents into findings with synthetic `doc://` URLs so
+/// the summarizer and reporter can cite them like any other source.
+fn document_results(documents: &[String]) -> ResearchResult {
+    ResearchResult {
+        question: DOCUMENTS_QUESTION.to_string(),
+        findings: documents
+            .iter()
+            .enumerate()
+            .map(|(i, content)| Finding {
+                title: format!("Document {}", i + 1),
+                url: format!("doc://{}", i + 1),
+                content: content.clone(),
+                score: 1.0,
+                query: None,
+                confidence: None,
+            })
+            .collect(),
+        unanswerable: false,
+    }
+}
+
+/// Findings that came from search, leaving out the provided documents.
+fn count_search_findings(results: &[ResearchResult]) -> usize {
+    results
+        .iter()
+        .filter(|result| result.question != DOCUMENTS_QUESTION)
+        .map(|result| result.findings.len())
+        .sum()
+}
+
+/// Stable-sorts `results` into the order of `questions`. Results for
+/// questions not in the list keep their relative order at the end.
+fn order_by_questions(results: &mut [ResearchResult], questions: &[String]) {
+    results.sort_by_key(|result| {
+        questions
+            .iter()
+            .position(|question| *question == result.question)
+            .unwrap_or(usize::MAX)
+    });
+}
+
+/// Parses the comma-separated `RESEARCHER_TOOLS` (currently only
+/// `fetch_url`), rejecting names the researcher cannot register.
+pub fn parse_researcher_tools(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| match name {
+            FetchUrl::NAME => Ok(name.to_string()),
+            unknown => Err(format!("unknown researcher tool {:?}", unknown)),
+        })
+        .collect()
+}
+
+/// Registers Tavily plus the extra `tools`, as parsed from `RESEARCHER_TOOLS`.
+fn researcher_agent(builder: LlmBuilder, search: TavilySearch, tools: &[String]) -> LlmBuilder {
+    let mut builder = builder.tool(search);
+    for name in tools {
+        if name == FetchUrl::NAME {
+            builder = builder.tool(FetchUrl);
+        }
+    }
+    builder
+}
+
+/// Keeps the first `limit` questions for research and returns the rest.
+fn cap_questions(questions: &mut Vec<String>, limit: Option<usize>) -> Vec<String> {
+    match limit.filter(|limit| questions.len() > *limit) {
+        Some(limit) => {
+            let skipped = questions.split_off(limit);
+            info!(researched = questions.len(), skipped = skipped.len(), "Capping researched questions");
+            skipped
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Takes results in completion order and writes the context back after each
+/// one, so a client polling the session sees findings as they land.
+async fn record_as_completed<S>(context: &Context, research_context: &mut ResearchContext, pending: &mut S)
+where
+    S: futures::Stream<Item = Result<ResearchResult, QuestionError>> + Unpin,
+{
+    while let Some(result) = pending.next().await {
+        match result {
+            Ok(result) => {
+                info!(
+                    question = %result.question,
+                    finding_count = result.findings.len(),
+                    "Question researched"
+                );
+                research_context.research_results.push(result);
+            }
+            Err(failure) => {
+                warn!("Research failed for question '{}': {}", failure.question, failure.error);
+                if is_quota_error(&failure.error) {
+                    research_context.search_unavailable =
+                        Some("Web search was unavailable because the Tavily quota is exhausted".to_string());
+                }
+                research_context.question_errors.push(failure);
+            }
+        }
+        set_research_context(context, research_context.clone()).await;
+    }
+}
+
+/// Turns research still running after `limit` into a timeout error for that
+/// question alone, so one hung call does not hold up the whole fan-out.
+async fn with_question_timeout<F>(limit: Option<Duration>, research: F) -> anyhow::Result<ResearchResult>
+where
+    F: std::future::Future<Output = anyhow::Result<ResearchResult>>,
+{
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, research)
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out after {}s", limit.as_secs()))),
+        None => research.await,
+    }
+}
+
+/// Runs one question's research in its own `research_question` span, a child
+/// of the researcher task's span, so trace UIs show the fan-out as one branch
+/// per question with the number of findings it produced.
+#[instrument(name = "research_question", skip_all, fields(question = %question, finding_count = tracing::field::Empty))]
+async fn in_question_span<F>(question: &str, research: F) -> anyhow::Result<ResearchResult>
+where
+    F: std::future::Future<Output = anyhow::Result<ResearchResult>>,
+{
+    let result = research.await;
+    if let Ok(result) = &result {
+        tracing::Span::current().record("finding_count", result.findings.len());
+    }
+    result
+}
+
+async fn research_question(
+    question: String,
+    config: &WorkflowConfig,
+    search: TavilySearch,
+    no_findings: NoFindingsPolicy,
+    subcalls: &SubcallTimes,
+) -> anyhow::Result<ResearchResult> {
+    let search = search.tracked();
+    let builder = researcher_agent(LlmBuilder::new(config)?, search.clone(), &settings().researcher_tools);
+    let tools = builder.tool_names().join(", ");
+    debug!("Researcher tools: {}", tools);
+    let agent = builder.build();
 
     let prompt = format!(
         r#"Search for information to answer this research question: "{}"
 
-Use the tavily_search tool to find relevant information. Search for specific, factual information that directly addresses the question."#,
-        question
+Use the tavily_search tool to find relevant information. Search for specific, factual information that directly addresses the question.
+Available tools: {}"#,
+        question, tools
     );
 
-    let response = agent.prompt(&prompt).await.map_err(|e| anyhow::anyhow!("Prompt error: {}", e))?;
-    
-    let findings = parse_search_results(&response);
+    let per_domain = settings().max_findings_per_domain.filter(|limit| *limit > 0);
+    let started = std::time::Instant::now();
+    let response = prompt_llm(&agent, &prompt).await?;
+    let mut agent_time = started.elapsed();
+    let started = std::time::Instant::now();
+    let findings = parse_search_results(&response, config.findings_per_question(), per_domain);
+    subcalls.record(format!("{} / parse", question), started.elapsed());
+
+    let mut findings = no_findings.recover(findings, || async {
+        info!(question = %question, "No findings, retrying with a broader query");
+        let broader = format!(
+            r#"A search for this research question returned no usable results: "{}"
+
+Reformulate it as a broader, more general search query (drop narrow qualifiers, dates and jargon) and use the tavily_search tool once with that query.
+Available tools: {}"#,
+            question, tools
+        );
+        let started = std::time::Instant::now();
+        let response = prompt_llm(&agent, &broader).await?;
+        agent_time += started.elapsed();
+        let started = std::time::Instant::now();
+        let findings = parse_search_results(&response, config.findings_per_question(), per_domain);
+        subcalls.record(format!("{} / parse", question), started.elapsed());
+        Ok(findings)
+    })
+    .await?;
+    // The agent call includes its Tavily tool calls; split them out.
+    subcalls.record(format!("{} / search", question), search.search_time());
+    subcalls.record(format!("{} / llm", question), agent_time.saturating_sub(search.search_time()));
+    for finding in &mut findings {
+        finding.query = search.query_for(&finding.url);
+    }
+    if settings().score_findings && !findings.is_empty() {
+        let started = std::time::Instant::now();
+        if let Err(e) = score_findings(config, &question, &mut findings).await {
+            warn!(question = %question, "Finding scoring failed, leaving findings unscored: {}", e);
+        }
+        subcalls.record(format!("{} / score", question), started.elapsed());
+    }
 
     Ok(ResearchResult {
+        unanswerable: no_findings.marks_unanswerable(&findings),
         question,
         findings,
     })
 }
 
-fn parse_search_results(response: &str) -> Vec<Finding> {
+/// Asks the model how well each finding answers `question` and stores the
+/// 0-1 ratings in `confidence`. Costs one extra call per question, hence
+/// `SCORE_FINDINGS`.
+async fn score_findings(config: &WorkflowConfig, question: &str, findings: &mut [Finding]) -> anyhow::Result<()> {
+    let agent = get_llm(config)?;
+    let listing = findings
+        .iter()
+        .enumerate()
+        .map(|(i, f)| format!("{}. {}: {}", i + 1, f.title, f.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let prompt = format!(
+        r#"Rate how well each numbered finding answers the research question "{}", from 0 (irrelevant) to 1 (answers it directly and fully).
+
+{}
+
+Format: Return only a JSON array with one number per finding, in the same order."#,
+        question, listing
+    );
+    let response = prompt_llm(&agent, &prompt).await?;
+    let scores: Vec<f64> = parse_json(&response)
+        .ok_or_else(|| anyhow::anyhow!("Could not parse confidence scores: {}", response))?;
+    apply_confidences(findings, &scores);
+    Ok(())
+}
+
+/// Attaches `scores` to `findings` by position, clamped to 0-1. Findings
+/// past the end of a short list stay unscored.
+fn apply_confidences(findings: &mut [Finding], scores: &[f64]) {
+    for (finding, score) in findings.iter_mut().zip(scores) {
+        finding.confidence = Some(score.clamp(0.0, 1.0));
+    }
+}
+
+/// Parses the agent's echoed search results, keeping at most `max_findings`
+/// in order and, with `per_domain` set, no more than that many from any one
+/// domain so a single site cannot fill every slot.
+fn parse_search_results(response: &str, max_findings: usize, per_domain: Option<usize>) -> Vec<Finding> {
+    let mut domain_counts: HashMap<String, usize> = HashMap::new();
     response
         .split("---")
         .filter_map(|section| {
@@ -94,6 +519,11 @@ fn parse_search_results(response: &str) -> Vec<Finding> {
                     .unwrap_or("")
                     .to_string();
                 
+                let score = lines.iter()
+                    .find(|l| l.starts_with("Score:"))
+                    .and_then(|l| l.trim_start_matches("Score:").trim().parse().ok())
+                    .unwrap_or(0.0);
+
                 let content = lines.iter()
                     .find(|l| l.starts_with("Content:"))
                     .map(|l| l.trim_start_matches("Content:").trim())
@@ -101,7 +531,14 @@ fn parse_search_results(response: &str) -> Vec<Finding> {
                     .to_string();
 
                 if !title.is_empty() && !url.is_empty() {
-                    Some(Finding { title, url, content })
+                    Some(Finding {
+                        title: normalize_text(&title),
+                        url,
+                        content: normalize_text(&content),
+                        score,
+                        query: None,
+                        confidence: None,
+                    })
                 } else {
                     None
                 }
@@ -109,6 +546,358 @@ fn parse_search_results(response: &str) -> Vec<Finding> {
                 None
             }
         })
-        .take(3)
+        .filter(|finding| match per_domain {
+            Some(limit) => {
+                let count = domain_counts.entry(domain_of(&finding.url)).or_default();
+                *count += 1;
+                *count <= limit
+            }
+            None => true,
+        })
+        .take(max_findings)
         .collect()
-}
\ No newline at end of file
+}
+
+/// Lowercase host of `url` without a leading `www.`, or the whole string if
+/// it has no recognizable host.
+fn domain_of(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or(host).to_lowercase();
+    host.strip_prefix("www.").map(str::to_string).unwrap_or(host)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Instrument, Subscriber};
+    use tracing_subscriber::layer::{Context as LayerContext, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    #[derive(Debug, Default, Clone)]
+    struct CapturedSpan {
+        name: String,
+        parent: Option<String>,
+        fields: HashMap<String, String>,
+    }
+
+    impl Visit for CapturedSpan {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.fields.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    /// Keeps every span's name, parent and recorded fields.
+    #[derive(Clone, Default)]
+    struct SpanCapture(Arc<Mutex<Vec<(Id, CapturedSpan)>>>);
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanCapture {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+            let mut span = CapturedSpan {
+                name: attrs.metadata().name().to_string(),
+                parent: ctx.span(id).and_then(|span| span.parent()).map(|parent| parent.name().to_string()),
+                ..Default::default()
+            };
+            attrs.record(&mut span);
+            self.0.lock().unwrap().push((id.clone(), span));
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, _ctx: LayerContext<'_, S>) {
+            if let Some((_, span)) = self.0.lock().unwrap().iter_mut().find(|(span_id, _)| span_id == id) {
+                values.record(span);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn each_question_gets_a_child_span_with_its_finding_count() {
+        let capture = SpanCapture::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+        let answered = |question: &str, findings: usize| ResearchResult {
+            question: question.to_string(),
+            findings: document_results(&vec!["content".to_string(); findings]).findings,
+            unanswerable: false,
+        };
+        let fan_out = async {
+            futures::join!(
+                in_question_span("What is Tokio?", async { Ok(answered("What is Tokio?", 2)) }),
+                in_question_span("What is async-std?", async { Err(anyhow::anyhow!("search failed")) }),
+            )
+        };
+        let (first, second) = fan_out.instrument(tracing::info_span!("researcher")).await;
+        assert_eq!(first.unwrap().findings.len(), 2);
+        assert!(second.is_err());
+
+        let spans: Vec<CapturedSpan> = capture
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, span)| span.clone())
+            .filter(|span| span.name == "research_question")
+            .collect();
+        assert_eq!(spans.len(), 2);
+        assert!(spans.iter().all(|span| span.parent.as_deref() == Some("researcher")));
+        assert_eq!(spans[0].fields["question"], "What is Tokio?");
+        assert_eq!(spans[0].fields["finding_count"], "2");
+        assert_eq!(spans[1].fields["question"], "What is async-std?");
+        assert!(!spans[1].fields.contains_key("finding_count"));
+    }
+
+    #[test]
+    fn provided_documents_do_not_count_as_search_findings() {
+        let searched = ResearchResult {
+            question: "What is Tokio?".to_string(),
+            findings: document_results(&["A runtime".to_string()]).findings,
+            unanswerable: false,
+        };
+        let documents = document_results(&["First".to_string(), "Second".to_string(), "Third".to_string()]);
+        assert_eq!(count_search_findings(&[documents.clone()]), 0);
+        assert_eq!(count_search_findings(&[searched, documents]), 1);
+    }
+
+    #[test]
+    fn provided_documents_become_citable_findings() {
+        let result = document_results(&["First text".to_string(), "Second text".to_string()]);
+        assert_eq!(result.question, DOCUMENTS_QUESTION);
+        assert!(!result.unanswerable);
+        let urls: Vec<&str> = result.findings.iter().map(|f| f.url.as_str()).collect();
+        assert_eq!(urls, ["doc://1", "doc://2"]);
+        assert_eq!(result.findings[1].title, "Document 2");
+        assert_eq!(result.findings[1].content, "Second text");
+    }
+
+    #[test]
+    fn researcher_agent_registers_the_configured_tools() {
+        let client = rig::providers::openai::Client::new("test-key");
+        let config = WorkflowConfig::default();
+        let agent = |tools: &str| {
+            let tools = parse_researcher_tools(tools).unwrap();
+            researcher_agent(LlmBuilder::with_client(&client, &config), TavilySearch::new("basic"), &tools)
+        };
+
+        assert_eq!(agent("").tool_names(), [TavilySearch::NAME]);
+        assert_eq!(agent("fetch_url").tool_names(), [TavilySearch::NAME, FetchUrl::NAME]);
+        assert!(parse_researcher_tools("fetch_url, calculator").is_err());
+    }
+
+    #[tokio::test]
+    async fn slow_question_times_out_without_holding_up_the_others() {
+        let limit = Some(Duration::from_millis(50));
+        let answer = |question: &str, delay: Duration| {
+            let result = ResearchResult {
+                question: question.to_string(),
+                findings: vec![],
+                unanswerable: true,
+            };
+            with_question_timeout(limit, async move {
+                tokio::time::sleep(delay).await;
+                Ok(result)
+            })
+        };
+
+        let started = std::time::Instant::now();
+        let (fast, hung, quick) = futures::join!(
+            answer("What is Tokio?", Duration::ZERO),
+            answer("What is async-std?", Duration::from_secs(30)),
+            answer("What is smol?", Duration::from_millis(5)),
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(fast.unwrap().question, "What is Tokio?");
+        assert_eq!(quick.unwrap().question, "What is smol?");
+        assert!(hung.unwrap_err().to_string().starts_with("timed out"));
+    }
+
+    #[tokio::test]
+    async fn results_are_recorded_in_completion_order() {
+        let answer_after = |question: &'static str, millis: u64| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            if question.contains("fail") {
+                Err(QuestionError {
+                    question: question.to_string(),
+                    error: "search failed".to_string(),
+                })
+            } else {
+                Ok(ResearchResult {
+                    question: question.to_string(),
+                    findings: vec![],
+                    unanswerable: true,
+                })
+            }
+        };
+        let mut pending: FuturesUnordered<_> = [("slow", 120), ("fast", 0), ("fail", 40), ("middle", 80)]
+            .into_iter()
+            .map(|(question, millis)| answer_after(question, millis))
+            .collect();
+
+        let context = Context::new();
+        let mut research_context = ResearchContext::default();
+        record_as_completed(&context, &mut research_context, &mut pending).await;
+
+        let order: Vec<&str> = research_context.research_results.iter().map(|r| r.question.as_str()).collect();
+        assert_eq!(order, ["fast", "middle", "slow"]);
+        assert_eq!(research_context.question_errors[0].question, "fail");
+        let stored = get_research_context(&context).await.unwrap();
+        assert_eq!(stored.research_results.len(), 3);
+    }
+
+    #[test]
+    fn only_the_first_questions_up_to_the_cap_are_researched() {
+        let extracted = || ["What?", "Why?", "How?", "Who?"].map(String::from).to_vec();
+
+        let mut questions = extracted();
+        assert_eq!(cap_questions(&mut questions, Some(2)), ["How?", "Who?"]);
+        assert_eq!(questions, ["What?", "Why?"]);
+
+        let mut questions = extracted();
+        assert!(cap_questions(&mut questions, Some(10)).is_empty());
+        assert!(cap_questions(&mut questions, None).is_empty());
+        assert_eq!(questions.len(), 4);
+    }
+
+    fn search_results(urls: &[&str]) -> String {
+        urls.iter()
+            .enumerate()
+            .map(|(i, url)| format!("Title: Result {i}\nURL: {url}\nScore: 0.9\nContent: About tokio"))
+            .collect::<Vec<_>>()
+            .join("\n---\n")
+    }
+
+    #[test]
+    fn findings_per_domain_are_capped() {
+        let response = search_results(&[
+            "https://tokio.rs/a",
+            "https://www.tokio.rs/b",
+            "https://TOKIO.rs/c",
+            "https://docs.rs/tokio",
+            "https://tokio.rs/d",
+            "https://github.com/tokio-rs/tokio",
+        ]);
+        let urls = |findings: Vec<Finding>| findings.into_iter().map(|f| f.url).collect::<Vec<_>>();
+
+        assert_eq!(
+            urls(parse_search_results(&response, 10, Some(2))),
+            ["https://tokio.rs/a", "https://www.tokio.rs/b", "https://docs.rs/tokio", "https://github.com/tokio-rs/tokio"]
+        );
+        assert_eq!(
+            urls(parse_search_results(&response, 3, Some(1))),
+            ["https://tokio.rs/a", "https://docs.rs/tokio", "https://github.com/tokio-rs/tokio"]
+        );
+        assert_eq!(parse_search_results(&response, 10, None).len(), 6);
+    }
+
+    #[tokio::test]
+    async fn out_of_order_results_are_put_back_in_question_order() {
+        let questions = ["What is Tokio?", "Who maintains smol?", "How fast is glommio?"].map(String::from);
+        let answer_after = |question: String, millis: u64| async move {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok::<_, QuestionError>(ResearchResult {
+                question,
+                findings: vec![],
+                unanswerable: true,
+            })
+        };
+        let mut pending: FuturesUnordered<_> = questions
+            .iter()
+            .cloned()
+            .zip([80, 0, 40])
+            .map(|(question, millis)| answer_after(question, millis))
+            .collect();
+        let mut research_context = ResearchContext::default();
+        record_as_completed(&Context::new(), &mut research_context, &mut pending).await;
+        research_context.research_results.push(ResearchResult {
+            question: "Provided documents".to_string(),
+            findings: vec![],
+            unanswerable: false,
+        });
+
+        order_by_questions(&mut research_context.research_results, &questions);
+        let order: Vec<&str> = research_context.research_results.iter().map(|r| r.question.as_str()).collect();
+        assert_eq!(order, ["What is Tokio?", "Who maintains smol?", "How fast is glommio?", "Provided documents"]);
+    }
+
+    #[tokio::test]
+    async fn each_no_findings_policy_handles_an_empty_search() {
+        let broader = vec![Finding {
+            title: "Tokio overview".to_string(),
+            url: "https://tokio.rs".to_string(),
+            content: "An asynchronous runtime for Rust".to_string(),
+            score: 0.8,
+            query: None,
+            confidence: None,
+        }];
+        let outcome = |policy: NoFindingsPolicy| {
+            let broader = broader.clone();
+            async move {
+                let retried = std::sync::atomic::AtomicBool::new(false);
+                let findings = policy
+                    .recover(vec![], || async {
+                        retried.store(true, std::sync::atomic::Ordering::Relaxed);
+                        Ok(broader)
+                    })
+                    .await
+                    .unwrap();
+                let unanswerable = policy.marks_unanswerable(&findings);
+                (retried.into_inner(), findings.len(), unanswerable)
+            }
+        };
+
+        assert_eq!(outcome(NoFindingsPolicy::Skip).await, (false, 0, false));
+        assert_eq!(outcome(NoFindingsPolicy::Retry).await, (true, 1, false));
+        assert_eq!(outcome(NoFindingsPolicy::Mark).await, (false, 0, true));
+
+        // A search that found something is left alone by every policy.
+        let found = NoFindingsPolicy::Retry
+            .recover(broader.clone(), || async { Err(anyhow::anyhow!("no retry needed")) })
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(!NoFindingsPolicy::Mark.marks_unanswerable(&found));
+    }
+
+    #[test]
+    fn confidences_attach_to_findings_by_position() {
+        let mut findings = parse_search_results(
+            &search_results(&["https://tokio.rs", "https://docs.rs/tokio", "https://blog.example", "https://forum.example"]),
+            10,
+            None,
+        );
+        // What a stubbed scoring call answers: out-of-range values and one score too few.
+        let scores: Vec<f64> = parse_json("```json\n[0.9, 1.4, -0.2]\n```").unwrap();
+        apply_confidences(&mut findings, &scores);
+
+        let confidences: Vec<_> = findings.iter().map(|f| (f.url.as_str(), f.confidence)).collect();
+        assert_eq!(
+            confidences,
+            [
+                ("https://tokio.rs", Some(0.9)),
+                ("https://docs.rs/tokio", Some(1.0)),
+                ("https://blog.example", Some(0.0)),
+                ("https://forum.example", None),
+            ]
+        );
+        assert_eq!(findings[0].confidence_note(), " [confidence 0.90]");
+        assert_eq!(findings[3].confidence_note(), "");
+    }
+
+    #[test]
+    fn follow_up_passes_neither_spend_retries_nor_fail_the_gate() {
+        let mut research_context = ResearchContext::default();
+        // A thin first pass retries once, then a good one passes.
+        assert_eq!(findings_gate(&mut research_context, false, 1, Some(5), 1), FindingsGate::Retry);
+        assert_eq!(findings_gate(&mut research_context, false, 6, Some(5), 1), FindingsGate::Pass);
+        // Thin follow-up passes after that are not gated or counted.
+        for _ in 0..3 {
+            assert_eq!(findings_gate(&mut research_context, true, 0, Some(5), 1), FindingsGate::Pass);
+        }
+        assert_eq!(research_context.research_attempts, 2);
+
+        let mut exhausted = ResearchContext::default();
+        assert_eq!(findings_gate(&mut exhausted, false, 1, Some(5), 0), FindingsGate::Fail(5));
+    }
+}
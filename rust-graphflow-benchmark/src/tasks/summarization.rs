@@ -0,0 +1,372 @@
+use crate::config::settings;
+use crate::models::{GroundedClaim, ResearchContext, ResearchResult};
+use crate::tools::llm::{
+    extract_structured, get_llm, parse_json, prompt_llm, prompt_llm_streaming, structured_or_prompted,
+    structured_output_enabled,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::join_all;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::str::FromStr;
+use tracing::warn;
+
+const DEFAULT_RELEVANCE_MIN_SCORE: f64 = 0.2;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Summary {
+    pub summary: String,
+    pub key_points: Vec<String>,
+    /// Gaps worth another research pass; only requested while
+    /// `MAX_RESEARCH_ITERATIONS` allows one.
+    #[serde(default)]
+    pub follow_up_questions: Vec<String>,
+    /// Each claim with its originating question and sources; only requested
+    /// under `REQUIRE_GROUNDING`.
+    #[serde(default)]
+    pub grounding: Vec<GroundedClaim>,
+    /// Time-to-first-token of the final summarization call.
+    #[serde(skip)]
+    pub ttft_ms: Option<u64>,
+}
+
+/// A way of condensing the research findings into a summary. Selected per
+/// process with `SUMMARY_STRATEGY`.
+#[async_trait]
+pub trait SummarizationStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn summarize(&self, context: &ResearchContext) -> Result<Summary>;
+}
+
+/// The strategies `SUMMARY_STRATEGY` can name (`single_pass` or
+/// `map_reduce`), defaulting to single-pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryStrategy {
+    #[default]
+    SinglePass,
+    MapReduce,
+}
+
+impl FromStr for SummaryStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "single_pass" => Ok(SummaryStrategy::SinglePass),
+            "map_reduce" => Ok(SummaryStrategy::MapReduce),
+            other => Err(format!("unknown strategy {:?}, expected single_pass or map_reduce", other)),
+        }
+    }
+}
+
+/// Returns the configured `SUMMARY_STRATEGY`.
+pub fn configured_strategy() -> Box<dyn SummarizationStrategy> {
+    match settings().summary_strategy {
+        SummaryStrategy::SinglePass => Box::new(SinglePassStrategy),
+        SummaryStrategy::MapReduce => Box::new(MapReduceStrategy),
+    }
+}
+
+/// Caps how many findings per question reach the summarizer, keeping the
+/// highest-scoring ones, via `SUMMARY_MAX_FINDINGS_PER_Q`. The reporter still
+/// sees everything the researcher kept.
+fn max_findings_per_question() -> Option<usize> {
+    settings().summary_max_findings_per_q.filter(|k| *k > 0)
+}
+
+/// How many research and summary passes a run may make, from
+/// `MAX_RESEARCH_ITERATIONS`. The default of 1 disables iterative research.
+pub fn max_research_iterations() -> u32 {
+    settings().max_research_iterations.unwrap_or(1).max(1)
+}
+
+/// Whether the summarizer must map each claim to its sources, from
+/// `REQUIRE_GROUNDING`.
+pub fn grounding_required() -> bool {
+    settings().require_grounding
+}
+
+/// Marks each claim grounded when it cites at least one URL and every URL it
+/// cites is among `sources`. Returns how many claims are not grounded.
+pub fn check_grounding(claims: &mut [GroundedClaim], sources: &[String]) -> usize {
+    for claim in claims.iter_mut() {
+        claim.grounded = !claim.urls.is_empty() && claim.urls.iter().all(|url| sources.contains(url));
+    }
+    claims.iter().filter(|claim| !claim.grounded).count()
+}
+
+/// The minimum topic relevance a question's findings need to reach the
+/// summary, when `RELEVANCE_GATE` is set. Tuned with `RELEVANCE_MIN_SCORE`.
+pub fn relevance_threshold() -> Option<f64> {
+    settings()
+        .relevance_gate
+        .then(|| settings().relevance_min_score.unwrap_or(DEFAULT_RELEVANCE_MIN_SCORE))
+}
+
+/// Fraction of the topic's keywords (words of four or more letters) that
+/// appear anywhere in the result's question or findings. A cheap stand-in
+/// for an LLM judgment that is good enough to catch questions which drifted
+/// to another subject. Topics with no keywords score 1.0.
+pub fn relevance_score(topic: &str, result: &ResearchResult) -> f64 {
+    let keywords = keywords(topic);
+    if keywords.is_empty() {
+        return 1.0;
+    }
+    let mut text = keywords(&result.question);
+    for finding in &result.findings {
+        text.extend(keywords(&finding.title));
+        text.extend(keywords(&finding.content));
+    }
+    keywords.iter().filter(|keyword| text.contains(*keyword)).count() as f64 / keywords.len() as f64
+}
+
+fn keywords(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 4)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Summarizes all findings with one LLM call.
+pub struct SinglePassStrategy;
+
+#[async_trait]
+impl SummarizationStrategy for SinglePassStrategy {
+    fn name(&self) -> &'static str {
+        "single_pass"
+    }
+
+    async fn summarize(&self, context: &ResearchContext) -> Result<Summary> {
+        summarize_text(context, &context.findings_text_limited(max_findings_per_question())).await
+    }
+}
+
+/// Summarizes each question's findings independently, then combines the
+/// partial summaries in a final pass.
+pub struct MapReduceStrategy;
+
+#[async_trait]
+impl SummarizationStrategy for MapReduceStrategy {
+    fn name(&self) -> &'static str {
+        "map_reduce"
+    }
+
+    async fn summarize(&self, context: &ResearchContext) -> Result<Summary> {
+        let agent = get_llm(&context.config.for_task("summarizer"))?;
+        let max_findings = max_findings_per_question();
+        let partials = join_all(context.research_results.iter().map(|result| {
+            let prompt = format!(
+                r#"Summarize what these findings say about the question "{}" in one paragraph:
+
+{}
+
+Do not include URLs or citations."#,
+                result.question,
+                result
+                    .top_findings(max_findings)
+                    .iter()
+                    .map(|f| format!("- {}{}: {}", f.title, f.confidence_note(), f.content))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            // The partial summaries carry no URLs, so list the question's
+            // sources next to each one for the final pass to cite.
+            let sources = if grounding_required() {
+                let urls: Vec<&str> = result.top_findings(max_findings).iter().map(|f| f.url.as_str()).collect();
+                format!("\nSources: {}", urls.join(", "))
+            } else {
+                String::new()
+            };
+            let agent = &agent;
+            async move {
+                prompt_llm(agent, &prompt)
+                    .await
+                    .map(|partial| format!("Question: {}\nSummary: {}{}", result.question, partial, sources))
+            }
+        }))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+        summarize_text(context, &partials.join("\n\n")).await
+    }
+}
+
+async fn summarize_text(context: &ResearchContext, findings_text: &str) -> Result<Summary> {
+    let (follow_up_requirement, follow_up_field) = if context.research_iterations + 1 < max_research_iterations() {
+        (
+            "\n- Also list up to 3 follow-up research questions about important gaps in the findings, or none if the findings are sufficient",
+            r#", "follow_up_questions": ["..."]"#,
+        )
+    } else {
+        ("", "")
+    };
+    let (grounding_requirement, grounding_field) = if grounding_required() {
+        (
+            "\n- Also list every factual claim in the summary with the research question it answers and the URLs of the findings that support it, using only URLs that appear in the findings",
+            r#", "grounding": [{"claim": "...", "question": "...", "urls": ["..."]}]"#,
+        )
+    } else {
+        ("", "")
+    };
+    let scored = context
+        .research_results
+        .iter()
+        .flat_map(|result| &result.findings)
+        .any(|finding| finding.confidence.is_some());
+    let confidence_requirement = if scored {
+        "\n- Findings marked with a confidence rate how well they answer their question; rely most on high-confidence findings"
+    } else {
+        ""
+    };
+    let prompt = format!(
+        r#"You are a research assistant. Summarize the key findings from this research about "{}":
+
+{}
+
+Requirements:
+- Create a concise summary (3-5 paragraphs) of the most important findings
+- Focus on facts and insights that directly relate to the topic
+- Organize information logically
+- Use clear, professional language
+- Do not include URLs or citations in the summary
+- Also extract 3-7 short key points, each a single sentence
+- Write the summary and key points in {}{}{}{}
+- Format: Return only a JSON object of the form {{"summary": "...", "key_points": ["...", "..."]{}{}}}"#,
+        context.topic,
+        findings_text,
+        context.detected_language,
+        confidence_requirement,
+        follow_up_requirement,
+        grounding_requirement,
+        follow_up_field,
+        grounding_field
+    );
+
+    let config = context.config.for_task("summarizer");
+    structured_or_prompted(
+        structured_output_enabled(),
+        "summary",
+        || extract_structured::<Summary>(&config, &prompt),
+        || async {
+            let agent = get_llm(&config)?;
+            let response = prompt_llm_streaming(&agent, &prompt).await?;
+
+            let summary = parse_json::<Summary>(&response.text).unwrap_or_else(|| {
+                warn!("Failed to parse structured summary, falling back to prose only");
+                Summary {
+                    summary: response.text,
+                    ..Default::default()
+                }
+            });
+            Ok(Summary {
+                ttft_ms: response.ttft_ms,
+                ..summary
+            })
+        },
+    )
+    .await
+}
+
+/// Asks the model to rate `summary` against the findings on coverage and
+/// faithfulness. Returns a score in `[0, 10]`, or `None` if the call fails or
+/// the reply contains no number.
+pub async fn self_score(context: &ResearchContext, summary: &str) -> Option<f32> {
+    let prompt = format!(
+        r#"Rate the following summary of research about "{}" on a scale from 0 to 10, considering both coverage (does it capture the important findings?) and faithfulness (is every statement supported by the findings?).
+
+Findings:
+{}
+
+Summary:
+{}
+
+Reply with only the number."#,
+        context.topic,
+        context.findings_text(),
+        summary
+    );
+
+    let agent = get_llm(&context.config.for_task("summarizer")).ok()?;
+    match prompt_llm(&agent, &prompt).await {
+        Ok(response) => {
+            let score = parse_score(&response);
+            if score.is_none() {
+                warn!("Could not parse summary self-score from {:?}", response);
+            }
+            score
+        }
+        Err(e) => {
+            warn!("Summary self-score failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Takes the first number in the reply, accepting forms like `8`, `7.5/10`
+/// or `Score: 9`, and rejects anything outside `[0, 10]`.
+fn parse_score(response: &str) -> Option<f32> {
+    response
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .find(|token| token.chars().any(|c| c.is_ascii_digit()))
+        .and_then(|token| token.trim_matches('.').parse::<f32>().ok())
+        .filter(|score| (0.0..=10.0).contains(score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structured_summary_is_parsed_with_key_points() {
+        let response = r#"```json
+{"summary": "Tokio is a runtime.", "key_points": ["It is async", "It is fast"]}
+```"#;
+        let summary = parse_json::<Summary>(response).unwrap();
+        assert_eq!(summary.summary, "Tokio is a runtime.");
+        assert_eq!(summary.key_points, ["It is async", "It is fast"]);
+        assert!(summary.follow_up_questions.is_empty());
+        assert!(summary.grounding.is_empty());
+
+        assert!(parse_json::<Summary>("Tokio is a runtime.").is_none());
+    }
+
+    #[test]
+    fn strategies_are_picked_by_name() {
+        assert_eq!(" map_reduce ".parse::<SummaryStrategy>(), Ok(SummaryStrategy::MapReduce));
+        assert_eq!("single_pass".parse::<SummaryStrategy>(), Ok(SummaryStrategy::SinglePass));
+        assert!("refine".parse::<SummaryStrategy>().is_err());
+        assert_eq!(configured_strategy().name(), "single_pass");
+    }
+
+    #[test]
+    fn self_scores_are_parsed_from_loose_replies() {
+        assert_eq!(parse_score("8"), Some(8.0));
+        assert_eq!(parse_score("7.5/10"), Some(7.5));
+        assert_eq!(parse_score("Score: 9."), Some(9.0));
+        assert_eq!(parse_score("42"), None);
+        assert_eq!(parse_score("Pretty good overall."), None);
+    }
+
+    #[test]
+    fn grounded_summaries_map_claims_to_their_sources() {
+        let response = r#"{"summary": "Tokio dominates async Rust.", "key_points": ["Tokio is widely used"],
+            "grounding": [
+                {"claim": "Tokio is the most used runtime", "question": "What is Tokio?", "urls": ["https://tokio.rs"]},
+                {"claim": "smol is faster", "question": "Who maintains smol?", "urls": ["https://made-up.example"]},
+                {"claim": "async-std is deprecated", "question": "Is async-std maintained?", "urls": []}
+            ]}"#;
+        let mut grounding = parse_json::<Summary>(response).unwrap().grounding;
+        assert_eq!(grounding[0].question, "What is Tokio?");
+        assert_eq!(grounding[1].urls, ["https://made-up.example"]);
+
+        let sources = vec!["https://tokio.rs".to_string(), "https://github.com/smol-rs/smol".to_string()];
+        assert_eq!(check_grounding(&mut grounding, &sources), 2);
+        let grounded: Vec<bool> = grounding.iter().map(|claim| claim.grounded).collect();
+        assert_eq!(grounded, [true, false, false]);
+    }
+}
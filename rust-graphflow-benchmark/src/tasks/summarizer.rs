@@ -1,11 +1,35 @@
+use crate::config::settings;
 use crate::models::ResearchContext;
-use crate::tools::llm::get_llm;
+use crate::sampling;
+use crate::context_keys::{get_research_context, set_research_context};
+use crate::tasks::reporter::format_research_results;
+use crate::tasks::{record_task_time, record_ttft};
+use crate::tasks::summarization::{
+    check_grounding, grounding_required, max_research_iterations, relevance_score, relevance_threshold,
+    configured_strategy, self_score, SummarizationStrategy,
+};
+use crate::tools::pools;
 use async_trait::async_trait;
 use graph_flow::{Context, GraphError, NextAction, Task, TaskResult};
-use rig::completion::Prompt;
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
-pub struct SummarizerTask;
+pub struct SummarizerTask {
+    strategy: Box<dyn SummarizationStrategy>,
+    max_iterations: u32,
+}
+
+impl SummarizerTask {
+    pub fn new(strategy: Box<dyn SummarizationStrategy>, max_iterations: u32) -> Self {
+        Self {
+            strategy,
+            max_iterations,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(configured_strategy(), max_research_iterations())
+    }
+}
 
 #[async_trait]
 impl Task for SummarizerTask {
@@ -16,61 +40,241 @@ impl Task for SummarizerTask {
     #[instrument(skip(self, context))]
     async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
         let start_time = std::time::Instant::now();
-        info!("Starting summarization task");
+        let _permit = pools::acquire("summarizer").await;
+        info!("Starting summarization task with {} strategy", self.strategy.name());
 
-        let mut research_context: ResearchContext = context
-            .get("research_context")
+        let mut research_context = get_research_context(&context)
             .await
             .ok_or_else(|| GraphError::ContextError("Research context not found".to_string()))?;
 
-        let findings_text = research_context
-            .research_results
-            .iter()
-            .map(|result| {
-                format!(
-                    "Question: {}\nFindings:\n{}",
-                    result.question,
-                    result
-                        .findings
-                        .iter()
-                        .map(|f| format!("- {} ({}): {}", f.title, f.url, f.content))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n\n");
+        // Drop off-topic questions before both the summary and the reporter's
+        // research data are built from the results.
+        if let Some(threshold) = relevance_threshold() {
+            drop_irrelevant(&mut research_context, threshold);
+        }
 
-        let prompt = format!(
-            r#"You are a research assistant. Summarize the key findings from this research about "{}":
+        // The reporter's research data does not depend on the summary, so format
+        // it on a blocking thread while the summary LLM call is in flight.
+        let summary = if settings().pipeline_report_prep {
+            let results = research_context.research_results.clone();
+            let prep = sampling::spawn_blocking(move || format_research_results(&results));
+            let (summary, prepared) = futures::join!(self.strategy.summarize(&research_context), prep);
+            research_context.prepared_research_data = prepared.ok();
+            summary
+        } else {
+            self.strategy.summarize(&research_context).await
+        }
+        .map_err(GraphError::Other)?;
 
-{}
-
-Requirements:
-- Create a concise summary (3-5 paragraphs) of the most important findings
-- Focus on facts and insights that directly relate to the topic
-- Organize information logically
-- Use clear, professional language
-- Do not include URLs or citations in the summary"#,
-            research_context.topic, findings_text
+        record_ttft(&context, "summarizer", summary.ttft_ms).await;
+        info!(
+            task = "summarizer",
+            summary_chars = summary.summary.len(),
+            key_points = summary.key_points.len(),
+            "Generated summary"
         );
+        if settings().self_score {
+            research_context.summary_score = self_score(&research_context, &summary.summary).await;
+            info!(task = "summarizer", summary_score = ?research_context.summary_score, "Scored summary");
+        }
+        if grounding_required() {
+            let mut grounding = summary.grounding;
+            let ungrounded = check_grounding(&mut grounding, &research_context.sources());
+            if grounding.is_empty() {
+                warn!(task = "summarizer", "REQUIRE_GROUNDING is set but the summary mapped no claims to sources");
+            }
+            info!(task = "summarizer", claims = grounding.len(), ungrounded, "Checked summary grounding");
+            research_context.grounding = grounding;
+        }
+        research_context.summary = summary.summary;
+        research_context.key_points = summary.key_points;
+        let iterate = next_pass(&mut research_context, summary.follow_up_questions, self.max_iterations);
+        set_research_context(&context, research_context).await;
 
-        let agent = get_llm().map_err(GraphError::Other)?;
-        let summary = agent.prompt(&prompt).await.map_err(|e| GraphError::Other(anyhow::anyhow!("Prompt error: {}", e)))?;
-
-        info!("Generated summary with {} characters", summary.len());
-        research_context.summary = summary;
-        context.set("research_context", research_context).await;
+        record_task_time(&context, "summarizer", start_time).await;
 
-        let elapsed = start_time.elapsed().as_millis() as u64;
-        let mut task_times: std::collections::HashMap<String, u64> = 
-            context.get("task_times").await.unwrap_or_default();
-        task_times.insert("summarizer".to_string(), elapsed);
-        context.set("task_times", task_times).await;
+        if iterate {
+            return Ok(TaskResult::new(
+                Some("Researching follow-up questions".to_string()),
+                NextAction::GoTo("researcher".to_string()),
+            ));
+        }
 
         Ok(TaskResult::new(
             Some("Summary generated successfully".to_string()),
-            NextAction::ContinueAndExecute,
+            NextAction::Continue,
         ))
     }
-}
\ No newline at end of file
+}
+
+/// Moves questions whose findings score below `threshold` against the topic
+/// out of the results and into `irrelevant_questions`. Caller-provided
+/// documents are always kept.
+fn drop_irrelevant(research_context: &mut ResearchContext, threshold: f64) {
+    let topic = research_context.topic.clone();
+    let (relevant, irrelevant): (Vec<_>, Vec<_>) = std::mem::take(&mut research_context.research_results)
+        .into_iter()
+        .partition(|result| {
+            result.findings.iter().any(|f| f.url.starts_with("doc://")) || relevance_score(&topic, result) >= threshold
+        });
+    for result in &irrelevant {
+        info!(task = "summarizer", question = %result.question, "Dropping off-topic question");
+    }
+    research_context.research_results = relevant;
+    research_context
+        .irrelevant_questions
+        .extend(irrelevant.into_iter().map(|result| result.question));
+}
+
+/// Counts the finished pass and queues the summarizer's new follow-up
+/// questions for the researcher. Returns whether another pass should run.
+fn next_pass(research_context: &mut ResearchContext, follow_up_questions: Vec<String>, max_iterations: u32) -> bool {
+    research_context.research_iterations += 1;
+
+    let next_questions: Vec<String> = follow_up_questions
+        .into_iter()
+        .map(|q| q.trim().to_string())
+        .filter(|q| !q.is_empty() && !research_context.questions.iter().any(|known| known.eq_ignore_ascii_case(q)))
+        .collect();
+    let iterate = research_context.research_iterations < max_iterations && !next_questions.is_empty();
+    if iterate {
+        info!(
+            task = "summarizer",
+            iteration = research_context.research_iterations,
+            follow_up_questions = next_questions.len(),
+            "Starting another research pass"
+        );
+        research_context.next_questions = next_questions;
+    }
+    iterate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::InMemorySessionStore;
+    use crate::tasks::summarization::Summary;
+    use graph_flow::{ExecutionStatus, FlowRunner, GraphBuilder, Session, SessionStorage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Replies with one queued list of follow-up questions per pass.
+    struct FollowUps(Mutex<Vec<Vec<String>>>);
+
+    #[async_trait]
+    impl SummarizationStrategy for FollowUps {
+        fn name(&self) -> &'static str {
+            "follow_ups"
+        }
+
+        async fn summarize(&self, _context: &ResearchContext) -> anyhow::Result<Summary> {
+            Ok(Summary {
+                summary: "Tokio leads.".to_string(),
+                follow_up_questions: self.0.lock().unwrap().remove(0),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Counts its runs and, like the real researcher, moves the summarizer's
+    /// follow-up questions into `questions`.
+    struct CountingResearcher(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Task for CountingResearcher {
+        fn id(&self) -> &str {
+            "researcher"
+        }
+
+        async fn run(&self, context: Context) -> Result<TaskResult, GraphError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let mut research_context = get_research_context(&context).await.unwrap();
+            let next_questions = std::mem::take(&mut research_context.next_questions);
+            research_context.questions.extend(next_questions);
+            set_research_context(&context, research_context).await;
+            Ok(TaskResult::new(None, NextAction::Continue))
+        }
+    }
+
+    #[tokio::test]
+    async fn one_follow_up_then_none_makes_two_passes() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let replies = vec![vec!["Who maintains Tokio?".to_string()], vec![]];
+        let graph = GraphBuilder::new("iterating")
+            .add_task(Arc::new(CountingResearcher(runs.clone())))
+            .add_task(Arc::new(SummarizerTask::new(Box::new(FollowUps(Mutex::new(replies))), 3)))
+            .add_edge("researcher", "summarizer")
+            .build();
+        let store = Arc::new(InMemorySessionStore::new());
+        let runner = FlowRunner::new(Arc::new(graph), store.clone() as Arc<dyn SessionStorage>);
+
+        let session = Session::new_from_task("iterating".to_string(), "researcher");
+        let research_context = ResearchContext {
+            questions: vec!["What is Tokio?".to_string()],
+            ..Default::default()
+        };
+        set_research_context(&session.context, research_context).await;
+        store.save(session).await.unwrap();
+        loop {
+            match runner.run("iterating").await.unwrap().status {
+                ExecutionStatus::Completed => break,
+                ExecutionStatus::Error(e) => panic!("workflow failed: {}", e),
+                _ => continue,
+            }
+        }
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        let session = store.get("iterating").await.unwrap().unwrap();
+        let research_context = get_research_context(&session.context).await.unwrap();
+        assert_eq!(research_context.research_iterations, 2);
+        assert_eq!(research_context.questions, ["What is Tokio?", "Who maintains Tokio?"]);
+    }
+
+    #[test]
+    fn known_questions_and_the_iteration_cap_stop_the_loop() {
+        let mut research_context = ResearchContext {
+            questions: vec!["What is Tokio?".to_string()],
+            ..Default::default()
+        };
+        assert!(!next_pass(&mut research_context, vec![" what is tokio? ".to_string()], 3));
+        assert!(research_context.next_questions.is_empty());
+
+        let mut capped = ResearchContext::default();
+        assert!(!next_pass(&mut capped, vec!["Who maintains Tokio?".to_string()], 1));
+        assert_eq!(capped.research_iterations, 1);
+    }
+
+    fn result(question: &str, url: &str, content: &str) -> crate::models::ResearchResult {
+        crate::models::ResearchResult {
+            question: question.to_string(),
+            findings: vec![crate::models::Finding {
+                title: String::new(),
+                url: url.to_string(),
+                content: content.to_string(),
+                score: 0.5,
+                query: None,
+                confidence: None,
+            }],
+            unanswerable: false,
+        }
+    }
+
+    #[test]
+    fn off_topic_questions_are_left_out_of_the_summary() {
+        let mut research_context = ResearchContext {
+            topic: "Tokio runtime performance".to_string(),
+            research_results: vec![
+                result("How fast is Tokio?", "https://tokio.rs", "Runtime performance benchmarks"),
+                result("What is the best sourdough recipe?", "https://bread.example", "Flour, water and salt"),
+                result("Notes", "doc://0", "Unrelated caller notes"),
+            ],
+            ..Default::default()
+        };
+        drop_irrelevant(&mut research_context, 0.5);
+
+        let kept: Vec<_> = research_context.research_results.iter().map(|r| r.question.as_str()).collect();
+        assert_eq!(kept, ["How fast is Tokio?", "Notes"]);
+        assert_eq!(research_context.irrelevant_questions, ["What is the best sourdough recipe?"]);
+    }
+}
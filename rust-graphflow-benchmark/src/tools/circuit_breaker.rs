@@ -0,0 +1,217 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Calls flow normally.
+    Closed,
+    /// Calls fail fast until the cooldown elapses.
+    Open,
+    /// The cooldown has elapsed and a single probe call is allowed through.
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+/// A closed/open/half-open circuit breaker. After `threshold` consecutive
+/// failures it opens and rejects calls for `cooldown`, then lets one probe
+/// through; the probe's outcome either closes the circuit or re-opens it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    /// Returns a permit if a call may proceed, moving an expired open circuit
+    /// to half-open. The call reports its outcome through the permit.
+    pub fn try_acquire(&self) -> Option<BreakerPermit<'_>> {
+        let mut inner = self.inner.lock().unwrap();
+        let probe = match inner.state {
+            BreakerState::Closed => false,
+            BreakerState::Open => {
+                let expired = inner
+                    .opened_at
+                    .is_none_or(|opened_at| opened_at.elapsed() >= self.cooldown);
+                if !expired {
+                    return None;
+                }
+                inner.state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::HalfOpen if inner.probe_in_flight => return None,
+            BreakerState::HalfOpen => true,
+        };
+        inner.probe_in_flight |= probe;
+        Some(BreakerPermit {
+            breaker: self,
+            probe,
+            reported: false,
+        })
+    }
+
+    fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.probe_in_flight = false;
+    }
+
+    fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        inner.probe_in_flight = false;
+        if inner.state == BreakerState::HalfOpen || inner.consecutive_failures >= self.threshold {
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Whether new calls are currently being rejected: the circuit is open and
+    /// cooling down, or half-open with its probe still running.
+    pub fn is_open(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            BreakerState::Closed => false,
+            BreakerState::Open => inner
+                .opened_at
+                .is_some_and(|opened_at| opened_at.elapsed() < self.cooldown),
+            BreakerState::HalfOpen => inner.probe_in_flight,
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let inner = self.inner.lock().unwrap();
+        BreakerStatus {
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+        }
+    }
+}
+
+/// One call admitted by `CircuitBreaker::try_acquire`. A permit dropped
+/// without reporting, because the request was cancelled or timed out, counts
+/// as a failed probe if it was the half-open probe; otherwise the breaker
+/// would wait forever for an outcome. Dropped ordinary calls are not counted.
+#[must_use]
+pub struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    reported: bool,
+}
+
+impl BreakerPermit<'_> {
+    pub fn success(mut self) {
+        self.reported = true;
+        self.breaker.record_success();
+    }
+
+    pub fn failure(mut self) {
+        self.reported = true;
+        self.breaker.record_failure();
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.reported {
+            self.breaker.record_failure();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(20);
+
+    fn tripped() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+        breaker.try_acquire().unwrap().failure();
+        breaker.try_acquire().unwrap().failure();
+        breaker
+    }
+
+    #[test]
+    fn opens_after_threshold_and_closes_on_successful_probe() {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 1);
+
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.status().state, BreakerState::Open);
+        assert!(breaker.is_open());
+        assert!(breaker.try_acquire().is_none());
+
+        std::thread::sleep(COOLDOWN);
+        let probe = breaker.try_acquire().unwrap();
+        assert_eq!(breaker.status().state, BreakerState::HalfOpen);
+        assert!(breaker.is_open());
+        assert!(breaker.try_acquire().is_none());
+
+        probe.success();
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn failed_probe_reopens() {
+        let breaker = tripped();
+        std::thread::sleep(COOLDOWN);
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.status().state, BreakerState::Open);
+        assert!(breaker.is_open());
+    }
+
+    #[test]
+    fn dropped_probe_reopens_instead_of_sticking() {
+        let breaker = tripped();
+        std::thread::sleep(COOLDOWN);
+        drop(breaker.try_acquire().unwrap());
+        assert_eq!(breaker.status().state, BreakerState::Open);
+
+        std::thread::sleep(COOLDOWN);
+        breaker.try_acquire().expect("a new probe after the cooldown").success();
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+    }
+
+    #[test]
+    fn dropped_closed_call_is_not_a_failure() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        drop(breaker.try_acquire().unwrap());
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::models::ResearchResult;
+use crate::tools::llm::embed_texts;
+use anyhow::Result;
+use std::collections::HashSet;
+use tracing::info;
+
+/// Removes findings that repeat an earlier one across all questions: first
+/// exact URL matches, then findings whose content embeddings have a cosine
+/// similarity of at least `threshold` with a finding already kept. The first
+/// occurrence wins. Returns how many findings were removed.
+pub async fn semantic_dedup(results: &mut [ResearchResult], threshold: f64) -> Result<usize> {
+    let mut removed = dedup_urls(results);
+
+    let contents: Vec<String> = results
+        .iter()
+        .flat_map(|result| result.findings.iter().map(|finding| finding.content.clone()))
+        .collect();
+    if contents.len() < 2 {
+        return Ok(removed);
+    }
+
+    let embeddings = embed_texts(contents).await?;
+    removed += dedup_embeddings(results, &embeddings, threshold);
+
+    info!(removed, threshold, "Deduplicated findings");
+    Ok(removed)
+}
+
+fn dedup_urls(results: &mut [ResearchResult]) -> usize {
+    let mut seen_urls = HashSet::new();
+    let mut removed = 0;
+    for result in results.iter_mut() {
+        let before = result.findings.len();
+        result.findings.retain(|finding| seen_urls.insert(finding.url.clone()));
+        removed += before - result.findings.len();
+    }
+    removed
+}
+
+/// Drops findings whose embedding, taken in finding order across `results`,
+/// is at least `threshold` similar to one already kept.
+fn dedup_embeddings(results: &mut [ResearchResult], embeddings: &[Vec<f64>], threshold: f64) -> usize {
+    let mut removed = 0;
+    let mut kept: Vec<&Vec<f64>> = Vec::new();
+    let mut embeddings = embeddings.iter();
+    for result in results.iter_mut() {
+        let before = result.findings.len();
+        result.findings.retain(|_| {
+            let Some(embedding) = embeddings.next() else {
+                return true;
+            };
+            if kept.iter().any(|other| cosine_similarity(embedding, other) >= threshold) {
+                false
+            } else {
+                kept.push(embedding);
+                true
+            }
+        });
+        removed += before - result.findings.len();
+    }
+    removed
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::Finding;
+
+    fn finding(url: &str, content: &str) -> Finding {
+        Finding {
+            title: url.to_string(),
+            url: url.to_string(),
+            content: content.to_string(),
+            score: 0.5,
+            query: None,
+            confidence: None,
+        }
+    }
+
+    fn result(question: &str, findings: Vec<Finding>) -> ResearchResult {
+        ResearchResult {
+            question: question.to_string(),
+            findings,
+            unanswerable: false,
+        }
+    }
+
+    #[test]
+    fn near_identical_findings_at_different_urls_collapse() {
+        let original = finding("https://a.example/tokio", "Tokio is an async runtime for Rust.");
+        let repeat = finding("https://a.example/tokio", "Same page, found twice.");
+        let mirror = finding("https://b.example/mirror", "Tokio is an asynchronous runtime for Rust.");
+        let other = finding("https://c.example/users", "Discord and AWS run Tokio in production.");
+        let mut results = vec![
+            result("What is Tokio?", vec![original, repeat]),
+            result("Who uses Tokio?", vec![mirror, other]),
+        ];
+        assert_eq!(dedup_urls(&mut results), 1);
+
+        // The mirror's embedding points almost the same way as the original's.
+        let embeddings = vec![vec![1.0, 0.0, 0.1], vec![0.98, 0.0, 0.12], vec![0.0, 1.0, 0.0]];
+        assert_eq!(dedup_embeddings(&mut results, &embeddings, 0.95), 1);
+
+        let urls: Vec<&str> = results.iter().flat_map(|r| r.findings.iter().map(|f| f.url.as_str())).collect();
+        assert_eq!(urls, ["https://a.example/tokio", "https://c.example/users"]);
+    }
+
+    #[test]
+    fn cosine_similarity_handles_zero_vectors() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-9);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+}
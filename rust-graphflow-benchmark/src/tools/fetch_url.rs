@@ -0,0 +1,92 @@
+use crate::tools::http::http_client;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Longest page text handed back to the model, in characters.
+const MAX_CONTENT_CHARS: usize = 8_000;
+
+#[derive(Debug)]
+pub struct FetchUrlError(String);
+
+impl std::fmt::Display for FetchUrlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Fetch error: {}", self.0)
+    }
+}
+
+impl std::error::Error for FetchUrlError {}
+
+/// Downloads a single page so the agent can read a source in full rather than
+/// relying on the search snippet.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FetchUrl;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FetchUrlArgs {
+    pub url: String,
+}
+
+impl Tool for FetchUrl {
+    const NAME: &'static str = "fetch_url";
+
+    type Error = FetchUrlError;
+    type Args = FetchUrlArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Fetch a web page and return its text content".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "url": {
+                        "type": "string",
+                        "description": "The http or https URL to fetch"
+                    }
+                },
+                "required": ["url"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        if !args.url.starts_with("http://") && !args.url.starts_with("https://") {
+            return Err(FetchUrlError(format!("Unsupported URL: {}", args.url)));
+        }
+
+        let body = http_client()
+            .get(&args.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| FetchUrlError(format!("Request failed: {}", e)))?
+            .text()
+            .await
+            .map_err(|e| FetchUrlError(format!("Failed to read body: {}", e)))?;
+
+        let text = strip_tags(&body);
+        Ok(text.chars().take(MAX_CONTENT_CHARS).collect())
+    }
+}
+
+/// Drops markup and collapses whitespace. Crude, but enough for the model to
+/// read the page's prose.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,104 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+use tracing::warn;
+
+/// Process-wide HTTP client for direct API calls (Tavily, moderation, page
+/// fetches and webhooks). Reusing it keeps connections and TLS sessions warm
+/// instead of paying a handshake per call.
+static HTTP_CLIENT: LazyLock<reqwest::Client> =
+    LazyLock::new(|| client_builder_from_env().build().expect("failed to build HTTP client"));
+
+pub fn http_client() -> &'static reqwest::Client {
+    &HTTP_CLIENT
+}
+
+/// Client settings for deployments behind a proxy or a private CA:
+/// `HTTP_PROXY_URL` routes every request through that proxy,
+/// `HTTP_CA_CERT_PATH` trusts an extra PEM certificate, and `HTTP_USER_AGENT`
+/// replaces reqwest's default user agent. Invalid values are logged and
+/// ignored rather than failing startup.
+pub fn client_builder_from_env() -> reqwest::ClientBuilder {
+    client_builder(|name| std::env::var(name).ok())
+}
+
+/// [`client_builder_from_env`] reading its settings through `lookup`.
+fn client_builder(lookup: impl Fn(&str) -> Option<String>) -> reqwest::ClientBuilder {
+    let mut builder = reqwest::Client::builder()
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60));
+
+    if let Some(proxy_url) = lookup("HTTP_PROXY_URL") {
+        match reqwest::Proxy::all(&proxy_url) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => warn!("Ignoring invalid HTTP_PROXY_URL {:?}: {}", proxy_url, e),
+        }
+    }
+
+    if let Some(path) = lookup("HTTP_CA_CERT_PATH") {
+        match std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|pem| reqwest::Certificate::from_pem(&pem).map_err(|e| e.to_string()))
+        {
+            Ok(certificate) => builder = builder.add_root_certificate(certificate),
+            Err(e) => warn!("Ignoring HTTP_CA_CERT_PATH {:?}: {}", path, e),
+        }
+    }
+
+    if let Some(user_agent) = lookup("HTTP_USER_AGENT") {
+        builder = builder.user_agent(user_agent);
+    }
+
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_call_gets_the_same_client() {
+        let first = http_client();
+        let second = std::thread::spawn(|| http_client() as *const reqwest::Client as usize)
+            .join()
+            .unwrap();
+        assert!(std::ptr::eq(first, http_client()));
+        assert_eq!(first as *const reqwest::Client as usize, second);
+    }
+
+    #[tokio::test]
+    async fn requests_go_through_the_configured_proxy() {
+        // A plain HTTP proxy receives the absolute target URL, so a local
+        // server standing in for it can echo back what it was asked for.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = format!("http://{}", listener.local_addr().unwrap());
+        let proxy = axum::Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() });
+        tokio::spawn(async move { axum::serve(listener, proxy).await });
+
+        let client = client_builder(|name| match name {
+            "HTTP_PROXY_URL" => Some(proxy_url.clone()),
+            "HTTP_USER_AGENT" => Some("benchmark-test".to_string()),
+            _ => None,
+        })
+        .build()
+        .unwrap();
+        let body = client
+            .get("http://search.example/query")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, "http://search.example/query");
+    }
+
+    #[test]
+    fn invalid_settings_are_ignored() {
+        let builder = client_builder(|name| match name {
+            "HTTP_PROXY_URL" => Some("not a url".to_string()),
+            "HTTP_CA_CERT_PATH" => Some("/nonexistent/ca.pem".to_string()),
+            _ => None,
+        });
+        assert!(builder.build().is_ok());
+    }
+}
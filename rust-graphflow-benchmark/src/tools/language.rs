@@ -0,0 +1,24 @@
+/// Language assumed when the topic is too short or ambiguous to detect reliably.
+const DEFAULT_LANGUAGE: &str = "English";
+
+/// Detects the language of `text`, returning its English name (e.g. "German").
+pub fn detect_language(text: &str) -> String {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().eng_name().to_string())
+        .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_topic_language_and_defaults_to_english() {
+        assert_eq!(
+            detect_language("Die Auswirkungen der künstlichen Intelligenz auf den deutschen Arbeitsmarkt in den nächsten zehn Jahren"),
+            "German"
+        );
+        assert_eq!(detect_language("AI"), "English");
+    }
+}
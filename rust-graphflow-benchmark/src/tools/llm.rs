@@ -1,20 +1,714 @@
+use crate::config::settings;
+use crate::models::WorkflowConfig;
+use crate::sampling;
+use crate::tools::circuit_breaker::CircuitBreaker;
+use crate::tools::llm_cache;
+use crate::tools::retry::{retry_if, RetryPolicy};
+use crate::tools::tavily::is_quota_error;
+use crate::tools::tier::LLM_GATE;
+use crate::tools::usage::{record_capped, record_usage, CHARS_PER_TOKEN};
 use anyhow::Result;
+use futures::{Stream, StreamExt};
+use rig::agent::AgentBuilder;
+use rig::completion::AssistantContent;
+use rig::completion::Prompt;
+use rig::embeddings::EmbeddingModel;
 use rig::prelude::*;
 use rig::providers::openai;
+use rig::streaming::StreamingPrompt;
 use rig::tool::Tool;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
-type LLMAgent = rig::agent::Agent<openai::CompletionModel>;
+/// A built agent plus the model name it was built for, which keys the
+/// response cache.
+pub struct LLMAgent {
+    agent: rig::agent::Agent<openai::CompletionModel>,
+    model: String,
+    max_tokens: Option<u64>,
+}
 
-pub fn get_llm() -> Result<LLMAgent> {
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| anyhow::anyhow!("OpenAI API key not configured"))?;
-    let client = openai::Client::new(&api_key);
-    Ok(client.agent("gpt-4o-mini").build())
+/// Breaker shared by every LLM call in the process, so an OpenAI outage
+/// fast-fails new requests instead of letting each one time out on its own.
+/// Tuned with `LLM_BREAKER_THRESHOLD` (consecutive failures, default 5) and
+/// `LLM_BREAKER_COOLDOWN_SECS` (default 30).
+pub static LLM_BREAKER: LazyLock<CircuitBreaker> = LazyLock::new(|| {
+    CircuitBreaker::new(
+        settings().llm_breaker_threshold.unwrap_or(5),
+        Duration::from_secs(settings().llm_breaker_cooldown_secs.unwrap_or(30)),
+    )
+});
+
+/// Chat models the benchmark knows by name. Anything else must be spelled
+/// `custom:<name>`, so a typo such as `gpt4o` is rejected when the request is
+/// parsed instead of failing at the OpenAI API mid-run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Model {
+    Gpt4o,
+    Gpt4oMini,
+    Gpt41,
+    Gpt41Mini,
+    Gpt41Nano,
+    O3Mini,
+    O4Mini,
+    /// Any other provider model name, e.g. a fine-tune.
+    Custom(String),
+}
+
+const CUSTOM_PREFIX: &str = "custom:";
+
+impl Model {
+    /// The name sent to the provider.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Model::Gpt4o => "gpt-4o",
+            Model::Gpt4oMini => "gpt-4o-mini",
+            Model::Gpt41 => "gpt-4.1",
+            Model::Gpt41Mini => "gpt-4.1-mini",
+            Model::Gpt41Nano => "gpt-4.1-nano",
+            Model::O3Mini => "o3-mini",
+            Model::O4Mini => "o4-mini",
+            Model::Custom(name) => name,
+        }
+    }
 }
 
-pub fn get_llm_with_tool<T: Tool + Clone + 'static>(tool: T) -> Result<LLMAgent> {
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim();
+        if let Some(custom) = name.strip_prefix(CUSTOM_PREFIX) {
+            return match custom.trim() {
+                "" => Err("custom model name is empty".to_string()),
+                custom => Ok(Model::Custom(custom.to_string())),
+            };
+        }
+        match name {
+            "gpt-4o" => Ok(Model::Gpt4o),
+            "gpt-4o-mini" => Ok(Model::Gpt4oMini),
+            "gpt-4.1" => Ok(Model::Gpt41),
+            "gpt-4.1-mini" => Ok(Model::Gpt41Mini),
+            "gpt-4.1-nano" => Ok(Model::Gpt41Nano),
+            "o3-mini" => Ok(Model::O3Mini),
+            "o4-mini" => Ok(Model::O4Mini),
+            other => Err(format!(
+                "unknown model {:?}; use a known model name or \"{}{}\"",
+                other, CUSTOM_PREFIX, other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Model::Custom(name) => write!(f, "{}{}", CUSTOM_PREFIX, name),
+            known => f.write_str(known.as_str()),
+        }
+    }
+}
+
+impl TryFrom<String> for Model {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl From<Model> for String {
+    fn from(model: Model) -> Self {
+        model.to_string()
+    }
+}
+
+/// Model for requests that do not name one: `LLM_MODEL` when set (validated
+/// at startup), otherwise `gpt-4o-mini`.
+pub static DEFAULT_MODEL: LazyLock<Model> =
+    LazyLock::new(|| settings().llm_model.clone().unwrap_or(Model::Gpt4oMini));
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// Default per-task models from `TASK_MODELS`, e.g.
+/// `researcher=gpt-4o-mini,summarizer=gpt-4o`. A request's
+/// `config.task_models` entries take precedence.
+pub fn task_models() -> &'static HashMap<String, Model> {
+    &settings().task_models
+}
+
+/// Completion token cap for `task`: its `TASK_MAX_TOKENS` entry (e.g.
+/// `reporter=4000,summarizer=1500`), otherwise a per-task default that a
+/// normal run stays well under. Caps bound a request's `max_tokens` from
+/// above rather than replacing it.
+pub fn task_max_tokens(task: &str) -> u64 {
+    settings().task_max_tokens.get(task).copied().unwrap_or(match task {
+        "categorizer" => 500,
+        "question_extractor" => 1_000,
+        "contradictions" | "fact_checker" => 2_000,
+        "summarizer" => 3_000,
+        "reporter" => 8_000,
+        _ => 4_000,
+    })
+}
+
+/// Parses comma-separated `task=value` entries.
+pub fn parse_task_map<T>(value: &str) -> Result<HashMap<String, T>, String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (task, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("{:?} is not a task=value entry", entry))?;
+            let value = value.trim().parse().map_err(|e: T::Err| format!("{:?}: {}", entry, e))?;
+            Ok((task.trim().to_string(), value))
+        })
+        .collect()
+}
+
+static OPENAI_CLIENT: OnceLock<openai::Client> = OnceLock::new();
+
+/// Returns the process-wide OpenAI client, created on first use so that every
+/// agent shares one connection pool.
+fn openai_client() -> Result<&'static openai::Client> {
+    if let Some(client) = OPENAI_CLIENT.get() {
+        return Ok(client);
+    }
     let api_key = std::env::var("OPENAI_API_KEY")
         .map_err(|_| anyhow::anyhow!("OpenAI API key not configured"))?;
-    let client = openai::Client::new(&api_key);
-    Ok(client.agent("gpt-4o-mini").tool(tool).build())
-}
\ No newline at end of file
+    Ok(OPENAI_CLIENT.get_or_init(|| openai::Client::new(&api_key)))
+}
+
+pub fn get_llm(config: &WorkflowConfig) -> Result<LLMAgent> {
+    LlmBuilder::new(config).map(LlmBuilder::build)
+}
+
+/// Builds an agent with any number of tools, remembering which were
+/// registered so callers can log or check the final tool set.
+pub struct LlmBuilder {
+    builder: AgentBuilder<openai::CompletionModel>,
+    model: String,
+    max_tokens: Option<u64>,
+    tool_names: Vec<&'static str>,
+}
+
+impl LlmBuilder {
+    pub fn new(config: &WorkflowConfig) -> Result<Self> {
+        Ok(Self::with_client(openai_client()?, config))
+    }
+
+    pub fn with_client(client: &openai::Client, config: &WorkflowConfig) -> Self {
+        let model = model_name(config).to_string();
+        let sampling = SamplingParams::from_config(config);
+        let mut builder = client.agent(&model);
+        if let Some(temperature) = sampling.temperature {
+            builder = builder.temperature(temperature);
+        }
+        if let Some(max_tokens) = sampling.max_tokens {
+            builder = builder.max_tokens(max_tokens);
+        }
+        if let Some(params) = sampling.additional_params {
+            builder = builder.additional_params(params);
+        }
+        Self {
+            builder,
+            model,
+            max_tokens: config.max_tokens,
+            tool_names: Vec::new(),
+        }
+    }
+
+    pub fn tool<T: Tool + 'static>(mut self, tool: T) -> Self {
+        self.tool_names.push(T::NAME);
+        self.builder = self.builder.tool(tool);
+        self
+    }
+
+    pub fn tool_names(&self) -> &[&'static str] {
+        &self.tool_names
+    }
+
+    pub fn build(self) -> LLMAgent {
+        LLMAgent {
+            agent: self.builder.build(),
+            model: self.model,
+            max_tokens: self.max_tokens,
+        }
+    }
+}
+
+/// The sampling settings handed to rig's agent builder. rig has no `top_p`
+/// setting of its own, so it goes in the provider's additional parameters.
+#[derive(Debug, PartialEq)]
+struct SamplingParams {
+    temperature: Option<f64>,
+    max_tokens: Option<u64>,
+    additional_params: Option<serde_json::Value>,
+}
+
+impl SamplingParams {
+    fn from_config(config: &WorkflowConfig) -> Self {
+        Self {
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            additional_params: config.top_p.map(|top_p| serde_json::json!({ "top_p": top_p })),
+        }
+    }
+}
+
+pub(crate) fn model_name(config: &WorkflowConfig) -> &str {
+    config.model.as_ref().unwrap_or(&DEFAULT_MODEL).as_str()
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmResponse {
+    pub text: String,
+    /// Milliseconds until the first streamed token arrived.
+    pub ttft_ms: Option<u64>,
+}
+
+/// Prompts the agent through the shared circuit breaker, retrying transient
+/// failures with backoff before counting the call as failed.
+pub async fn prompt_llm(agent: &LLMAgent, prompt: &str) -> Result<String> {
+    if let Some(response) = llm_cache::lookup(&agent.model, prompt).await {
+        return Ok(response);
+    }
+    let response = guarded("LLM prompt", || async {
+        agent
+            .agent
+            .prompt(prompt)
+            .await
+            .map_err(|e| anyhow::anyhow!("Prompt error: {}", e))
+    })
+    .await?;
+    let response = enforce_token_cap(&agent.model, agent.max_tokens, response);
+    record_usage(prompt, &response);
+    llm_cache::store(&agent.model, prompt, &response).await;
+    Ok(response)
+}
+
+/// Response-side backstop for `max_tokens`. rig's prompt API does not expose
+/// the finish reason, so a completion whose estimated length reaches the cap
+/// is treated as cut off: it is truncated to the cap, if the provider let it
+/// run over, and counted as capped for the response's `token_capped_tasks`.
+fn enforce_token_cap(model: &str, max_tokens: Option<u64>, mut text: String) -> String {
+    if let Some(max_chars) = token_cap_chars(model, max_tokens, &text) {
+        if let Some((end, _)) = text.char_indices().nth(max_chars) {
+            text.truncate(end);
+        }
+    }
+    text
+}
+
+/// The cap in characters when `text`'s estimated length reaches
+/// `max_tokens`, after logging and counting the call as capped; `None`
+/// while it stays under. Lengths are in characters, like the usage estimate,
+/// so non-ASCII text is not capped early.
+fn token_cap_chars(model: &str, max_tokens: Option<u64>, text: &str) -> Option<usize> {
+    let max_tokens = max_tokens?;
+    let max_chars = (max_tokens * CHARS_PER_TOKEN) as usize;
+    let completion_chars = text.chars().count();
+    if completion_chars < max_chars {
+        return None;
+    }
+    tracing::warn!(model, max_tokens, completion_chars, "Completion hit the max_tokens cap");
+    record_capped();
+    Some(max_chars)
+}
+
+/// Embeds `texts` with `EMBEDDING_MODEL` (default `text-embedding-3-small`),
+/// returning one vector per input in the same order.
+pub async fn embed_texts(texts: Vec<String>) -> Result<Vec<Vec<f64>>> {
+    let model_name = settings()
+        .embedding_model
+        .clone()
+        .unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.to_string());
+    let model = openai_client()?.embedding_model(&model_name);
+
+    let embeddings = guarded("Embedding request", || {
+        let texts = texts.clone();
+        let model = &model;
+        async move {
+            model
+                .embed_texts(texts)
+                .await
+                .map_err(|e| anyhow::anyhow!("Embedding error: {}", e))
+        }
+    })
+    .await?;
+    Ok(embeddings.into_iter().map(|embedding| embedding.vec).collect())
+}
+
+/// Like `prompt_llm`, but streams the completion to measure time-to-first-token.
+/// Cache hits have no TTFT.
+pub async fn prompt_llm_streaming(agent: &LLMAgent, prompt: &str) -> Result<LlmResponse> {
+    if let Some(text) = llm_cache::lookup(&agent.model, prompt).await {
+        return Ok(LlmResponse { text, ttft_ms: None });
+    }
+    let mut response = guarded("LLM streaming prompt", || stream_prompt(agent, prompt)).await?;
+    response.text = enforce_token_cap(&agent.model, agent.max_tokens, response.text);
+    record_usage(prompt, &response.text);
+    llm_cache::store(&agent.model, prompt, &response.text).await;
+    Ok(response)
+}
+
+/// Pulls chunks straight off the rig stream into the response text.
+async fn stream_prompt(agent: &LLMAgent, prompt: &str) -> Result<LlmResponse> {
+    let start_time = Instant::now();
+    let mut stream = agent
+        .agent
+        .stream_prompt(prompt)
+        .await
+        .map_err(|e| anyhow::anyhow!("Prompt error: {}", e))?;
+
+    let mut text = String::new();
+    let mut ttft_ms = None;
+    while let Some(chunk) = stream.next().await {
+        if let AssistantContent::Text(chunk) = chunk.map_err(|e| anyhow::anyhow!("Stream error: {}", e))? {
+            if ttft_ms.is_none() && !chunk.text.is_empty() {
+                ttft_ms = Some(start_time.elapsed().as_millis() as u64);
+            }
+            text.push_str(&chunk.text);
+        }
+    }
+
+    Ok(LlmResponse { text, ttft_ms })
+}
+
+/// Text chunks a streaming client may fall behind by before the rig stream
+/// stops being polled.
+const DEFAULT_STREAM_BUFFER_CAPACITY: usize = 32;
+
+/// Channel capacity for `stream_llm`, from `STREAM_BUFFER_CAPACITY`.
+pub fn stream_buffer_capacity() -> usize {
+    settings()
+        .stream_buffer_capacity
+        .filter(|capacity| *capacity > 0)
+        .unwrap_or(DEFAULT_STREAM_BUFFER_CAPACITY)
+}
+
+/// Streams the completion for `prompt` as text chunks through a channel of
+/// `stream_buffer_capacity()` items. The call holds an `LLM_GATE` slot and a
+/// breaker permit for its whole duration but is not retried, since earlier
+/// chunks may already have reached the client. Dropping the receiver stops
+/// the stream.
+pub fn stream_llm(agent: Arc<LLMAgent>, prompt: String) -> Result<mpsc::Receiver<Result<String>>> {
+    let Some(permit) = LLM_BREAKER.try_acquire() else {
+        return Err(anyhow::anyhow!("LLM circuit breaker is open"));
+    };
+    let (sender, receiver) = mpsc::channel(stream_buffer_capacity());
+
+    sampling::spawn(async move {
+        let _slot = LLM_GATE.enter().await;
+        let stream = match agent.agent.stream_prompt(&prompt).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                permit.failure();
+                let _ = sender.send(Err(anyhow::anyhow!("Prompt error: {}", e))).await;
+                return;
+            }
+        };
+
+        let mut failed = false;
+        let chunks = stream.filter_map(|chunk| {
+            let chunk = match chunk {
+                Ok(AssistantContent::Text(chunk)) => Some(Ok(chunk.text)),
+                Ok(_) => None,
+                Err(e) => {
+                    failed = true;
+                    Some(Err(anyhow::anyhow!("Stream error: {}", e)))
+                }
+            };
+            std::future::ready(chunk)
+        });
+        forward_bounded(chunks, &sender).await;
+
+        if failed {
+            permit.failure();
+        } else {
+            permit.success();
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Sends every item of `stream` to `sender`. `send` waits while the channel
+/// is full, so a slow receiver pauses consumption of `stream` rather than
+/// letting items pile up in memory. Returns false if the receiver went away
+/// before the stream ended.
+async fn forward_bounded<S: Stream>(stream: S, sender: &mpsc::Sender<S::Item>) -> bool {
+    futures::pin_mut!(stream);
+    while let Some(item) = stream.next().await {
+        if sender.send(item).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Runs `operation` behind the circuit breaker and retry policy. Every
+/// attempt waits at `LLM_GATE` first, so retries count against the
+/// `PROVIDER_TIER` limits too.
+async fn guarded<T, F, Fut>(label: &str, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let Some(permit) = LLM_BREAKER.try_acquire() else {
+        return Err(anyhow::anyhow!("LLM circuit breaker is open"));
+    };
+
+    // A search quota error surfacing through a tool call will not go away on
+    // retry, and says nothing about the LLM provider's health.
+    let policy = RetryPolicy::from_env();
+    let gated = || {
+        let attempt = operation();
+        async move {
+            let _slot = LLM_GATE.enter().await;
+            attempt.await
+        }
+    };
+    match retry_if(&policy, label, gated, |e| !is_quota_error(&e.to_string())).await {
+        Ok(response) => {
+            permit.success();
+            Ok(response)
+        }
+        Err(e) if is_quota_error(&e.to_string()) => {
+            permit.success();
+            Err(e)
+        }
+        Err(e) => {
+            permit.failure();
+            Err(e)
+        }
+    }
+}
+
+/// Whether `STRUCTURED_OUTPUT` asks for schema-enforced output through
+/// function calling instead of JSON described in the prompt.
+pub fn structured_output_enabled() -> bool {
+    settings().structured_output
+}
+
+/// Uses `structured` when `enabled`, falling back to `prompted` when it is
+/// disabled or fails. `what` names the output in the fallback warning.
+pub async fn structured_or_prompted<T, S, SF, P, PF>(enabled: bool, what: &str, structured: S, prompted: P) -> Result<T>
+where
+    S: FnOnce() -> SF,
+    SF: Future<Output = Result<T>>,
+    P: FnOnce() -> PF,
+    PF: Future<Output = Result<T>>,
+{
+    if enabled {
+        match structured().await {
+            Ok(output) => return Ok(output),
+            Err(e) => tracing::warn!("Structured {} failed, falling back to prompt parsing: {}", what, e),
+        }
+    }
+    prompted().await
+}
+
+/// Gets a `T` from the model through a function call whose parameters are
+/// `T`'s JSON schema, so the provider enforces the shape instead of the
+/// prompt. Callers fall back to prompt parsing when this fails.
+pub async fn extract_structured<T>(config: &WorkflowConfig, prompt: &str) -> Result<T>
+where
+    T: JsonSchema + DeserializeOwned + Serialize + Send + Sync + 'static,
+{
+    let model = model_name(config);
+    let mut extractor = openai_client()?.extractor::<T>(model);
+    if let Some(max_tokens) = config.max_tokens {
+        extractor = extractor.max_tokens(max_tokens);
+    }
+    let extractor = extractor.build();
+    let output = guarded("Structured extraction", || async {
+        extractor
+            .extract(prompt)
+            .await
+            .map_err(|e| anyhow::anyhow!("Extraction error: {}", e))
+    })
+    .await?;
+    // A function call cut off at the cap fails to parse above; one that still
+    // parsed is kept whole, but counted as capped all the same.
+    let json = serde_json::to_string(&output).unwrap_or_default();
+    token_cap_chars(model, config.max_tokens, &json);
+    record_usage(prompt, &json);
+    Ok(output)
+}
+
+/// Parses a JSON payload out of an LLM response, tolerating a surrounding
+/// markdown code fence.
+pub fn parse_json<T: DeserializeOwned>(response: &str) -> Option<T> {
+    let json = response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    serde_json::from_str(json).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::usage::{capped_calls, track_usage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn slow_consumer_pauses_the_producer() {
+        const CAPACITY: usize = 4;
+        let produced = Arc::new(AtomicUsize::new(0));
+        let counter = produced.clone();
+        let items = futures::stream::iter(0..100).inspect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let (sender, mut receiver) = mpsc::channel(CAPACITY);
+        let pump = tokio::spawn(async move { forward_bounded(items, &sender).await });
+
+        for consumed in 1..=10 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            assert_eq!(receiver.recv().await, Some(consumed - 1));
+            // The channel holds at most CAPACITY items and one more may be
+            // waiting in `send`; nothing beyond that has been pulled.
+            assert!(produced.load(Ordering::SeqCst) <= consumed + CAPACITY + 1);
+        }
+
+        drop(receiver);
+        assert!(!pump.await.unwrap());
+        assert!(produced.load(Ordering::SeqCst) < 100);
+    }
+
+    #[tokio::test]
+    async fn token_cap_counts_characters_not_bytes() {
+        let (caps, _) = track_usage(async {
+            // Seven two-byte characters stay under a two-token (eight-character) cap.
+            assert_eq!(token_cap_chars("gpt-4o-mini", Some(2), &"é".repeat(7)), None);
+            assert_eq!(token_cap_chars("gpt-4o-mini", Some(2), &"é".repeat(8)), Some(8));
+            assert_eq!(token_cap_chars("gpt-4o-mini", None, &"é".repeat(100)), None);
+            capped_calls()
+        })
+        .await;
+        assert_eq!(caps, 1);
+    }
+
+    #[tokio::test]
+    async fn fast_consumer_receives_everything() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let pump = tokio::spawn(async move { forward_bounded(futures::stream::iter(0..10), &sender).await });
+        let mut received = Vec::new();
+        while let Some(item) = receiver.recv().await {
+            received.push(item);
+        }
+        assert!(pump.await.unwrap());
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sampling_parameters_reach_the_agent_builder() {
+        let config = WorkflowConfig {
+            temperature: Some(0.2),
+            top_p: Some(0.9),
+            max_tokens: Some(512),
+            ..Default::default()
+        };
+        assert_eq!(
+            SamplingParams::from_config(&config),
+            SamplingParams {
+                temperature: Some(0.2),
+                max_tokens: Some(512),
+                additional_params: Some(serde_json::json!({ "top_p": 0.9 })),
+            }
+        );
+        assert_eq!(
+            SamplingParams::from_config(&WorkflowConfig::default()),
+            SamplingParams { temperature: None, max_tokens: None, additional_params: None }
+        );
+
+        let invalid = [
+            WorkflowConfig { temperature: Some(2.5), ..Default::default() },
+            WorkflowConfig { top_p: Some(0.0), ..Default::default() },
+            WorkflowConfig { max_tokens: Some(0), ..Default::default() },
+        ];
+        assert!(config.validate().is_ok());
+        assert!(invalid.iter().all(|config| config.validate().is_err()));
+    }
+
+    #[tokio::test]
+    async fn structured_output_is_used_when_enabled_and_falls_back_otherwise() {
+        let calls = std::sync::Mutex::new(Vec::new());
+        let run = |enabled: bool, structured_ok: bool| {
+            let calls = &calls;
+            structured_or_prompted(
+                enabled,
+                "test output",
+                move || async move {
+                    calls.lock().unwrap().push("structured");
+                    if structured_ok {
+                        Ok("from schema")
+                    } else {
+                        Err(anyhow::anyhow!("no function call in the reply"))
+                    }
+                },
+                move || async move {
+                    calls.lock().unwrap().push("prompted");
+                    Ok("from prompt")
+                },
+            )
+        };
+
+        assert_eq!(run(true, true).await.unwrap(), "from schema");
+        assert_eq!(std::mem::take(&mut *calls.lock().unwrap()), ["structured"]);
+
+        assert_eq!(run(true, false).await.unwrap(), "from prompt");
+        assert_eq!(std::mem::take(&mut *calls.lock().unwrap()), ["structured", "prompted"]);
+
+        assert_eq!(run(false, true).await.unwrap(), "from prompt");
+        assert_eq!(std::mem::take(&mut *calls.lock().unwrap()), ["prompted"]);
+    }
+
+    #[test]
+    fn unknown_model_names_fail_unless_marked_custom() {
+        assert_eq!(" gpt-4o ".parse::<Model>(), Ok(Model::Gpt4o));
+        assert_eq!("o4-mini".parse::<Model>(), Ok(Model::O4Mini));
+        let typo = "gpt4o".parse::<Model>().unwrap_err();
+        assert!(typo.contains("custom:gpt4o"), "{typo}");
+        assert_eq!("custom:ft:gpt-4o:acme".parse::<Model>(), Ok(Model::Custom("ft:gpt-4o:acme".to_string())));
+        assert!("custom: ".parse::<Model>().is_err());
+
+        // Request bodies are checked at the boundary, and names round-trip.
+        assert!(serde_json::from_str::<WorkflowConfig>(r#"{"model": "gpt-4-0"}"#).is_err());
+        let config: WorkflowConfig = serde_json::from_str(r#"{"model": "custom:my-model"}"#).unwrap();
+        assert_eq!(config.model.as_ref().map(Model::as_str), Some("my-model"));
+        assert_eq!(serde_json::to_value(&config).unwrap()["model"], "custom:my-model");
+    }
+
+    #[tokio::test]
+    async fn runaway_completions_are_truncated_and_flagged() {
+        let ((normal, capped), _) = track_usage(async {
+            let normal = enforce_token_cap("gpt-4o-mini", Some(4), "short".to_string());
+            let normal_caps = capped_calls();
+            let runaway = enforce_token_cap("gpt-4o-mini", Some(4), "x".repeat(1000));
+            ((normal, normal_caps), (runaway, capped_calls()))
+        })
+        .await;
+        assert_eq!(normal, ("short".to_string(), 0));
+        // Four tokens at four characters each.
+        assert_eq!(capped.0.len(), 16);
+        assert_eq!(capped.1, 1);
+    }
+}
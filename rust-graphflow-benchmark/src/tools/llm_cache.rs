@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+fn cache_dir() -> Option<PathBuf> {
+    std::env::var("LLM_CACHE_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// FNV-1a over the model and prompt. `DefaultHasher` is not guaranteed to be
+/// stable across Rust releases, which would silently invalidate the cache.
+fn cache_key(model: &str, prompt: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in model.bytes().chain([0]).chain(prompt.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn entry_path(dir: &Path, model: &str, prompt: &str) -> PathBuf {
+    dir.join(format!("{}.txt", cache_key(model, prompt)))
+}
+
+/// Returns the cached response for this model and prompt when `LLM_CACHE_DIR`
+/// is set. Meant for development only: re-runs become free and deterministic
+/// while iterating on parsing or formatting, but entries never expire and are
+/// shared by every session.
+pub async fn lookup(model: &str, prompt: &str) -> Option<String> {
+    lookup_in(&cache_dir()?, model, prompt).await
+}
+
+/// Saves a response for `lookup` when `LLM_CACHE_DIR` is set.
+pub async fn store(model: &str, prompt: &str, response: &str) {
+    if let Some(dir) = cache_dir() {
+        store_in(&dir, model, prompt, response).await;
+    }
+}
+
+async fn lookup_in(dir: &Path, model: &str, prompt: &str) -> Option<String> {
+    let path = entry_path(dir, model, prompt);
+    let response = tokio::fs::read_to_string(&path).await.ok()?;
+    debug!("LLM cache hit: {}", path.display());
+    Some(response)
+}
+
+/// Writes to a uniquely named temporary file and renames it into place, so a
+/// concurrent `lookup` or a crash mid-write never leaves a truncated entry
+/// that later runs would replay.
+async fn store_in(dir: &Path, model: &str, prompt: &str, response: &str) {
+    if let Err(e) = tokio::fs::create_dir_all(dir).await {
+        warn!("Could not create LLM cache directory {}: {}", dir.display(), e);
+        return;
+    }
+    let path = entry_path(dir, model, prompt);
+    let temp = dir.join(format!(".{}.tmp", Uuid::new_v4()));
+    if let Err(e) = tokio::fs::write(&temp, response).await {
+        warn!("Could not write LLM cache entry {}: {}", temp.display(), e);
+        let _ = tokio::fs::remove_file(&temp).await;
+        return;
+    }
+    if let Err(e) = tokio::fs::rename(&temp, &path).await {
+        warn!("Could not move LLM cache entry into {}: {}", path.display(), e);
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn second_prompt_hits_the_cache() {
+        let dir = std::env::temp_dir().join(format!("llm-cache-{}", Uuid::new_v4()));
+        assert_eq!(lookup_in(&dir, "gpt-4o-mini", "Summarize").await, None);
+
+        store_in(&dir, "gpt-4o-mini", "Summarize", "A summary").await;
+        assert_eq!(lookup_in(&dir, "gpt-4o-mini", "Summarize").await.as_deref(), Some("A summary"));
+        assert_eq!(lookup_in(&dir, "gpt-4o", "Summarize").await, None);
+        assert_eq!(lookup_in(&dir, "gpt-4o-mini", "Summarize again").await, None);
+
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().into_string().unwrap());
+        }
+        assert_eq!(names, [format!("{}.txt", cache_key("gpt-4o-mini", "Summarize"))]);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -1,2 +1,16 @@
+pub mod circuit_breaker;
+pub mod dedup;
+pub mod fetch_url;
+pub mod http;
+pub mod language;
 pub mod llm;
-pub mod tavily;
\ No newline at end of file
+pub mod llm_cache;
+pub mod moderation;
+pub mod pools;
+pub mod rate_limit;
+pub mod retry;
+pub mod tavily;
+pub mod tier;
+pub mod unicode;
+pub mod usage;
+pub mod webhook;
\ No newline at end of file
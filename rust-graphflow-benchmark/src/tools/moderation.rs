@@ -0,0 +1,137 @@
+use crate::config::settings;
+use crate::tools::http::http_client;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    pub reason: Option<String>,
+    /// Which check produced the verdict: `blocklist` or `openai`.
+    pub source: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationEntry {
+    flagged: bool,
+    #[serde(default)]
+    categories: std::collections::HashMap<String, bool>,
+}
+
+/// Screens a topic before any workflow work is done. Terms in the
+/// comma-separated `MODERATION_BLOCKLIST` are checked locally first; anything
+/// that passes is sent to OpenAI's moderation endpoint. If that call fails the
+/// topic is allowed through so an outage of the moderation API does not take
+/// the research endpoint down with it.
+pub async fn moderate(topic: &str) -> ModerationResult {
+    if let Some(term) = blocklisted_term(topic, &settings().moderation_blocklist) {
+        return ModerationResult {
+            flagged: true,
+            reason: Some(format!("topic contains blocked term '{}'", term)),
+            source: "blocklist",
+        };
+    }
+
+    match openai_moderation(topic).await {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Moderation request failed, allowing topic: {}", e);
+            ModerationResult {
+                flagged: false,
+                reason: None,
+                source: "openai",
+            }
+        }
+    }
+}
+
+/// Parses the comma-separated `MODERATION_BLOCKLIST` into lowercase terms,
+/// dropping blank entries.
+pub fn parse_blocklist(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|term| term.trim().to_lowercase())
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+fn blocklisted_term(topic: &str, blocklist: &[String]) -> Option<String> {
+    let topic = topic.to_lowercase();
+    blocklist.iter().find(|term| topic.contains(term.as_str())).cloned()
+}
+
+async fn openai_moderation(topic: &str) -> Result<ModerationResult, String> {
+    let api_key = std::env::var("OPENAI_API_KEY").map_err(|_| "OPENAI_API_KEY not set".to_string())?;
+
+    let response: ModerationResponse = http_client()
+        .post("https://api.openai.com/v1/moderations")
+        .bearer_auth(api_key)
+        .json(&json!({ "input": topic }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(verdict(&response))
+}
+
+/// Flags the topic if any result is flagged, naming every category hit.
+fn verdict(response: &ModerationResponse) -> ModerationResult {
+    let flagged = response.results.iter().any(|r| r.flagged);
+    let mut categories: Vec<&str> = response
+        .results
+        .iter()
+        .flat_map(|r| r.categories.iter())
+        .filter(|(_, hit)| **hit)
+        .map(|(category, _)| category.as_str())
+        .collect();
+    categories.sort_unstable();
+    categories.dedup();
+
+    ModerationResult {
+        flagged,
+        reason: flagged.then(|| format!("flagged for {}", categories.join(", "))),
+        source: "openai",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocklisted_terms_match_case_insensitively() {
+        let blocklist = parse_blocklist("malware, Exploit Kit");
+        assert_eq!(blocklisted_term("Rust async runtimes", &blocklist), None);
+        assert_eq!(
+            blocklisted_term("Building an Exploit Kit", &blocklist),
+            Some("exploit kit".to_string())
+        );
+        assert!(parse_blocklist(" , ").is_empty());
+    }
+
+    #[test]
+    fn flagged_responses_name_their_categories() {
+        let response: ModerationResponse = serde_json::from_value(json!({
+            "results": [{"flagged": true, "categories": {"violence": true, "hate": false, "harassment": true}}]
+        }))
+        .unwrap();
+        let result = verdict(&response);
+        assert!(result.flagged);
+        assert_eq!(result.reason.as_deref(), Some("flagged for harassment, violence"));
+
+        let benign: ModerationResponse = serde_json::from_value(json!({"results": [{"flagged": false}]})).unwrap();
+        let result = verdict(&benign);
+        assert!(!result.flagged);
+        assert_eq!(result.reason, None);
+    }
+}
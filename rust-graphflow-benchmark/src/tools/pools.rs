@@ -0,0 +1,79 @@
+use crate::config::settings;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Per-task concurrency limits from `TASK_POOL_SIZES`, e.g.
+/// `researcher=8,reporter=4`. Each task type draws from its own semaphore, so
+/// a burst of research fan-out cannot take the slots reserved for other
+/// requests' reports. Tasks without an entry are unlimited.
+static POOLS: LazyLock<TaskPools> = LazyLock::new(|| TaskPools::new(&settings().task_pool_sizes));
+
+/// One semaphore per task type that has a configured size.
+struct TaskPools(HashMap<String, Arc<Semaphore>>);
+
+impl TaskPools {
+    fn new(sizes: &HashMap<String, usize>) -> Self {
+        Self(
+            sizes
+                .iter()
+                .map(|(task, size)| (task.clone(), Arc::new(Semaphore::new(*size))))
+                .collect(),
+        )
+    }
+
+    async fn acquire(&self, task: &str) -> Option<OwnedSemaphorePermit> {
+        let pool = self.0.get(task)?.clone();
+        pool.acquire_owned().await.ok()
+    }
+}
+
+/// Parses `TASK_POOL_SIZES`. Every entry must be `task=size` with a positive
+/// size.
+pub fn parse_pool_sizes(value: &str) -> Result<HashMap<String, usize>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .and_then(|(task, size)| Some((task.trim().to_string(), size.trim().parse::<usize>().ok()?)))
+                .filter(|(_, size)| *size > 0)
+                .ok_or_else(|| format!("{:?} is not a task=size entry with a positive size", entry))
+        })
+        .collect()
+}
+
+/// Waits for a slot in `task`'s pool. The slot is held until the returned
+/// permit is dropped; `None` means the task has no pool.
+pub async fn acquire(task: &str) -> Option<OwnedSemaphorePermit> {
+    POOLS.acquire(task).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn saturated_researcher_pool_does_not_block_the_reporter() {
+        let pools = TaskPools::new(&parse_pool_sizes("researcher=2, reporter=1").unwrap());
+        let _held = [pools.acquire("researcher").await, pools.acquire("researcher").await];
+
+        let researcher = tokio::time::timeout(Duration::from_millis(50), pools.acquire("researcher")).await;
+        assert!(researcher.is_err(), "the researcher pool should be full");
+        let reporter = tokio::time::timeout(Duration::from_millis(50), pools.acquire("reporter")).await;
+        assert!(reporter.unwrap().is_some());
+        // Tasks without a pool are not limited at all.
+        assert!(pools.acquire("summarizer").await.is_none());
+    }
+
+    #[test]
+    fn pool_sizes_must_be_positive_task_entries() {
+        assert!(parse_pool_sizes("researcher=0").is_err());
+        assert!(parse_pool_sizes("researcher").is_err());
+        assert!(parse_pool_sizes("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Paces callers to at most `qps` acquisitions per second across the process.
+/// Each caller reserves the next free slot and sleeps until it arrives, so
+/// bursts are spread out evenly instead of being rejected.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(qps: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / qps),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_callers_are_spread_out() {
+        let limiter = RateLimiter::new(20.0);
+        let started = Instant::now();
+        futures::future::join_all((0..5).map(|_| limiter.acquire())).await;
+        // The first slot is immediate; the other four wait 50ms each.
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+}
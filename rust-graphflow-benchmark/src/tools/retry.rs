@@ -0,0 +1,203 @@
+use crate::config::settings;
+use rand::Rng;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Exponential backoff settings shared by LLM and Tavily calls.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Reads `RETRY_MAX_ATTEMPTS` (default 3), `RETRY_BASE_DELAY_MS` (default 500),
+    /// `RETRY_MAX_DELAY_MS` (default 10000) and `RETRY_JITTER` (default on).
+    pub fn from_env() -> Self {
+        let settings = settings();
+        Self {
+            max_attempts: settings.retry_max_attempts.unwrap_or(3).max(1),
+            base_delay: Duration::from_millis(settings.retry_base_delay_ms.unwrap_or(500)),
+            max_delay: Duration::from_millis(settings.retry_max_delay_ms.unwrap_or(10_000)),
+            jitter: settings.retry_jitter,
+        }
+    }
+
+    /// Delay before retrying after the given zero-based failed attempt.
+    ///
+    /// Without jitter this is `min(max_delay, base_delay * 2^attempt)`. With
+    /// jitter enabled it is "full jitter": a uniformly random duration in
+    /// `[0, min(max_delay, base_delay * 2^attempt)]`, which spreads out retries
+    /// from concurrent research questions that failed at the same moment.
+    pub fn backoff<R: Rng>(&self, attempt: u32, rng: &mut R) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        if self.jitter {
+            exponential.mul_f64(rng.gen_range(0.0..=1.0))
+        } else {
+            exponential
+        }
+    }
+}
+
+tokio::task_local! {
+    static RETRY_BUDGET: Arc<AtomicU32>;
+}
+
+/// Runs `future` with at most `budget` retries shared between every `retry`
+/// call it makes, including concurrent fan-out. Returns the output together
+/// with the unused budget. `None` leaves retries limited only per call.
+pub async fn with_retry_budget<F: Future>(budget: Option<u32>, future: F) -> (F::Output, Option<u32>) {
+    match budget {
+        Some(budget) => {
+            let remaining = Arc::new(AtomicU32::new(budget));
+            let output = RETRY_BUDGET.scope(remaining.clone(), future).await;
+            (output, Some(remaining.load(Ordering::Relaxed)))
+        }
+        None => (future.await, None),
+    }
+}
+
+/// Takes one retry from the current request's budget, if it has one.
+fn take_retry() -> bool {
+    RETRY_BUDGET
+        .try_with(|remaining| {
+            remaining
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+        })
+        .unwrap_or(true)
+}
+
+/// Runs `operation` until it succeeds or `policy.max_attempts` is exhausted,
+/// sleeping for `policy.backoff` between attempts. Once the request's retry
+/// budget is spent, failures are returned immediately.
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, label: &str, operation: F) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_if(policy, label, operation, |_| true).await
+}
+
+/// Like `retry`, but returns errors for which `retryable` is false straight away.
+pub async fn retry_if<T, E, F, Fut, R>(
+    policy: &RetryPolicy,
+    label: &str,
+    mut operation: F,
+    retryable: R,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    R: Fn(&E) -> bool,
+{
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) if !retryable(&e) => return Err(e),
+            Err(e) if attempt + 1 < policy.max_attempts && take_retry() => {
+                let delay = policy.backoff(attempt, &mut rand::thread_rng());
+                warn!("{} failed (attempt {}): {}, retrying in {:?}", label, attempt + 1, e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                if attempt + 1 < policy.max_attempts {
+                    warn!("{} failed and the request retry budget is exhausted: {}", label, e);
+                }
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let delays: Vec<u128> = (0..5).map(|attempt| policy(false).backoff(attempt, &mut rng).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000]);
+    }
+
+    #[test]
+    fn seeded_jitter_is_reproducible_and_bounded() {
+        let jittered = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..5).map(|attempt| policy(true).backoff(attempt, &mut rng)).collect::<Vec<_>>()
+        };
+        let delays = jittered(42);
+        assert_eq!(delays, jittered(42));
+        assert_ne!(delays, jittered(43));
+        for (attempt, delay) in delays.iter().enumerate() {
+            assert!(*delay <= policy(false).backoff(attempt as u32, &mut StdRng::seed_from_u64(0)));
+        }
+    }
+
+    #[tokio::test]
+    async fn budget_caps_retries_across_fan_out() {
+        let policy = RetryPolicy {
+            base_delay: Duration::ZERO,
+            ..policy(false)
+        };
+        let attempts = AtomicU32::new(0);
+        let always_failing = |label: &'static str| {
+            retry(&policy, label, || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err::<(), _>("unavailable")
+            })
+        };
+        let fan_out = async {
+            futures::join!(always_failing("first"), always_failing("second"), always_failing("third"))
+        };
+
+        let ((first, second, third), remaining) = with_retry_budget(Some(4), fan_out).await;
+        assert!(first.is_err() && second.is_err() && third.is_err());
+        // Three first attempts plus the four budgeted retries, rather than the
+        // fifteen attempts the per-call limit alone would allow.
+        assert_eq!(attempts.load(Ordering::Relaxed), 7);
+        assert_eq!(remaining, Some(0));
+    }
+
+    #[tokio::test]
+    async fn without_a_budget_only_the_per_call_limit_applies() {
+        let policy = RetryPolicy {
+            base_delay: Duration::ZERO,
+            ..policy(false)
+        };
+        let attempts = AtomicU32::new(0);
+        let failing = retry(&policy, "only", || async {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err::<(), _>("unavailable")
+        });
+        let (result, remaining) = with_retry_budget(None, failing).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 5);
+        assert_eq!(remaining, None);
+    }
+}
@@ -1,23 +1,233 @@
-use crate::models::{TavilySearchRequest, TavilySearchResponse};
+use crate::config::settings;
+use crate::models::{TavilyResult, TavilySearchRequest, TavilySearchResponse};
+use crate::tools::http::http_client;
+use crate::tools::retry::{retry_if, RetryPolicy};
+use crate::tools::tier::SEARCH_GATE;
+use crate::tools::unicode::sanitize_text;
 use rig::tool::Tool;
 use rig::completion::ToolDefinition;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Prefix of the quota error message, which travels through rig and `anyhow`
+/// as text and is matched on further up.
+pub const QUOTA_EXHAUSTED: &str = "Tavily quota exhausted";
 
 #[derive(Debug)]
-pub struct TavilyError(String);
+pub enum TavilyError {
+    /// The account is out of credits or not authorized; retrying cannot help.
+    QuotaExhausted(String),
+    Failed(String),
+}
 
 impl std::fmt::Display for TavilyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Tavily error: {}", self.0)
+        match self {
+            TavilyError::QuotaExhausted(detail) => write!(f, "{}: {}", QUOTA_EXHAUSTED, detail),
+            TavilyError::Failed(detail) => write!(f, "Tavily error: {}", detail),
+        }
+    }
+}
+
+/// Conversational filler that models put into search queries. Deliberately
+/// short: removing content words would hurt more than the filler does.
+const QUERY_STOPWORDS: &[&str] = &[
+    "a", "an", "the", "please", "can", "could", "you", "find", "search", "look", "up", "for", "me",
+    "some", "information", "info", "about", "tell", "i", "want", "would", "like", "to", "know",
+    "details", "on", "regarding",
+];
+
+/// Longest query sent to Tavily under `CLEAN_SEARCH_QUERIES`, unless
+/// `SEARCH_QUERY_MAX_CHARS` says otherwise.
+const DEFAULT_QUERY_MAX_CHARS: usize = 200;
+/// Longest title or content kept per result, unless `MAX_FINDING_CONTENT_CHARS`
+/// says otherwise.
+const DEFAULT_MAX_CONTENT_CHARS: usize = 5_000;
+
+/// Strips quotes, trailing punctuation and filler words from a model-written
+/// query and cuts it to `max_chars` at a word boundary. Falls back to the
+/// trimmed original when nothing would be left.
+pub fn clean_query(query: &str, max_chars: usize) -> String {
+    let mut cleaned = String::new();
+    for word in query.split_whitespace() {
+        let word = word.trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '?' | '!' | '.' | ',' | ':' | ';'));
+        if word.is_empty() || QUERY_STOPWORDS.contains(&word.to_lowercase().as_str()) {
+            continue;
+        }
+        let needed = if cleaned.is_empty() { word.len() } else { word.len() + 1 };
+        if cleaned.len() + needed > max_chars {
+            break;
+        }
+        if !cleaned.is_empty() {
+            cleaned.push(' ');
+        }
+        cleaned.push_str(word);
+    }
+    if cleaned.is_empty() {
+        query.trim().to_string()
+    } else {
+        cleaned
+    }
+}
+
+pub fn is_quota_error(message: &str) -> bool {
+    message.contains(QUOTA_EXHAUSTED)
+}
+
+/// Tavily reports plan and credit limits as 432/433 and bad keys as 401/403;
+/// a 429 is only treated as quota when the body says so.
+fn is_quota_response(status: reqwest::StatusCode, body: &str) -> bool {
+    let body = body.to_lowercase();
+    matches!(status.as_u16(), 401 | 402 | 403 | 432 | 433)
+        || ["quota", "credits", "plan limit", "usage limit"]
+            .iter()
+            .any(|marker| body.contains(marker))
+}
+
+impl TavilyError {
+    fn is_retryable(&self) -> bool {
+        !matches!(self, TavilyError::QuotaExhausted(_))
     }
 }
 
 impl std::error::Error for TavilyError {}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TavilySearch;
+pub struct TavilySearch {
+    search_depth: String,
+    include_domains: Vec<String>,
+    exclude_domains: Vec<String>,
+    /// Query that returned each URL, shared between clones.
+    #[serde(skip)]
+    issued_queries: Arc<Mutex<HashMap<String, String>>>,
+    /// Milliseconds spent in Tavily calls, shared between clones.
+    #[serde(skip)]
+    search_ms: Arc<AtomicU64>,
+}
+
+impl TavilySearch {
+    pub fn new(search_depth: &str) -> Self {
+        Self {
+            search_depth: search_depth.to_string(),
+            include_domains: Vec::new(),
+            exclude_domains: Vec::new(),
+            issued_queries: Arc::default(),
+            search_ms: Arc::default(),
+        }
+    }
+
+    /// A copy with its own query log and search timer, so one question's lookups do not see
+    /// another's searches.
+    pub fn tracked(&self) -> Self {
+        Self {
+            issued_queries: Arc::default(),
+            search_ms: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Total time spent waiting on Tavily by this search and its clones.
+    pub fn search_time(&self) -> Duration {
+        Duration::from_millis(self.search_ms.load(Ordering::Relaxed))
+    }
+
+    /// The query that returned `url`, if this search (or a clone) found it.
+    pub fn query_for(&self, url: &str) -> Option<String> {
+        self.issued_queries.lock().unwrap().get(url).cloned()
+    }
+
+    /// Restricts results to `include` (when non-empty) and drops results from `exclude`.
+    pub fn with_domains(mut self, include: Vec<String>, exclude: Vec<String>) -> Self {
+        self.include_domains = include;
+        self.exclude_domains = exclude;
+        self
+    }
+
+    fn record_query(&self, query: &str, results: &[TavilyResult]) {
+        let mut issued_queries = self.issued_queries.lock().unwrap();
+        for r in results {
+            issued_queries.insert(r.url.clone(), query.to_string());
+        }
+    }
+
+    fn request(&self, query: String) -> TavilySearchRequest {
+        TavilySearchRequest {
+            query,
+            max_results: 5,
+            search_depth: self.search_depth.clone(),
+            include_raw_content: true,
+            include_domains: self.include_domains.clone(),
+            exclude_domains: self.exclude_domains.clone(),
+        }
+    }
+}
+
+/// Decodes a search response body and sanitizes each result's title and
+/// content to at most `max_chars` characters. Scraped pages occasionally carry
+/// invalid UTF-8, which would fail strict decoding of the whole response, so
+/// it is replaced instead.
+fn parse_results(body: &[u8], max_chars: usize) -> Result<Vec<TavilyResult>, TavilyError> {
+    let mut search_response: TavilySearchResponse = serde_json::from_str(&String::from_utf8_lossy(body))
+        .map_err(|e| TavilyError::Failed(format!("Failed to parse response: {}", e)))?;
+    for result in &mut search_response.results {
+        result.title = sanitize_text(&result.title, max_chars);
+        result.content = sanitize_text(&result.content, max_chars);
+    }
+    Ok(search_response.results)
+}
+
+/// Keeps results scored within `[min_score, max_score]`, logging how many
+/// were dropped.
+fn within_score_range(results: Vec<TavilyResult>, min_score: f64, max_score: f64) -> Vec<TavilyResult> {
+    let total = results.len();
+    let kept: Vec<_> = results
+        .into_iter()
+        .filter(|r| r.score >= min_score && r.score <= max_score)
+        .collect();
+    if kept.len() < total {
+        tracing::info!(
+            "Filtered {} of {} Tavily results outside score range [{}, {}]",
+            total - kept.len(),
+            total,
+            min_score,
+            max_score
+        );
+    }
+    kept
+}
+
+/// Normalizes domain filters to bare lowercase hostnames such as `example.com`,
+/// dropping entries that do not look like one.
+pub fn valid_domains(domains: &[String]) -> Vec<String> {
+    domains
+        .iter()
+        .filter_map(|domain| {
+            let normalized = domain
+                .trim()
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .trim_end_matches('/')
+                .to_lowercase();
+            let valid = normalized.contains('.')
+                && !normalized.starts_with('.')
+                && !normalized.ends_with('.')
+                && normalized
+                    .split('.')
+                    .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+            if valid {
+                Some(normalized)
+            } else {
+                tracing::warn!("Ignoring invalid domain filter {:?}", domain);
+                None
+            }
+        })
+        .collect()
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TavilySearchArgs {
@@ -50,36 +260,197 @@ impl Tool for TavilySearch {
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         let api_key = env::var("TAVILY_API_KEY")
-            .map_err(|_| TavilyError("TAVILY_API_KEY not set".to_string()))?;
+            .map_err(|_| TavilyError::Failed("TAVILY_API_KEY not set".to_string()))?;
 
-        let client = reqwest::Client::new();
-        let request = TavilySearchRequest {
-            query: args.query,
-            max_results: 5,
-            search_depth: "advanced".to_string(),
-            include_raw_content: true,
+        // Off by default: over-cleaning can drop words that mattered.
+        let query = if settings().clean_search_queries {
+            let max_chars = settings().search_query_max_chars.unwrap_or(DEFAULT_QUERY_MAX_CHARS);
+            let cleaned = clean_query(&args.query, max_chars);
+            if cleaned != args.query {
+                tracing::debug!(original = %args.query, cleaned = %cleaned, "Cleaned search query");
+            }
+            cleaned
+        } else {
+            args.query.clone()
         };
 
-        let response = client
-            .post("https://api.tavily.com/search")
-            .header("api-key", api_key)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| TavilyError(format!("Request failed: {}", e)))?;
+        let started = Instant::now();
+        let client = http_client();
+        let request = self.request(query.clone());
+
+        let policy = RetryPolicy::from_env();
+        let response = retry_if(
+            &policy,
+            "Tavily search",
+            || async {
+                let _slot = SEARCH_GATE.enter().await;
+                let response = client
+                    .post("https://api.tavily.com/search")
+                    .header("api-key", &api_key)
+                    .json(&request)
+                    .send()
+                    .await
+                    .map_err(|e| TavilyError::Failed(format!("Request failed: {}", e)))?;
 
-        let search_response: TavilySearchResponse = response
-            .json()
+                let status = response.status();
+                if status.is_success() {
+                    return Ok(response);
+                }
+                let body = response.text().await.unwrap_or_default();
+                if is_quota_response(status, &body) {
+                    Err(TavilyError::QuotaExhausted(format!("{} {}", status, body.trim())))
+                } else {
+                    Err(TavilyError::Failed(format!("Request failed with {}: {}", status, body.trim())))
+                }
+            },
+            TavilyError::is_retryable,
+        )
+        .await?;
+
+        let body = response
+            .bytes()
             .await
-            .map_err(|e| TavilyError(format!("Failed to parse response: {}", e)))?;
+            .map_err(|e| TavilyError::Failed(format!("Failed to read response: {}", e)))?;
+        let max_chars = settings().max_finding_content_chars.unwrap_or(DEFAULT_MAX_CONTENT_CHARS);
+        let results = parse_results(&body, max_chars)?;
+        self.search_ms.fetch_add(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+
+        // Scores outside [TAVILY_MIN_SCORE, TAVILY_MAX_SCORE] are dropped before
+        // the model ever sees them.
+        let min_score = settings().tavily_min_score.unwrap_or(f64::NEG_INFINITY);
+        let max_score = settings().tavily_max_score.unwrap_or(f64::INFINITY);
+        let results = within_score_range(results, min_score, max_score);
+
+        self.record_query(&query, &results);
 
-        let formatted_results = search_response
-            .results
+        let formatted_results = results
             .iter()
-            .map(|r| format!("Title: {}\nURL: {}\nContent: {}\n", r.title, r.url, r.content))
+            .map(|r| format!("Title: {}\nURL: {}\nScore: {}\nContent: {}\n", r.title, r.url, r.score, r.content))
             .collect::<Vec<_>>()
             .join("\n---\n");
 
         Ok(formatted_results)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(url: &str, score: f64) -> TavilyResult {
+        TavilyResult {
+            title: url.to_string(),
+            url: url.to_string(),
+            content: String::new(),
+            score,
+        }
+    }
+
+    #[test]
+    fn results_outside_the_score_range_are_dropped() {
+        let results = vec![result("low", 0.1), result("edge", 0.4), result("high", 0.9), result("top", 1.0)];
+        let kept = within_score_range(results, 0.4, 0.95);
+        let urls: Vec<_> = kept.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(urls, ["edge", "high"]);
+    }
+
+    #[test]
+    fn unset_thresholds_keep_everything() {
+        let results = vec![result("low", 0.0), result("high", 1.0)];
+        assert_eq!(within_score_range(results, f64::NEG_INFINITY, f64::INFINITY).len(), 2);
+    }
+
+    #[test]
+    fn domain_filters_are_sent_to_tavily() {
+        let domains = |list: &[&str]| list.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        let search = TavilySearch::new("basic").with_domains(
+            valid_domains(&domains(&["https://Docs.rs/", "not a domain", "tokio.rs"])),
+            valid_domains(&domains(&["reddit.com", ".com"])),
+        );
+        let request = serde_json::to_value(search.request("tokio".to_string())).unwrap();
+        assert_eq!(request["include_domains"], json!(["docs.rs", "tokio.rs"]));
+        assert_eq!(request["exclude_domains"], json!(["reddit.com"]));
+
+        let unfiltered = serde_json::to_value(TavilySearch::new("basic").request("tokio".to_string())).unwrap();
+        assert!(unfiltered.get("include_domains").is_none());
+        assert!(unfiltered.get("exclude_domains").is_none());
+    }
+
+    #[test]
+    fn quota_responses_are_told_apart_from_transient_ones() {
+        use reqwest::StatusCode;
+        assert!(is_quota_response(StatusCode::from_u16(432).unwrap(), ""));
+        assert!(is_quota_response(StatusCode::UNAUTHORIZED, "invalid api key"));
+        assert!(is_quota_response(StatusCode::TOO_MANY_REQUESTS, "You have exceeded your plan limit"));
+        assert!(!is_quota_response(StatusCode::TOO_MANY_REQUESTS, "slow down"));
+        assert!(!is_quota_response(StatusCode::BAD_GATEWAY, "upstream error"));
+    }
+
+    #[tokio::test]
+    async fn quota_errors_are_not_retried_and_say_why() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: false,
+        };
+        let attempts = AtomicU64::new(0);
+        let result: Result<(), _> = retry_if(
+            &policy,
+            "Tavily search",
+            || async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(TavilyError::QuotaExhausted("432 {\"detail\": \"plan limit\"}".to_string()))
+            },
+            TavilyError::is_retryable,
+        )
+        .await;
+
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+        let message = result.unwrap_err().to_string();
+        assert!(is_quota_error(&message), "{}", message);
+        assert!(TavilyError::Failed("502".to_string()).is_retryable());
+    }
+
+    #[test]
+    fn findings_trace_back_to_the_query_that_found_them() {
+        let shared = TavilySearch::new("basic");
+        let question = shared.tracked();
+        // The agent gets a clone of the tracked search, as in the researcher.
+        let agent_copy = question.clone();
+        agent_copy.record_query("tokio runtime", &[result("https://tokio.rs", 0.9)]);
+        agent_copy.record_query("tokio scheduler", &[result("https://docs.rs/tokio", 0.8)]);
+
+        assert_eq!(question.query_for("https://tokio.rs").as_deref(), Some("tokio runtime"));
+        assert_eq!(question.query_for("https://docs.rs/tokio").as_deref(), Some("tokio scheduler"));
+        assert_eq!(question.query_for("https://lib.rs"), None);
+        // Other questions' searches are tracked separately.
+        assert_eq!(shared.tracked().query_for("https://tokio.rs"), None);
+        assert_eq!(shared.query_for("https://tokio.rs"), None);
+    }
+
+    #[test]
+    fn verbose_queries_are_cleaned_to_their_keywords() {
+        let verbose = r#"Could you please search for some information about "Tokio runtime benchmarks" in 2024?"#;
+        assert_eq!(clean_query(verbose, 200), "Tokio runtime benchmarks in 2024");
+        // Cut at a word boundary, never mid-word.
+        assert_eq!(clean_query(verbose, 20), "Tokio runtime");
+        // A query made only of filler is sent as it was.
+        assert_eq!(clean_query("  find me some info  ", 200), "find me some info");
+    }
+
+    #[test]
+    fn control_characters_and_invalid_utf8_are_cleaned_and_truncated() {
+        let mut body = br#"{"results":[{"title":"Tokio\u0007 docs","url":"https://tokio.rs","content":"line one\nbell\u0007 nul\u0000 esc\u001b[0m ok\tdone "#.to_vec();
+        body.extend_from_slice(b"\xff\xfe");
+        body.extend_from_slice(br#" tail","score":0.9}]}"#);
+
+        let results = parse_results(&body, 1000).unwrap();
+        assert_eq!(results[0].title, "Tokio docs");
+        assert_eq!(results[0].content, "line one\nbell nul esc[0m ok\tdone \u{fffd}\u{fffd} tail");
+
+        let truncated = parse_results(&body, 8).unwrap();
+        assert_eq!(truncated[0].content, "line one…");
+        assert!(parse_results(b"not json", 8).is_err());
+    }
+}
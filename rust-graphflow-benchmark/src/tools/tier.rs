@@ -0,0 +1,178 @@
+use crate::config::{settings, Config};
+use crate::tools::rate_limit::RateLimiter;
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// OpenAI usage tier from `PROVIDER_TIER`. Each tier maps to concurrency and
+/// QPS defaults for the LLM and search clients that sit comfortably inside
+/// that tier's rate limits; see `limits` for the numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderTier {
+    Free,
+    Tier1,
+    Tier2,
+    Tier3,
+    Tier4,
+    Tier5,
+}
+
+impl FromStr for ProviderTier {
+    type Err = String;
+
+    /// Accepts `free`, `1`-`5` and `tier1`-`tier5`, optionally written as
+    /// `tier-1` or `tier_1`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let normalized = value.trim().to_ascii_lowercase().replace(['-', '_'], "");
+        match normalized.strip_prefix("tier").unwrap_or(&normalized) {
+            "free" => Ok(ProviderTier::Free),
+            "1" => Ok(ProviderTier::Tier1),
+            "2" => Ok(ProviderTier::Tier2),
+            "3" => Ok(ProviderTier::Tier3),
+            "4" => Ok(ProviderTier::Tier4),
+            "5" => Ok(ProviderTier::Tier5),
+            _ => Err(format!("unknown provider tier {:?}, expected free or 1-5", value)),
+        }
+    }
+}
+
+/// Concurrency and pacing for the outbound clients. `None` leaves that
+/// dimension unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClientLimits {
+    pub llm_concurrency: Option<usize>,
+    pub llm_qps: Option<f64>,
+    pub search_concurrency: Option<usize>,
+    pub search_qps: Option<f64>,
+}
+
+impl ProviderTier {
+    /// The documented defaults for this tier. LLM QPS is about 90% of the
+    /// tier's requests-per-minute limit for the mini models; search limits
+    /// scale alongside so the researcher's fan-out stays balanced.
+    pub fn limits(self) -> ClientLimits {
+        let (llm_concurrency, llm_qps, search_concurrency, search_qps) = match self {
+            ProviderTier::Free => (1, 0.05, 1, 0.5),
+            ProviderTier::Tier1 => (4, 7.5, 2, 1.0),
+            ProviderTier::Tier2 => (8, 75.0, 4, 2.0),
+            ProviderTier::Tier3 => (16, 75.0, 8, 4.0),
+            ProviderTier::Tier4 => (32, 150.0, 16, 8.0),
+            ProviderTier::Tier5 => (64, 450.0, 32, 16.0),
+        };
+        ClientLimits {
+            llm_concurrency: Some(llm_concurrency),
+            llm_qps: Some(llm_qps),
+            search_concurrency: Some(search_concurrency),
+            search_qps: Some(search_qps),
+        }
+    }
+}
+
+impl ClientLimits {
+    /// The `PROVIDER_TIER` defaults with `LLM_MAX_CONCURRENCY`, `LLM_QPS`,
+    /// `SEARCH_MAX_CONCURRENCY` and `TAVILY_QPS` overriding them one by one.
+    /// Without a tier only the explicit settings apply.
+    pub fn from_config(config: &Config) -> Self {
+        let defaults = config.provider_tier.map(ProviderTier::limits).unwrap_or_default();
+        ClientLimits {
+            llm_concurrency: config.llm_max_concurrency.or(defaults.llm_concurrency),
+            llm_qps: config.llm_qps.or(defaults.llm_qps),
+            search_concurrency: config.search_max_concurrency.or(defaults.search_concurrency),
+            search_qps: config.tavily_qps.or(defaults.search_qps),
+        }
+    }
+}
+
+static LIMITS: LazyLock<ClientLimits> = LazyLock::new(|| ClientLimits::from_config(settings()));
+
+/// Process-wide gate for LLM calls, shared by every request.
+pub static LLM_GATE: LazyLock<ClientGate> =
+    LazyLock::new(|| ClientGate::new(LIMITS.llm_concurrency, LIMITS.llm_qps));
+
+/// Process-wide gate for Tavily searches, shared by every request.
+pub static SEARCH_GATE: LazyLock<ClientGate> =
+    LazyLock::new(|| ClientGate::new(LIMITS.search_concurrency, LIMITS.search_qps));
+
+/// A concurrency cap plus pacing for one client. Zero or non-positive
+/// values disable that half of the gate.
+#[derive(Debug)]
+pub struct ClientGate {
+    slots: Option<Arc<Semaphore>>,
+    pacing: Option<RateLimiter>,
+}
+
+impl ClientGate {
+    fn new(concurrency: Option<usize>, qps: Option<f64>) -> Self {
+        Self {
+            slots: concurrency.filter(|n| *n > 0).map(|n| Arc::new(Semaphore::new(n))),
+            pacing: qps.filter(|qps| *qps > 0.0).map(RateLimiter::new),
+        }
+    }
+
+    /// Waits for a free slot and then for the next paced start time. The
+    /// slot is held until the returned permit is dropped.
+    pub async fn enter(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = match &self.slots {
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        if let Some(pacing) = &self.pacing {
+            pacing.acquire().await;
+        }
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_tier_resolves_to_its_documented_limits() {
+        // The table in the README.
+        let documented = [
+            ("free", 1, 0.05, 1, 0.5),
+            ("1", 4, 7.5, 2, 1.0),
+            ("tier2", 8, 75.0, 4, 2.0),
+            ("tier-3", 16, 75.0, 8, 4.0),
+            ("Tier_4", 32, 150.0, 16, 8.0),
+            (" 5 ", 64, 450.0, 32, 16.0),
+        ];
+        for (name, llm_concurrency, llm_qps, search_concurrency, search_qps) in documented {
+            let tier: ProviderTier = name.parse().unwrap();
+            assert_eq!(
+                tier.limits(),
+                ClientLimits {
+                    llm_concurrency: Some(llm_concurrency),
+                    llm_qps: Some(llm_qps),
+                    search_concurrency: Some(search_concurrency),
+                    search_qps: Some(search_qps),
+                },
+                "{name}"
+            );
+        }
+        assert!("6".parse::<ProviderTier>().is_err());
+    }
+
+    #[test]
+    fn explicit_limits_override_the_tier_one_by_one() {
+        let config = Config {
+            provider_tier: Some(ProviderTier::Tier2),
+            llm_qps: Some(10.0),
+            search_max_concurrency: Some(1),
+            ..Config::default()
+        };
+        assert_eq!(
+            ClientLimits::from_config(&config),
+            ClientLimits {
+                llm_concurrency: Some(8),
+                llm_qps: Some(10.0),
+                search_concurrency: Some(1),
+                search_qps: Some(2.0),
+            }
+        );
+        assert_eq!(ClientLimits::from_config(&Config::default()), ClientLimits::default());
+    }
+}
@@ -0,0 +1,40 @@
+use crate::config::settings;
+use unicode_normalization::UnicodeNormalization;
+
+/// Converts `text` to Unicode NFC so that precomposed and combining-character
+/// spellings of the same string compare equal in dedup. Returns the input
+/// unchanged when `UNICODE_NORMALIZE` is off.
+pub fn normalize_text(text: &str) -> String {
+    if settings().unicode_normalize {
+        text.nfc().collect()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Makes scraped text safe to put in prompts and logs: control characters
+/// other than newlines and tabs are dropped, and the result is cut to
+/// `max_chars` characters, with an ellipsis marking the cut. Invalid UTF-8
+/// must already have been replaced when the bytes were decoded.
+pub fn sanitize_text(text: &str, max_chars: usize) -> String {
+    let mut cleaned = text.chars().filter(|c| !c.is_control() || matches!(c, '\n' | '\t'));
+    let mut sanitized: String = cleaned.by_ref().take(max_chars).collect();
+    if cleaned.next().is_some() {
+        sanitized.push('…');
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precomposed_and_combining_spellings_normalize_equal() {
+        let precomposed = "Caf\u{e9} culture in Z\u{fc}rich";
+        let combining = "Cafe\u{301} culture in Zu\u{308}rich";
+        assert_ne!(precomposed, combining);
+        assert_eq!(normalize_text(precomposed), normalize_text(combining));
+        assert_eq!(normalize_text(combining), precomposed);
+    }
+}
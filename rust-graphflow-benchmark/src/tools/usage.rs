@@ -0,0 +1,162 @@
+use crate::config::settings;
+use crate::models::{CostBreakdown, TaskCost, TokenUsage};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub const CHARS_PER_TOKEN: u64 = 4;
+
+#[derive(Debug, Default)]
+struct UsageCounter {
+    prompt_chars: AtomicU64,
+    completion_chars: AtomicU64,
+    /// Completions that reached their `max_tokens` cap.
+    capped: AtomicU64,
+    /// Prompt and response of each call, kept only under `CAPTURE_LLM_CALLS`.
+    calls: Mutex<Vec<(String, String)>>,
+}
+
+impl UsageCounter {
+    fn usage(&self) -> TokenUsage {
+        let prompt_tokens = self.prompt_chars.load(Ordering::Relaxed) / CHARS_PER_TOKEN;
+        let completion_tokens = self.completion_chars.load(Ordering::Relaxed) / CHARS_PER_TOKEN;
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+}
+
+tokio::task_local! {
+    static USAGE: Arc<UsageCounter>;
+}
+
+/// Runs `future` while attributing every LLM call it makes to a fresh counter,
+/// returning the output together with the estimated token usage.
+pub async fn track_usage<F: Future>(future: F) -> (F::Output, TokenUsage) {
+    let counter = Arc::new(UsageCounter::default());
+    let output = USAGE.scope(counter.clone(), future).await;
+    (output, counter.usage())
+}
+
+/// Usage so far within the enclosing `track_usage`, or zero outside one.
+/// Taking it before and after a task gives that task's share.
+pub fn current_usage() -> TokenUsage {
+    USAGE.try_with(|counter| counter.usage()).unwrap_or_default()
+}
+
+/// Adds one LLM call to the current request's usage. Calls made outside
+/// `track_usage`, such as during warmup, are not counted.
+pub fn record_usage(prompt: &str, completion: &str) {
+    let _ = USAGE.try_with(|counter| {
+        counter.prompt_chars.fetch_add(prompt.len() as u64, Ordering::Relaxed);
+        counter.completion_chars.fetch_add(completion.len() as u64, Ordering::Relaxed);
+        // `CAPTURE_LLM_CALLS` keeps every prompt and raw response for
+        // `?include=prompts` and `?include=raw_responses`. Off by default
+        // since it grows each session by the full text of every LLM call.
+        if settings().capture_llm_calls {
+            counter.calls.lock().unwrap().push((prompt.to_string(), completion.to_string()));
+        }
+    });
+}
+
+/// Counts a completion cut off at its `max_tokens` cap against the current
+/// request.
+pub fn record_capped() {
+    let _ = USAGE.try_with(|counter| counter.capped.fetch_add(1, Ordering::Relaxed));
+}
+
+/// Capped completions so far within the enclosing `track_usage`. Like
+/// `current_usage`, taking it around a task attributes caps to that task.
+pub fn capped_calls() -> u64 {
+    USAGE.try_with(|counter| counter.capped.load(Ordering::Relaxed)).unwrap_or_default()
+}
+
+/// Removes and returns the calls captured so far within the enclosing
+/// `track_usage`, so each task's calls can be stored as they finish.
+pub fn take_calls() -> Vec<(String, String)> {
+    USAGE
+        .try_with(|counter| std::mem::take(&mut *counter.calls.lock().unwrap()))
+        .unwrap_or_default()
+}
+
+/// USD per million prompt and completion tokens. `LLM_PROMPT_PRICE_PER_MTOK`
+/// and `LLM_COMPLETION_PRICE_PER_MTOK` override the built-in list prices,
+/// which cover only the models this benchmark is usually run with.
+fn price_per_million(model: &str) -> Option<(f64, f64)> {
+    let listed = match model {
+        "gpt-4o-mini" => Some((0.15, 0.60)),
+        "gpt-4o" => Some((2.50, 10.00)),
+        "gpt-4.1" => Some((2.00, 8.00)),
+        "gpt-4.1-mini" => Some((0.40, 1.60)),
+        "gpt-4.1-nano" => Some((0.10, 0.40)),
+        "o3-mini" | "o4-mini" => Some((1.10, 4.40)),
+        _ => None,
+    };
+    match (settings().llm_prompt_price_per_mtok, settings().llm_completion_price_per_mtok) {
+        (Some(prompt), Some(completion)) => Some((prompt, completion)),
+        (prompt, completion) => listed.map(|(p, c)| (prompt.unwrap_or(p), completion.unwrap_or(c))),
+    }
+}
+
+/// Prices each task's usage for `model`. The total is the sum of the task
+/// entries, so it can differ from `token_usage` by per-task rounding. Returns
+/// `None` when no price is known for the model.
+pub fn cost_breakdown(by_task: &HashMap<String, TokenUsage>, model: &str) -> Option<CostBreakdown> {
+    let (prompt_price, completion_price) = price_per_million(model)?;
+    let tasks: HashMap<String, TaskCost> = by_task
+        .iter()
+        .map(|(task, usage)| {
+            let cost_usd = (usage.prompt_tokens as f64 * prompt_price
+                + usage.completion_tokens as f64 * completion_price)
+                / 1_000_000.0;
+            let cost = TaskCost {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                cost_usd,
+            };
+            (task.clone(), cost)
+        })
+        .collect();
+    let total = tasks.values().fold(TaskCost::default(), |total, cost| TaskCost {
+        prompt_tokens: total.prompt_tokens + cost.prompt_tokens,
+        completion_tokens: total.completion_tokens + cost.completion_tokens,
+        cost_usd: total.cost_usd + cost.cost_usd,
+    });
+    Some(CostBreakdown { tasks, total })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[test]
+    fn cost_breakdown_has_every_task_and_sums_to_the_total() {
+        let by_task = HashMap::from([
+            ("question_extractor".to_string(), usage(1_000, 200)),
+            ("researcher".to_string(), usage(20_000, 4_000)),
+            ("reporter".to_string(), usage(30_000, 8_000)),
+        ]);
+        let breakdown = cost_breakdown(&by_task, "gpt-4o").unwrap();
+
+        assert_eq!(breakdown.tasks.len(), 3);
+        // gpt-4o: $2.50 per million prompt tokens, $10 per million completion tokens.
+        assert!((breakdown.tasks["reporter"].cost_usd - 0.155).abs() < 1e-9);
+        assert_eq!(breakdown.total.prompt_tokens, 51_000);
+        assert_eq!(breakdown.total.completion_tokens, 12_200);
+        let summed: f64 = breakdown.tasks.values().map(|cost| cost.cost_usd).sum();
+        assert!((breakdown.total.cost_usd - summed).abs() < 1e-12);
+
+        assert!(cost_breakdown(&by_task, "unpriced-model").is_none());
+    }
+}
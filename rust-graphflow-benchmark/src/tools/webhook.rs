@@ -0,0 +1,42 @@
+use crate::sampling;
+use crate::tools::http::http_client;
+use crate::tools::retry::{retry, RetryPolicy};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::warn;
+
+/// Per-attempt timeout, kept short so a slow receiver only costs a background task.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// POSTs `payload` to `url` in the background, retrying twice on failure. The
+/// caller never waits on the receiver.
+pub fn notify<T: Serialize>(url: String, payload: &T) {
+    let body = match serde_json::to_value(payload) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to serialize webhook payload: {}", e);
+            return;
+        }
+    };
+
+    sampling::spawn(async move {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(2),
+            jitter: true,
+        };
+        let sent = retry(&policy, "Webhook", || {
+            http_client()
+                .post(&url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .json(&body)
+                .send()
+        })
+        .await
+        .and_then(|response| response.error_for_status());
+        if let Err(e) = sent {
+            warn!("Webhook to {} failed: {}", url, e);
+        }
+    });
+}